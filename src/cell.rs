@@ -0,0 +1,360 @@
+use anyhow::anyhow;
+#[cfg(feature = "cli")]
+use clap::{builder::PossibleValue, ValueEnum};
+use std::fmt::{Debug, Display};
+
+/// Width of a single memory cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellWidth {
+    #[default]
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl CellWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::Bits8 => 8,
+            CellWidth::Bits16 => 16,
+            CellWidth::Bits32 => 32,
+            CellWidth::Bits64 => 64,
+        }
+    }
+
+    pub fn bytes(self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Reduces `value` modulo 2^bits.
+    pub fn truncate(self, value: u64) -> u64 {
+        match self {
+            CellWidth::Bits64 => value,
+            _ => value & ((1 << self.bits()) - 1),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ValueEnum for CellWidth {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Bits8, Self::Bits16, Self::Bits32, Self::Bits64]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<CellWidth> for &str {
+    fn from(value: CellWidth) -> Self {
+        match value {
+            CellWidth::Bits8 => "8",
+            CellWidth::Bits16 => "16",
+            CellWidth::Bits32 => "32",
+            CellWidth::Bits64 => "64",
+        }
+    }
+}
+
+impl TryFrom<&str> for CellWidth {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "8" => Ok(CellWidth::Bits8),
+            "16" => Ok(CellWidth::Bits16),
+            "32" => Ok(CellWidth::Bits32),
+            "64" => Ok(CellWidth::Bits64),
+            _ => Err(anyhow!("{} is not a supported cell width", &value)),
+        }
+    }
+}
+
+/// What `,` stores in the current cell once input is exhausted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eof {
+    /// Leave the cell as it was.
+    #[default]
+    Unchanged,
+    /// Set the cell to 0.
+    Zero,
+    /// Set the cell to -1, i.e. every bit set (255 for 8-bit unsigned cells).
+    MinusOne,
+}
+
+#[cfg(feature = "cli")]
+impl ValueEnum for Eof {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Unchanged, Self::Zero, Self::MinusOne]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Eof> for &str {
+    fn from(value: Eof) -> Self {
+        match value {
+            Eof::Unchanged => "unchanged",
+            Eof::Zero => "zero",
+            Eof::MinusOne => "minus-one",
+        }
+    }
+}
+
+impl TryFrom<&str> for Eof {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "unchanged" => Ok(Eof::Unchanged),
+            "zero" => Ok(Eof::Zero),
+            "minus-one" => Ok(Eof::MinusOne),
+            _ => Err(anyhow!("{} is not a valid EOF behaviour", &value)),
+        }
+    }
+}
+
+/// How the tape reacts when the data pointer moves past its edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tape {
+    /// The tape has a fixed number of cells and leaving it is an error.
+    #[default]
+    Fixed,
+    /// The tape grows to the right as needed. Moving left of the first cell
+    /// is still an error.
+    Growable,
+    /// The tape grows in both directions as needed.
+    Bidirectional,
+}
+
+#[cfg(feature = "cli")]
+impl ValueEnum for Tape {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Fixed, Self::Growable, Self::Bidirectional]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Tape> for &str {
+    fn from(value: Tape) -> Self {
+        match value {
+            Tape::Fixed => "fixed",
+            Tape::Growable => "growable",
+            Tape::Bidirectional => "bidirectional",
+        }
+    }
+}
+
+impl TryFrom<&str> for Tape {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "fixed" => Ok(Tape::Fixed),
+            "growable" => Ok(Tape::Growable),
+            "bidirectional" => Ok(Tape::Bidirectional),
+            _ => Err(anyhow!("{} is not a valid tape", &value)),
+        }
+    }
+}
+
+/// A value that can be stored in a memory cell.
+///
+/// Cells are stored as unsigned integers. Whether they are interpreted as
+/// signed is a runtime setting, since it only changes where the bounds lie
+/// and how values are shown, not how they are stored.
+///
+/// Arithmetic is done through `i128` so that a single implementation covers
+/// every width, and so that a count larger than the cell can hold is still
+/// reported as an overflow instead of being silently truncated.
+pub trait Cell: Copy + Default + PartialEq + Debug + Display {
+    /// Adds `delta` to the cell, wrapping around at its bounds.
+    fn wrapping_offset(self, delta: i128) -> Self;
+
+    /// Adds `delta` to the cell, returning `None` if the result does not fit.
+    fn checked_offset(self, delta: i128, signed: bool) -> Option<Self>;
+
+    /// The value of the cell, read as two's complement if `signed` is set.
+    fn value(self, signed: bool) -> i128;
+
+    fn from_byte(byte: u8) -> Self;
+
+    /// The byte written to output for this cell.
+    fn low_byte(self) -> u8;
+
+    /// Index of the first zero cell in `cells`.
+    fn find_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().position(|&cell| cell == Self::default())
+    }
+
+    /// Index of the last zero cell in `cells`.
+    fn rfind_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().rposition(|&cell| cell == Self::default())
+    }
+}
+
+macro_rules! impl_cell {
+    ($($ty:ty => $signed:ty $({ $($extra:item)* })?),*) => {$(
+        impl Cell for $ty {
+            $($($extra)*)?
+
+            fn wrapping_offset(self, delta: i128) -> Self {
+                (self as i128).wrapping_add(delta) as Self
+            }
+
+            fn checked_offset(self, delta: i128, signed: bool) -> Option<Self> {
+                let value = self.value(signed).checked_add(delta)?;
+
+                if signed {
+                    <$signed>::try_from(value).ok().map(|value| value as Self)
+                } else {
+                    Self::try_from(value).ok()
+                }
+            }
+
+            fn value(self, signed: bool) -> i128 {
+                if signed {
+                    self as $signed as i128
+                } else {
+                    self as i128
+                }
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte as Self
+            }
+
+            fn low_byte(self) -> u8 {
+                self as u8
+            }
+        }
+    )*};
+}
+
+impl_cell!(
+    u8 => i8 {
+        fn find_zero(cells: &[Self]) -> Option<usize> {
+            memchr::memchr(0, cells)
+        }
+
+        fn rfind_zero(cells: &[Self]) -> Option<usize> {
+            memchr::memrchr(0, cells)
+        }
+    },
+    u16 => i16,
+    u32 => i32,
+    u64 => i64
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(0u8, 1, false, Some(1))]
+    #[case(255u8, 1, false, None)]
+    #[case(0u8, -1, false, None)]
+    #[case(127u8, 1, false, Some(128))]
+    #[case(127u8, 1, true, None)]
+    #[case(0u8, -1, true, Some(255))]
+    #[case(128u8, -1, true, None)]
+    #[case(0u8, 300, false, None)]
+    #[case(200u8, -300, true, None)]
+    fn test_checked_offset(
+        #[case] cell: u8,
+        #[case] delta: i128,
+        #[case] signed: bool,
+        #[case] expected: Option<u8>,
+    ) {
+        assert_eq!(cell.checked_offset(delta, signed), expected);
+    }
+
+    fn wrapping(width: CellWidth, cell: u64, delta: i128) -> u64 {
+        match width {
+            CellWidth::Bits8 => (cell as u8).wrapping_offset(delta) as u64,
+            CellWidth::Bits16 => (cell as u16).wrapping_offset(delta) as u64,
+            CellWidth::Bits32 => (cell as u32).wrapping_offset(delta) as u64,
+            CellWidth::Bits64 => cell.wrapping_offset(delta),
+        }
+    }
+
+    fn checked(width: CellWidth, cell: u64, delta: i128, signed: bool) -> Option<u64> {
+        match width {
+            CellWidth::Bits8 => (cell as u8).checked_offset(delta, signed).map(u64::from),
+            CellWidth::Bits16 => (cell as u16).checked_offset(delta, signed).map(u64::from),
+            CellWidth::Bits32 => (cell as u32).checked_offset(delta, signed).map(u64::from),
+            CellWidth::Bits64 => cell.checked_offset(delta, signed),
+        }
+    }
+
+    #[rstest]
+    #[case(CellWidth::Bits8, 0xff, 1, 0)]
+    #[case(CellWidth::Bits8, 0, -1, 0xff)]
+    #[case(CellWidth::Bits8, 0x7f, 1, 0x80)]
+    #[case(CellWidth::Bits8, 0, 0x1ff, 0xff)]
+    #[case(CellWidth::Bits16, 0xffff, 1, 0)]
+    #[case(CellWidth::Bits16, 0, -1, 0xffff)]
+    #[case(CellWidth::Bits16, 0x8000, -1, 0x7fff)]
+    #[case(CellWidth::Bits32, 0xffff_ffff, 1, 0)]
+    #[case(CellWidth::Bits32, 0, -1, 0xffff_ffff)]
+    #[case(CellWidth::Bits32, 0x7fff_ffff, 1, 0x8000_0000)]
+    #[case(CellWidth::Bits64, u64::MAX, 1, 0)]
+    #[case(CellWidth::Bits64, 0, -1, u64::MAX)]
+    #[case(CellWidth::Bits64, i64::MAX as u64, 1, 1 << 63)]
+    #[case(CellWidth::Bits64, 0, 1 << 64, 0)]
+    fn test_wrapping_offset(
+        #[case] width: CellWidth,
+        #[case] cell: u64,
+        #[case] delta: i128,
+        #[case] expected: u64,
+    ) {
+        assert_eq!(wrapping(width, cell, delta), expected);
+    }
+
+    #[rstest]
+    #[case(CellWidth::Bits8, 0xfe, 1, false, Some(0xff))]
+    #[case(CellWidth::Bits8, 0xff, 1, false, None)]
+    #[case(CellWidth::Bits8, 0x7f, 1, true, None)]
+    #[case(CellWidth::Bits8, 0x80, -1, true, None)]
+    #[case(CellWidth::Bits8, 0xff, 1, true, Some(0))]
+    #[case(CellWidth::Bits16, 0xffff, 1, false, None)]
+    #[case(CellWidth::Bits16, 0, -1, false, None)]
+    #[case(CellWidth::Bits16, 0x7fff, 1, true, None)]
+    #[case(CellWidth::Bits16, 0x8000, -1, true, None)]
+    #[case(CellWidth::Bits16, 0x8001, -1, true, Some(0x8000))]
+    #[case(CellWidth::Bits32, 0xffff_ffff, 1, false, None)]
+    #[case(CellWidth::Bits32, 0, 0xffff_ffff, false, Some(0xffff_ffff))]
+    #[case(CellWidth::Bits32, 0x7fff_ffff, 1, true, None)]
+    #[case(CellWidth::Bits32, 0x8000_0000, -1, true, None)]
+    #[case(CellWidth::Bits32, 0, -1, true, Some(0xffff_ffff))]
+    #[case(CellWidth::Bits64, u64::MAX, 1, false, None)]
+    #[case(CellWidth::Bits64, 1, -1, false, Some(0))]
+    #[case(CellWidth::Bits64, i64::MAX as u64, 1, true, None)]
+    #[case(CellWidth::Bits64, 1 << 63, -1, true, None)]
+    #[case(CellWidth::Bits64, u64::MAX, 1, true, Some(0))]
+    fn test_checked_offset_widths(
+        #[case] width: CellWidth,
+        #[case] cell: u64,
+        #[case] delta: i128,
+        #[case] signed: bool,
+        #[case] expected: Option<u64>,
+    ) {
+        assert_eq!(checked(width, cell, delta, signed), expected);
+    }
+
+    #[rstest]
+    #[case(0xffffu16, false, 65535)]
+    #[case(0xffffu16, true, -1)]
+    #[case(0x8000u16, true, -32768)]
+    fn test_value(#[case] cell: u16, #[case] signed: bool, #[case] expected: i128) {
+        assert_eq!(cell.value(signed), expected);
+    }
+}
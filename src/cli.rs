@@ -1,7 +1,12 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::compiler::arch::Target;
+use nauc::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::arch::Target,
+    interpreter::Flush,
+    ir::passes::{Pass, PassManager, MAX_LEVEL},
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -17,13 +22,55 @@ pub enum Command {
         /// File to feed to interpreter.
         file: PathBuf,
 
+        #[command(flatten)]
+        optimization: Optimization,
+
+        /// Treat `#` as an instruction that dumps the cells around the pointer to stderr.
+        #[arg(long)]
+        debug_hash: bool,
+
         /// Disable the wrapping of cell values. If on, IntegerOverflow errors are possible.
         #[arg(short = 'w', long)]
         no_wrap: bool,
 
-        /// Number of cells in memory
+        /// Number of cells in memory. A tape that grows starts with this many.
         #[arg(short, long, default_value = "30000")]
         memory: usize,
+
+        /// Whether the tape grows when the data pointer moves past its edges.
+        #[arg(long, default_value = "fixed")]
+        tape: Tape,
+
+        /// Width of each cell in bits.
+        #[arg(short, long, default_value = "8")]
+        cell_bits: CellWidth,
+
+        /// When output is flushed.
+        #[arg(short, long, default_value = "line")]
+        flush: Flush,
+
+        /// Treat cell values as signed.
+        #[arg(short, long, default_value = "false")]
+        signed: bool,
+
+        /// What `,` stores in the current cell once input is exhausted.
+        #[arg(short, long, default_value = "unchanged")]
+        eof: Eof,
+
+        /// Run without optimizing, counting how often each token runs, and print
+        /// a report to stderr.
+        #[arg(long)]
+        profile: bool,
+
+        /// Write the profile in collapsed stack format, for flamegraph tools, to
+        /// this file.
+        #[arg(long, requires = "profile")]
+        flamegraph: Option<PathBuf>,
+
+        /// Run without optimizing, recording which tokens run and which way
+        /// loops branch, and write that to this file in lcov format.
+        #[arg(long)]
+        coverage: Option<PathBuf>,
     },
 
     /// Run in compiler mode.
@@ -31,24 +78,123 @@ pub enum Command {
         /// Source file.
         file: PathBuf,
 
+        #[command(flatten)]
+        optimization: Optimization,
+
+        /// Treat `#` as an instruction that dumps the cells around the pointer to stderr.
+        #[arg(long)]
+        debug_hash: bool,
+
         /// Target architecture
         #[arg(short, long = "target", default_value = "x86_64-linux")]
         target: Target,
 
-        /// Number of cells in memory.
+        /// Number of cells in memory, unless the tape grows.
         #[arg(short, long, default_value = "30000")]
         memory: usize,
 
-        /// Output file.
+        /// Whether the tape grows when the data pointer moves past its edges. A tape
+        /// that grows is a large region of reserved address space between guard pages.
+        #[arg(long, default_value = "fixed")]
+        tape: Tape,
+
+        /// Width of each cell in bits.
+        #[arg(short, long, default_value = "8")]
+        cell_bits: CellWidth,
+
+        /// Treat cell values as signed.
+        #[arg(short, long, default_value = "false")]
+        signed: bool,
+
+        /// What `,` stores in the current cell once input is exhausted.
+        #[arg(short, long, default_value = "unchanged")]
+        eof: Eof,
+
+        /// Disable the wrapping of cell values. If a cell leaves its range, the program
+        /// prints where to stderr and exits with status 4 (overflow) or 5 (underflow).
+        #[arg(short = 'w', long)]
+        no_wrap: bool,
+
+        /// Check that the data pointer stays on the tape. If it leaves, the program
+        /// prints where to stderr and exits with status 3, like the interpreter.
+        #[arg(long)]
+        bounds_check: bool,
+
+        /// Output file. The executable is written to the current directory, named after
+        /// its stem. With the c target, an output file with a `c` extension is the
+        /// generated C, written at that path and not compiled.
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Keep build artifacts.
+        /// Keep the generated assembly, or C, next to the executable.
         #[arg(short, long, default_value = "false")]
         keep_artifacts: bool,
 
-        /// Include debug symbols (implies --keep-artifacts)
+        /// Include debug symbols, assembling and linking with the platform's toolchain (implies --keep-artifacts)
         #[arg(short = 'g', long, default_value = "false")]
         debug: bool,
     },
+
+    /// Step through a program in the interpreter. Commands are read from stdin.
+    Debug {
+        /// Source file.
+        file: PathBuf,
+
+        /// File the program reads its input from. Without one, input is empty.
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+
+        /// Treat `#` as an instruction that dumps the cells around the pointer to stderr.
+        #[arg(long)]
+        debug_hash: bool,
+
+        /// Disable the wrapping of cell values. If on, IntegerOverflow errors are possible.
+        #[arg(short = 'w', long)]
+        no_wrap: bool,
+
+        /// Number of cells in memory
+        #[arg(short, long, default_value = "30000")]
+        memory: usize,
+
+        /// Width of each cell in bits.
+        #[arg(short, long, default_value = "8")]
+        cell_bits: CellWidth,
+
+        /// Treat cell values as signed.
+        #[arg(short, long, default_value = "false")]
+        signed: bool,
+
+        /// What `,` stores in the current cell once input is exhausted.
+        #[arg(short, long, default_value = "unchanged")]
+        eof: Eof,
+    },
+}
+
+/// Optimization options shared by every subcommand that runs the IR.
+#[derive(Args)]
+pub struct Optimization {
+    /// Optimization level. Each level enables more passes.
+    #[arg(short = 'O', default_value_t = MAX_LEVEL, value_parser = clap::value_parser!(u8).range(0..=MAX_LEVEL as i64))]
+    pub level: u8,
+
+    /// Enable a pass regardless of the optimization level.
+    #[arg(long = "pass", value_name = "PASS")]
+    pub enable: Vec<Pass>,
+
+    /// Disable a pass regardless of the optimization level. Wins over --pass.
+    #[arg(long = "no-pass", value_name = "PASS")]
+    pub disable: Vec<Pass>,
+}
+
+impl Optimization {
+    pub fn pass_manager(&self) -> PassManager {
+        let mut manager = PassManager::new(self.level);
+        self.enable.iter().for_each(|&pass| {
+            manager.enable(pass);
+        });
+        self.disable.iter().for_each(|&pass| {
+            manager.disable(pass);
+        });
+        manager
+    }
 }
@@ -1,10 +1,417 @@
-use crate::parser::Token;
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::{checked_steps, Options, GUARD, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
 use indoc::formatdoc;
 
-pub fn codegen(program: &[Token], memory: usize) -> String {
+/// 1 GiB, a third of the address space a process has, which leaves room for
+/// the rest of the program.
+const RESERVED: usize = 1 << 30;
+
+/// The checks described in [`super`], which branch to stubs that load the
+/// message into r1 and r2 and the status into r3.
+struct Traps {
+    options: Options,
+    stubs: String,
+    messages: String,
+    count: usize,
+}
+
+impl Traps {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            stubs: String::new(),
+            messages: String::new(),
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, error: InterpreterError) -> String {
+        let n = self.count;
+        let message = format!("Error: {error}");
+
+        self.count += 1;
+        self.messages
+            .push_str(&format!("E{n}:  .ascii \"{message}\\n\"\n"));
+        self.stubs.push_str(&formatdoc! {"
+            T{n}:
+                mov32  r1, #E{n}
+                mov32  r2, #{length}
+                mov    r3, #{status}
+                b      trap
+        ", length = message.len() + 1, status = error.status()});
+
+        format!("T{n}")
+    }
+
+    /// Checks the data pointer, whose byte offset is in r4.
+    fn pointer(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!("    {}\n", self.offset(span, "r4").trim_end()),
+            false => String::new(),
+        }
+    }
+
+    /// Checks the byte offset in `register`.
+    fn offset(&mut self, span: Span, register: &str) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "mov32  r12, #{}\n    cmp    {register}, r12\n    bhs    {}\n    ",
+                self.options.layout(RESERVED).0,
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    /// Adds `value` to the cell at byte offset `index`, checked with `bcs` or
+    /// `bcc`, or `bvs` for signed cells.
+    fn checked_add(&mut self, span: Span, index: &str, value: isize) -> String {
+        let Options { cell, signed, .. } = self.options;
+        let magnitude = value.unsigned_abs() as u64;
+        let (op, trap) = match value >= 0 {
+            true => ("add", self.add(InterpreterError::IntegerOverflow(span))),
+            false => ("sub", self.add(InterpreterError::IntegerUnderflow(span))),
+        };
+        let branch = match (signed, op) {
+            (true, _) => "bvs",
+            (false, "add") => "bcs",
+            (false, _) => "bcc",
+        };
+
+        let steps = match (cell, checked_steps(magnitude, &self.options)) {
+            (_, None) => return format!("b      {trap}"),
+            // Narrow cells cannot leave the range of a register, so the result
+            // is checked against the cell's range instead of the flags.
+            (CellWidth::Bits8 | CellWidth::Bits16, _) => {
+                let range = match signed {
+                    true => format!(
+                        "{}   r3, r0\n    cmp    r3, r0\n    bne    {trap}",
+                        if cell == CellWidth::Bits8 {
+                            "sxtb"
+                        } else {
+                            "sxth"
+                        }
+                    ),
+                    false => format!(
+                        "mov32  r3, #{}\n    cmp    r0, r3\n    bhs    {trap}",
+                        1u64 << cell.bits()
+                    ),
+                };
+
+                format!("mov32  r2, #{magnitude}\n    {op}    r0, r0, r2\n    {range}")
+            }
+            (CellWidth::Bits32, Some(steps)) => steps
+                .into_iter()
+                .map(|step| {
+                    format!("mov32  r2, #{step}\n    {op}s   r0, r0, r2\n    {branch}    {trap}")
+                })
+                .collect::<Vec<_>>()
+                .join("\n    "),
+            (CellWidth::Bits64, Some(steps)) => steps
+                .into_iter()
+                .map(|step| {
+                    format!(
+                        "mov32  r2, #{}\n    \
+                         mov32  r3, #{}\n    \
+                         {op}s   r0, r0, r2\n    \
+                         {}s   r1, r1, r3\n    \
+                         {branch}    {trap}",
+                        step & 0xffff_ffff,
+                        step >> 32,
+                        if op == "add" { "adc" } else { "sbc" },
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n    "),
+        };
+
+        format!(
+            "{}\n    {steps}\n    {}",
+            load_extended(cell, signed, index),
+            store(cell, index)
+        )
+    }
+
+    /// Checks that the signed cell at byte offset `index` is not negative.
+    fn negative(&mut self, span: Span, index: &str) -> String {
+        let Options { cell, signed, .. } = self.options;
+        let sign = if cell == CellWidth::Bits64 {
+            "r1"
+        } else {
+            "r0"
+        };
+
+        match signed && !self.options.wrapping {
+            true => format!(
+                "{}\n    cmp    {sign}, #0\n    blt    {}\n    ",
+                load_extended(cell, signed, index),
+                self.add(InterpreterError::IntegerUnderflow(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn emit(&self) -> String {
+        if self.count == 0 {
+            return String::new();
+        }
+
+        formatdoc! {"
+
+            .text
+            {stubs}
+            trap:
+                mov    r0, #2
+                mov    r7, #4
+                svc    #0
+                mov    r0, r3
+                mov    r7, #1
+                svc    #0
+
+            .data
+            {messages}"
+        , stubs = self.stubs, messages = self.messages}
+    }
+}
+
+/// Loads the cell at byte offset `index` into `r0` (and `r1` for the high
+/// word of 64-bit cells).
+fn load(cell: CellWidth, index: &str) -> String {
+    match cell {
+        CellWidth::Bits8 => format!("ldrb   r0, [r5, {index}]"),
+        CellWidth::Bits16 => format!("ldrh   r0, [r5, {index}]"),
+        CellWidth::Bits32 => format!("ldr    r0, [r5, {index}]"),
+        CellWidth::Bits64 => format!("ldrd   r0, r1, [r5, {index}]"),
+    }
+}
+
+/// Stores `r0` (and `r1` for the high word of 64-bit cells) into the cell at
+/// byte offset `index`.
+fn store(cell: CellWidth, index: &str) -> String {
+    match cell {
+        CellWidth::Bits8 => format!("strb   r0, [r5, {index}]"),
+        CellWidth::Bits16 => format!("strh   r0, [r5, {index}]"),
+        CellWidth::Bits32 => format!("str    r0, [r5, {index}]"),
+        CellWidth::Bits64 => format!("strd   r0, r1, [r5, {index}]"),
+    }
+}
+
+/// Like [`load`], but sign-extends narrow cells if they are `signed`.
+fn load_extended(cell: CellWidth, signed: bool, index: &str) -> String {
+    match (cell, signed) {
+        (CellWidth::Bits8, true) => format!("ldrsb  r0, [r5, {index}]"),
+        (CellWidth::Bits16, true) => format!("ldrsh  r0, [r5, {index}]"),
+        _ => load(cell, index),
+    }
+}
+
+/// Sets the Z flag if the current cell is zero.
+fn test_zero(cell: CellWidth) -> String {
+    match cell {
+        CellWidth::Bits64 => format!("{}\n    orrs   r0, r0, r1", load(cell, "r4")),
+        _ => format!("{}\n    cmp    r0, #0", load(cell, "r4")),
+    }
+}
+
+/// Sets r6 to the byte offset of the cell `offset` away from the data pointer.
+fn select(offset: isize, bytes: usize) -> String {
+    match offset * bytes as isize {
+        0 => "mov    r6, r4".to_string(),
+        displacement => format!(
+            "mov32  r6, #{}\n    {}    r6, r4, r6",
+            displacement.unsigned_abs(),
+            if displacement > 0 { "add" } else { "sub" }
+        ),
+    }
+}
+
+/// Loads the cell whose index is in r9 into r1:r0, extended to 64 bits. r5
+/// holds the address of cell 0.
+fn load_dumped(cell: CellWidth, signed: bool) -> String {
+    let load = match (cell, signed) {
+        (CellWidth::Bits8, false) => "ldrb   r0, [r5, r9]",
+        (CellWidth::Bits8, true) => "ldrsb  r0, [r5, r9]",
+        (CellWidth::Bits16, false) => "lsl    r3, r9, #1\n    ldrh   r0, [r5, r3]",
+        (CellWidth::Bits16, true) => "lsl    r3, r9, #1\n    ldrsh  r0, [r5, r3]",
+        (CellWidth::Bits32, _) => "ldr    r0, [r5, r9, lsl #2]",
+        (CellWidth::Bits64, _) => return "lsl    r3, r9, #3\n    ldrd   r0, r1, [r5, r3]".into(),
+    };
+    let extend = if signed {
+        "asr    r1, r0, #31"
+    } else {
+        "mov    r1, #0"
+    };
+
+    format!("{load}\n    {extend}")
+}
+
+/// The `dump` routine, with the data it needs. It takes the header at r1, r2
+/// bytes long.
+fn dump(options: &Options, headers: &str) -> String {
+    let bytes = options.cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+    let index = match bytes.trailing_zeros() {
+        0 => String::new(),
+        shift => format!("\n    asr    r8, r8, #{shift}"),
+    };
+    let powers = (1..20)
+        .rev()
+        .map(|power| format!("    .quad  {}", 10u64.pow(power)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    formatdoc! {"
+
+        .text
+        dump:
+            push   {{r4-r11, lr}}
+            mov32  r10, #dumpbuf
+        dump_header:
+            ldrb   r3, [r1], #1
+            strb   r3, [r10], #1
+            subs   r2, r2, #1
+            bne    dump_header
+            mov32  r3, #{origin}
+            sub    r8, r4, r3{index}
+            add    r5, r5, r3
+            mov    r0, r8
+            asr    r1, r0, #31
+            bl     dump_signed
+            mov    r3, #':'
+            strb   r3, [r10], #1
+            sub    r9, r8, #{radius}
+            mov32  r3, #{first}
+            cmp    r9, r3
+            movlt  r9, r3
+            add    r11, r8, #{radius}
+            mov32  r3, #{last}
+            cmp    r11, r3
+            movgt  r11, r3
+        dump_cell:
+            mov    r3, #' '
+            strb   r3, [r10], #1
+            cmp    r9, r8
+            moveq  r3, #'['
+            strbeq r3, [r10], #1
+            {load}
+            bl     {number}
+            cmp    r9, r8
+            moveq  r3, #']'
+            strbeq r3, [r10], #1
+            add    r9, r9, #1
+            cmp    r9, r11
+            ble    dump_cell
+            mov    r3, #10
+            strb   r3, [r10], #1
+            mov32  r1, #dumpbuf
+            sub    r2, r10, r1
+            mov    r0, #2
+            mov    r7, #4
+            svc    #0
+            pop    {{r4-r11, pc}}
+
+        @ Appends r1:r0 in decimal at r10, read as signed or unsigned.
+        dump_signed:
+            cmp    r1, #0
+            bge    dump_number
+            mov    r3, #'-'
+            strb   r3, [r10], #1
+            rsbs   r0, r0, #0
+            rsc    r1, r1, #0
+        dump_number:
+            mov32  r12, #pow10
+            mov    r7, #19
+            mov    r6, #0
+        dump_digit:
+            ldrd   r2, r3, [r12], #8
+            mov    r4, #0
+        dump_subtract:
+            subs   r0, r0, r2
+            sbcs   r1, r1, r3
+            addcs  r4, r4, #1
+            bcs    dump_subtract
+            adds   r0, r0, r2
+            adc    r1, r1, r3
+            orrs   r6, r6, r4
+            addne  r4, r4, #'0'
+            strbne r4, [r10], #1
+            subs   r7, r7, #1
+            bne    dump_digit
+            add    r0, r0, #'0'
+            strb   r0, [r10], #1
+            mov    pc, lr
+
+        .data
+        .balign 8
+        pow10:
+        {powers}
+        {headers}
+        .bss
+        dumpbuf: .space 512, 0x0
+    ",
+        radius = DUMP_RADIUS,
+        first = (origin / bytes).wrapping_neg() as u32,
+        last = (tape_size - origin) / bytes - 1,
+        load = load_dumped(options.cell, options.signed),
+        number = if options.signed { "dump_signed" } else { "dump_number" },
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
     let mut asm = String::new();
+    let cell = options.cell;
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => String::new(),
+        Eof::Zero => format!(
+            "mov    r0, #0\n    mov    r1, #0\n    {}\n    ",
+            store(cell, "r6")
+        ),
+        Eof::MinusOne => format!(
+            "mvn    r0, #0\n    mvn    r1, #0\n    {}\n    ",
+            store(cell, "r6")
+        ),
+    };
+    let bytes = cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
 
+    // r5 holds the address of the tape.
+    let start = match options.tape {
+        Tape::Fixed => "mov32  r5, #tape\n".to_string(),
+        Tape::Growable | Tape::Bidirectional => formatdoc! {"
+            mov    r0, #0
+                mov32  r1, #{mapped}
+                mov    r2, #0
+                mov32  r3, #0x4022
+                mvn    r4, #0
+                mov    r5, #0
+                mov    r7, #192
+                svc    #0
+                cmn    r0, #4096
+                bhi    no_tape
+                mov32  r1, #{GUARD}
+                add    r5, r0, r1
+                mov    r0, r5
+                mov32  r1, #{tape_size}
+                mov    r2, #3
+                mov    r7, #125
+                svc    #0
+                cmp    r0, #0
+                bne    no_tape
+            ",
+            mapped = tape_size + 2 * GUARD,
+        },
+    };
+
+    // `read` and `write` act on the cell whose byte offset is in r6.
     asm.push_str(&formatdoc! {"
+        @ {description}
+
         .syntax unified
         .global _start
 
@@ -16,7 +423,7 @@ pub fn codegen(program: &[Token], memory: usize) -> String {
         .text
         write:
             mov    r0, #1
-            add    r1, r5, r4
+            add    r1, r5, r6
             mov    r2, #1
             mov    r7, #4
             svc    #0
@@ -24,67 +431,250 @@ pub fn codegen(program: &[Token], memory: usize) -> String {
 
         read:
             mov    r0, #0
-            add    r1, r5, r4
+            mov32  r1, #inbuf
             mov    r2, #1
             mov    r7, #3
             svc    #0
+            cmp    r0, #1
+            bne    read_eof
+            mov32  r1, #inbuf
+            ldrb   r0, [r1]
+            mov    r1, #0
+            {store}
             mov    pc, lr
+        read_eof:
+            {on_eof}mov    pc, lr
 
         _start:
-            mov    r4, #0
-            mov32  r5, #tape
-    "});
+            {start}    mov32  r4, #{origin}
+    ",
+        description = options.describe(),
+        store = store(cell, "r6"),
+    });
+
+    // The header of each `Debug` dump, in the data section.
+    let mut headers = String::new();
+    let mut traps = Traps::new(options);
+
+    for (i, instruction) in program.iter().enumerate() {
+        let span = instruction.span;
+
+        // Checks the cell an instruction selected into r6, if it is not the
+        // current one.
+        let mut check = |offset: isize| match offset {
+            0 => String::new(),
+            _ => traps.offset(span, "r6"),
+        };
+
+        asm.push_str(&match instruction.node {
+            Instruction::Move(distance) => formatdoc! {"
+            @
+                mov32  r0, #{offset}
+                {op}    r4, r4, r0
+            {check}",
+                offset = distance.unsigned_abs() * bytes,
+                op = if distance >= 0 { "add" } else { "sub" },
+                check = traps.pointer(span),
+            },
+            Instruction::Add { offset, value } if !options.wrapping => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "r4"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "r6",
+                    ),
+                };
 
-    for (i, token) in program.iter().enumerate() {
-        asm.push_str(&match token {
-            Token::Right(operand) => {
                 formatdoc! {"
                 @
-                    mov32  r0, #{operand}
-                    add    r4, r4, r0
-                "}
+                    {setup}{add}
+                ", add = traps.checked_add(span, index, value)}
             }
-            Token::Left(operand) => formatdoc! {"
-            @
-                mov32  r0, #{operand}
-                sub    r4, r4, r0
-            "},
-            Token::Add(operand) => formatdoc! {"
-            @
-                mov32  r0, #{operand}
-                ldrb   r1, [r5, r4]
-                add    r1, r0, r1
-                strb   r1, [r5, r4]
-            "},
-            Token::Sub(operand) => formatdoc! {"
-            @
-                mov32  r0, #{operand}
-                ldrb   r1, [r5, r4]
-                sub    r1, r1, r0
-                strb   r1, [r5, r4]
-            "},
-            Token::Read => formatdoc! {"
+            Instruction::Add { offset, value } => {
+                let op = if value >= 0 { "add" } else { "sub" };
+                let value = cell.truncate(value.unsigned_abs() as u64);
+                let (setup, index) = match offset {
+                    0 => (String::new(), "r4"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "r6",
+                    ),
+                };
+
+                match cell {
+                    CellWidth::Bits64 => formatdoc! {"
+                    @
+                        {setup}mov32  r2, #{low}
+                        mov32  r3, #{high}
+                        {load}
+                        {op}s   r0, r0, r2
+                        {op_carry}    r1, r1, r3
+                        {store}
+                    ",
+                        low = value & 0xffff_ffff,
+                        high = value >> 32,
+                        op_carry = if op == "add" { "adc" } else { "sbc" },
+                        load = load(cell, index),
+                        store = store(cell, index),
+                    },
+                    _ => formatdoc! {"
+                    @
+                        {setup}mov32  r2, #{value}
+                        {load}
+                        {op}    r0, r0, r2
+                        {store}
+                    ",
+                        load = load(cell, index),
+                        store = store(cell, index),
+                    },
+                }
+            }
+            Instruction::Clear { offset } => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "r4"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "r6",
+                    ),
+                };
+
+                formatdoc! {"
+                @
+                    {setup}{negative}mov    r0, #0
+                    mov    r1, #0
+                    {store}
+                ",
+                    negative = traps.negative(span, index),
+                    store = store(cell, index),
+                }
+            }
+            Instruction::MulAdd { offset, factor } if !options.wrapping => {
+                let count = match cell {
+                    CellWidth::Bits64 => {
+                        "subs   r8, r8, #1\n    sbc    r9, r9, #0\n    orrs   r2, r8, r9"
+                    }
+                    _ => "subs   r8, r8, #1",
+                };
+
+                formatdoc! {"
+                @
+                    {load_counter}
+                    {test}
+                    beq    M{i}
+                    {select}
+                    {check}{negative}mov    r8, r0
+                    mov    r9, r1
+                M{i}_loop:
+                    {add}
+                    {count}
+                    bne    M{i}_loop
+                M{i}:
+                ",
+                    load_counter = load(cell, "r4"),
+                    test = match cell {
+                        CellWidth::Bits64 => "orrs   r2, r0, r1",
+                        _ => "cmp    r0, #0",
+                    },
+                    select = select(offset, bytes),
+                    check = check(offset),
+                    negative = traps.negative(span, "r4"),
+                    add = traps.checked_add(span, "r6", factor),
+                }
+            }
+            Instruction::MulAdd { offset, factor } => {
+                let factor = factor as i64 as u64;
+
+                // The low 64 bits of a 64 by 64-bit product, in r9:r8.
+                let multiply = match cell {
+                    CellWidth::Bits64 => format!(
+                        "mov32  r2, #{}\n    \
+                         mov32  r3, #{}\n    \
+                         umull  r8, r9, r0, r2\n    \
+                         mla    r9, r0, r3, r9\n    \
+                         mla    r9, r1, r2, r9",
+                        factor & 0xffff_ffff,
+                        factor >> 32,
+                    ),
+                    _ => format!(
+                        "mov32  r2, #{}\n    mul    r8, r0, r2",
+                        factor & 0xffff_ffff
+                    ),
+                };
+                let add = match cell {
+                    CellWidth::Bits64 => "adds   r0, r0, r8\n    adc    r1, r1, r9",
+                    _ => "add    r0, r0, r8",
+                };
+                let test = match cell {
+                    CellWidth::Bits64 => "orrs   r2, r0, r1",
+                    _ => "cmp    r0, #0",
+                };
+
+                formatdoc! {"
+                @
+                    {load_counter}
+                    {test}
+                    beq    M{i}
+                    {multiply}
+                    {select}
+                    {check}{load}
+                    {add}
+                    {store}
+                M{i}:
+                ",
+                    check = check(offset),
+                    load_counter = load(cell, "r4"),
+                    select = select(offset, bytes),
+                    load = load(cell, "r6"),
+                    store = store(cell, "r6"),
+                }
+            }
+            Instruction::Scan(stride) => formatdoc! {"
+                @
+                    mov32  r3, #{distance}
+                S{i}:
+                    {test}
+                    beq    S{i}_end
+                    {op}    r4, r4, r3
+                    {check}b      S{i}
+                S{i}_end:
+            ",
+                distance = stride.unsigned_abs() * bytes,
+                test = test_zero(cell),
+                op = if stride > 0 { "add" } else { "sub" },
+                check = traps.offset(span, "r4"),
+            },
+            Instruction::Input { offset } => formatdoc! {"
             @
-                bl     read
-            "},
-            Token::Write => formatdoc! {"
+                {select}
+                {check}bl     read
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Output { offset } => formatdoc! {"
             @
-                bl     write
-            "},
-            Token::Loop(j) => formatdoc! {"
+                {select}
+                {check}bl     write
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Debug => {
+                let header = format!("# {} cell ", instruction.span);
+                headers.push_str(&format!("D{i}:  .ascii \"{header}\"\n"));
+
+                formatdoc! {"
                 @
-                    ldrb   r0, [r5, r4]
-                    cmp    r0, #0
+                    mov32  r1, #D{i}
+                    mov32  r2, #{length}
+                    bl     dump
+                ", length = header.len()}
+            }
+            Instruction::LoopEnd(j) => formatdoc! {"
+                @
+                    {test}
                     bne    L{j}
                 L{i}:
-            "},
-            Token::Break(j) => formatdoc! {"
+            ", test = test_zero(cell)},
+            Instruction::LoopStart(j) => formatdoc! {"
                 L{i}:
-                    ldrb   r0, [r5, r4]
-                    cmp    r0, #0
+                    {test}
                     beq    L{j}
-            "},
-            Token::Comment => String::new(),
+            ", test = test_zero(cell)},
         });
     }
 
@@ -95,8 +685,76 @@ pub fn codegen(program: &[Token], memory: usize) -> String {
             svc    #0
 
         .bss
-        tape:    .space {memory}, 0x0
-    "});
+        .balign 8
+        {tape}inbuf:   .space 1, 0x0
+    ", tape = match options.tape {
+        Tape::Fixed => format!("tape:    .space {tape_size}, 0x0\n"),
+        Tape::Growable | Tape::Bidirectional => String::new(),
+    }});
+
+    if options.tape != Tape::Fixed {
+        asm.push_str(&formatdoc! {"
+
+            .text
+            no_tape:
+                mov    r0, #2
+                mov32  r1, #no_tape_message
+                mov32  r2, #{length}
+                mov    r7, #4
+                svc    #0
+                mov    r0, #1
+                mov    r7, #1
+                svc    #0
+
+            .data
+            no_tape_message:  .ascii \"{NO_TAPE}\\n\"
+        ", length = NO_TAPE.len() + 1});
+    }
+
+    asm.push_str(&traps.emit());
+    if !headers.is_empty() {
+        asm.push_str(&dump(options, &headers));
+    }
 
     asm
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::options;
+    use rstest::*;
+
+    #[rstest]
+    #[case(CellWidth::Bits32, false, 1, "adds   r0, r0, r2\n    bcs    T0")]
+    #[case(CellWidth::Bits32, false, -1, "subs   r0, r0, r2\n    bcc    T0")]
+    #[case(CellWidth::Bits32, true, 1, "adds   r0, r0, r2\n    bvs    T0")]
+    #[case(CellWidth::Bits64, false, 1, "adcs   r1, r1, r3\n    bcs    T0")]
+    #[case(CellWidth::Bits64, true, -1, "sbcs   r1, r1, r3\n    bvs    T0")]
+    #[case(CellWidth::Bits8, false, 1, "cmp    r0, r3\n    bhs    T0")]
+    #[case(
+        CellWidth::Bits8,
+        true,
+        1,
+        "sxtb   r3, r0\n    cmp    r3, r0\n    bne    T0"
+    )]
+    fn test_checked_add(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] value: isize,
+        #[case] expected: &str,
+    ) {
+        let mut traps = Traps::new(&options(cell, signed));
+        let code = traps.checked_add(Span::default(), "r6", value);
+
+        assert!(code.contains(expected), "{code}");
+    }
+
+    #[test]
+    fn test_negative_64() {
+        let code = Traps::new(&options(CellWidth::Bits64, true)).negative(Span::default(), "r4");
+
+        // The sign is in the high word.
+        assert!(code.contains("cmp    r1, #0\n    blt    T0"), "{code}");
+    }
+}
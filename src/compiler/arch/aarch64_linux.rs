@@ -0,0 +1,752 @@
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::{checked_steps, Options, GUARD, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
+use indoc::formatdoc;
+
+/// 64 GiB, which fits even the 39-bit address space of kernels built with
+/// the smallest virtual addresses.
+const RESERVED: usize = 1 << 36;
+
+/// The checks described in [`super`], which branch to stubs that load the
+/// message into x1 and x2 and the status into x3.
+struct Traps {
+    options: Options,
+    stubs: String,
+    messages: String,
+    count: usize,
+}
+
+impl Traps {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            stubs: String::new(),
+            messages: String::new(),
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, error: InterpreterError) -> String {
+        let n = self.count;
+        let message = format!("Error: {error}");
+
+        self.count += 1;
+        self.messages
+            .push_str(&format!("E{n}:  .ascii \"{message}\\n\"\n"));
+        self.stubs.push_str(&formatdoc! {"
+            T{n}:
+                {address}
+                mov    x2, #{length}
+                mov    x3, #{status}
+                b      trap
+        ",
+            address = address("x1", &format!("E{n}")),
+            length = message.len() + 1,
+            status = error.status(),
+        });
+
+        format!("T{n}")
+    }
+
+    /// Checks the data pointer, whose byte offset is in x20.
+    fn pointer(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!("    {}\n", self.offset(span, "x20").trim_end()),
+            false => String::new(),
+        }
+    }
+
+    /// Checks the byte offset in `register` against the size of the tape in
+    /// x22.
+    fn offset(&mut self, span: Span, register: &str) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "cmp    {register}, x22\n    b.hs   {}\n    ",
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    /// Adds `value` to the cell at byte offset `index`, checked with `b.cs`
+    /// or `b.cc`, or `b.vs` for signed cells.
+    fn checked_add(&mut self, span: Span, index: &str, value: isize) -> String {
+        let Options { cell, signed, .. } = self.options;
+        let magnitude = value.unsigned_abs() as u64;
+        let (op, trap) = match value >= 0 {
+            true => ("add", self.add(InterpreterError::IntegerOverflow(span))),
+            false => ("sub", self.add(InterpreterError::IntegerUnderflow(span))),
+        };
+        let branch = match (signed, op) {
+            (true, _) => "b.vs",
+            (false, "add") => "b.cs",
+            (false, _) => "b.cc",
+        };
+
+        let steps = match (cell, checked_steps(magnitude, &self.options)) {
+            (_, None) => return format!("b      {trap}"),
+            // Narrow cells are checked against their range instead of the
+            // flags, which only follow whole registers.
+            (CellWidth::Bits8 | CellWidth::Bits16, _) => {
+                let range = match signed {
+                    true => format!(
+                        "{}   x3, w0\n    cmp    x3, x0\n    b.ne   {trap}",
+                        if cell == CellWidth::Bits8 {
+                            "sxtb"
+                        } else {
+                            "sxth"
+                        }
+                    ),
+                    false => format!(
+                        "{}\n    cmp    x0, x3\n    b.hs   {trap}",
+                        mov("x3", 1u64 << cell.bits())
+                    ),
+                };
+
+                format!(
+                    "{}\n    {op}    x0, x0, x2\n    {range}",
+                    mov("x2", magnitude)
+                )
+            }
+            // The flags of 32-bit operations are those of the cell.
+            (_, Some(steps)) => {
+                let register = if cell == CellWidth::Bits32 { "w" } else { "x" };
+
+                steps
+                    .into_iter()
+                    .map(|step| {
+                        format!(
+                            "{}\n    {op}s   {register}0, {register}0, {register}2\n    {branch}   {trap}",
+                            mov("x2", step)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n    ")
+            }
+        };
+
+        format!(
+            "{}\n    {steps}\n    {}",
+            load_extended(cell, signed, index),
+            store(cell, "x0", index)
+        )
+    }
+
+    /// Checks that the signed cell at byte offset `index` is not negative.
+    fn negative(&mut self, span: Span, index: &str) -> String {
+        let Options { cell, signed, .. } = self.options;
+
+        match signed && !self.options.wrapping {
+            true => format!(
+                "{}\n    cmp    x0, #0\n    b.lt   {}\n    ",
+                load_extended(cell, signed, index),
+                self.add(InterpreterError::IntegerUnderflow(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn emit(&self) -> String {
+        if self.count == 0 {
+            return String::new();
+        }
+
+        formatdoc! {"
+
+            .text
+            {stubs}
+            trap:
+                mov    x0, #2
+                mov    x8, #64
+                svc    #0
+                mov    x0, x3
+                mov    x8, #93
+                svc    #0
+
+            .data
+            {messages}"
+        , stubs = self.stubs, messages = self.messages}
+    }
+}
+
+/// Sets `register` to `value`, 16 bits at a time.
+fn mov(register: &str, value: u64) -> String {
+    if value < 0x1_0000 {
+        return format!("mov    {register}, #{value}");
+    }
+
+    (0..4)
+        .filter(|chunk| chunk == &0 || (value >> (16 * chunk)) & 0xffff != 0)
+        .map(|chunk| {
+            format!(
+                "{}   {register}, #{}, lsl #{}",
+                if chunk == 0 { "movz" } else { "movk" },
+                (value >> (16 * chunk)) & 0xffff,
+                16 * chunk
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n    ")
+}
+
+/// Sets `register` to `value` plus or minus a constant that may not fit an
+/// immediate.
+fn add_constant(register: &str, source: &str, value: isize) -> String {
+    let op = if value >= 0 { "add" } else { "sub" };
+
+    match value.unsigned_abs() {
+        magnitude if magnitude < 0x1000 => format!("{op}    {register}, {source}, #{magnitude}"),
+        magnitude => format!(
+            "{}\n    {op}    {register}, {source}, x9",
+            mov("x9", magnitude as u64)
+        ),
+    }
+}
+
+/// Sets `register` to the address of `symbol`.
+fn address(register: &str, symbol: &str) -> String {
+    format!("adrp   {register}, {symbol}\n    add    {register}, {register}, :lo12:{symbol}")
+}
+
+/// Loads the cell at byte offset `index` into x0, zero-extended.
+fn load(cell: CellWidth, index: &str) -> String {
+    match cell {
+        CellWidth::Bits8 => format!("ldrb   w0, [x19, {index}]"),
+        CellWidth::Bits16 => format!("ldrh   w0, [x19, {index}]"),
+        CellWidth::Bits32 => format!("ldr    w0, [x19, {index}]"),
+        CellWidth::Bits64 => format!("ldr    x0, [x19, {index}]"),
+    }
+}
+
+/// Stores the low bits of the 64-bit `register` into the cell at byte offset
+/// `index`.
+fn store(cell: CellWidth, register: &str, index: &str) -> String {
+    let low = register.replacen('x', "w", 1);
+
+    match cell {
+        CellWidth::Bits8 => format!("strb   {low}, [x19, {index}]"),
+        CellWidth::Bits16 => format!("strh   {low}, [x19, {index}]"),
+        CellWidth::Bits32 => format!("str    {low}, [x19, {index}]"),
+        CellWidth::Bits64 => format!("str    {register}, [x19, {index}]"),
+    }
+}
+
+/// Like [`load`], but sign-extends cells if they are `signed`.
+fn load_extended(cell: CellWidth, signed: bool, index: &str) -> String {
+    match (cell, signed) {
+        (CellWidth::Bits8, true) => format!("ldrsb  x0, [x19, {index}]"),
+        (CellWidth::Bits16, true) => format!("ldrsh  x0, [x19, {index}]"),
+        (CellWidth::Bits32, true) => format!("ldrsw  x0, [x19, {index}]"),
+        _ => load(cell, index),
+    }
+}
+
+/// Sets x21 to the byte offset of the cell `offset` away from the data
+/// pointer.
+fn select(offset: isize, bytes: usize) -> String {
+    match offset * bytes as isize {
+        0 => "mov    x21, x20".to_string(),
+        displacement => add_constant("x21", "x20", displacement),
+    }
+}
+
+/// Loads the cell whose index is in x9 into x0, extended to 64 bits. x11
+/// holds the address of cell 0.
+fn load_dumped(cell: CellWidth, signed: bool) -> &'static str {
+    match (cell, signed) {
+        (CellWidth::Bits8, false) => "ldrb   w0, [x11, x9]",
+        (CellWidth::Bits8, true) => "ldrsb  x0, [x11, x9]",
+        (CellWidth::Bits16, false) => "ldrh   w0, [x11, x9, lsl #1]",
+        (CellWidth::Bits16, true) => "ldrsh  x0, [x11, x9, lsl #1]",
+        (CellWidth::Bits32, false) => "ldr    w0, [x11, x9, lsl #2]",
+        (CellWidth::Bits32, true) => "ldrsw  x0, [x11, x9, lsl #2]",
+        (CellWidth::Bits64, _) => "ldr    x0, [x11, x9, lsl #3]",
+    }
+}
+
+/// The `dump` routine, with the data it needs. It takes the header at x1, x2
+/// bytes long.
+fn dump(options: &Options, headers: &str) -> String {
+    let bytes = options.cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    formatdoc! {"
+
+        .text
+        dump:
+            stp    x29, x30, [sp, #-16]!
+            {dumpbuf}
+        dump_header:
+            ldrb   w3, [x1], #1
+            strb   w3, [x10], #1
+            subs   x2, x2, #1
+            b.ne   dump_header
+            {origin}
+            add    x11, x19, x3
+            sub    x12, x20, x3
+            asr    x12, x12, #{shift}
+            mov    x0, x12
+            bl     dump_signed
+            mov    w3, #':'
+            strb   w3, [x10], #1
+            sub    x9, x12, #{radius}
+            {first}
+            cmp    x9, x3
+            csel   x9, x3, x9, lt
+            add    x13, x12, #{radius}
+            {last}
+            cmp    x13, x3
+            csel   x13, x3, x13, gt
+        dump_cell:
+            mov    w3, #' '
+            strb   w3, [x10], #1
+            cmp    x9, x12
+            b.ne   dump_value
+            mov    w3, #'['
+            strb   w3, [x10], #1
+        dump_value:
+            {load}
+            bl     {number}
+            cmp    x9, x12
+            b.ne   dump_next
+            mov    w3, #']'
+            strb   w3, [x10], #1
+        dump_next:
+            add    x9, x9, #1
+            cmp    x9, x13
+            b.le   dump_cell
+            mov    w3, #10
+            strb   w3, [x10], #1
+            {write}
+            sub    x2, x10, x1
+            mov    x0, #2
+            mov    x8, #64
+            svc    #0
+            ldp    x29, x30, [sp], #16
+            ret
+
+        // Appends x0 in decimal at x10, read as signed or unsigned.
+        dump_signed:
+            cmp    x0, #0
+            b.ge   dump_number
+            mov    w3, #'-'
+            strb   w3, [x10], #1
+            neg    x0, x0
+        dump_number:
+            {digits}
+            add    x4, x4, #20
+            mov    x5, x4
+            mov    x6, #10
+        dump_digit:
+            udiv   x3, x0, x6
+            msub   x2, x3, x6, x0
+            add    w2, w2, #'0'
+            strb   w2, [x4, #-1]!
+            mov    x0, x3
+            cbnz   x0, dump_digit
+        dump_copy:
+            ldrb   w2, [x4], #1
+            strb   w2, [x10], #1
+            cmp    x4, x5
+            b.ne   dump_copy
+            ret
+
+        .data
+        {headers}
+        .bss
+        dumpbuf: .space 512, 0x0
+        digits:  .space 20, 0x0
+    ",
+        dumpbuf = address("x10", "dumpbuf"),
+        origin = mov("x3", origin as u64),
+        shift = bytes.trailing_zeros(),
+        radius = DUMP_RADIUS,
+        first = mov("x3", (origin / bytes).wrapping_neg() as u64),
+        last = mov("x3", ((tape_size - origin) / bytes - 1) as u64),
+        load = load_dumped(options.cell, options.signed),
+        number = if options.signed { "dump_signed" } else { "dump_number" },
+        write = address("x1", "dumpbuf"),
+        digits = address("x4", "digits"),
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
+    let mut asm = String::new();
+    let cell = options.cell;
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => String::new(),
+        Eof::Zero => format!("{}\n    ", store(cell, "xzr", "x21")),
+        Eof::MinusOne => format!("mov    x0, #-1\n    {}\n    ", store(cell, "x0", "x21")),
+    };
+    let bytes = cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    // x19 holds the address of the tape, and x22 its size.
+    let start = match options.tape {
+        Tape::Fixed => format!("{}\n", address("x19", "tape")),
+        Tape::Growable | Tape::Bidirectional => formatdoc! {"
+            mov    x0, #0
+                {mapped}
+                mov    x2, #0
+                mov    x3, #0x4022
+                mov    x4, #-1
+                mov    x5, #0
+                mov    x8, #222
+                svc    #0
+                cmn    x0, #4096
+                b.hi   no_tape
+                {guard}
+                add    x19, x0, x1
+                mov    x0, x19
+                {size}
+                mov    x2, #3
+                mov    x8, #226
+                svc    #0
+                cbnz   x0, no_tape
+            ",
+            mapped = mov("x1", (tape_size + 2 * GUARD) as u64),
+            guard = mov("x1", GUARD as u64),
+            size = mov("x1", tape_size as u64),
+        },
+    };
+
+    // `read` and `write` act on the cell whose byte offset is in x21.
+    asm.push_str(&formatdoc! {"
+        // {description}
+
+        .global _start
+
+        .text
+        write:
+            mov    x0, #1
+            add    x1, x19, x21
+            mov    x2, #1
+            mov    x8, #64
+            svc    #0
+            ret
+
+        read:
+            mov    x0, #0
+            {inbuf}
+            mov    x2, #1
+            mov    x8, #63
+            svc    #0
+            cmp    x0, #1
+            b.ne   read_eof
+            ldrb   w0, [x1]
+            {store}
+            ret
+        read_eof:
+            {on_eof}ret
+
+        _start:
+            {start}    {limit}
+            {origin}
+    ",
+        description = options.describe(),
+        inbuf = address("x1", "inbuf"),
+        store = store(cell, "x0", "x21"),
+        limit = mov("x22", tape_size as u64),
+        origin = mov("x20", origin as u64),
+    });
+
+    // The header of each `Debug` dump, in the data section.
+    let mut headers = String::new();
+    let mut traps = Traps::new(options);
+
+    for (i, instruction) in program.iter().enumerate() {
+        let span = instruction.span;
+
+        // Checks the cell an instruction selected into x21, if it is not the
+        // current one.
+        let mut check = |offset: isize| match offset {
+            0 => String::new(),
+            _ => traps.offset(span, "x21"),
+        };
+
+        asm.push_str(&match instruction.node {
+            Instruction::Move(distance) => formatdoc! {"
+            //
+                {add}
+            {check}",
+                add = add_constant("x20", "x20", distance * bytes as isize),
+                check = traps.pointer(span),
+            },
+            Instruction::Add { offset, value } if !options.wrapping => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "x20"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "x21",
+                    ),
+                };
+
+                formatdoc! {"
+                //
+                    {setup}{add}
+                ", add = traps.checked_add(span, index, value)}
+            }
+            Instruction::Add { offset, value } => {
+                let op = if value >= 0 { "add" } else { "sub" };
+                let value = cell.truncate(value.unsigned_abs() as u64);
+                let (setup, index) = match offset {
+                    0 => (String::new(), "x20"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "x21",
+                    ),
+                };
+                let operand = match value {
+                    0..0x1000 => format!("#{value}"),
+                    _ => "x2".to_string(),
+                };
+
+                formatdoc! {"
+                //
+                    {setup}{load}
+                    {set}{op}    x0, x0, {operand}
+                    {store}
+                ",
+                    load = load(cell, index),
+                    set = match value {
+                        0..0x1000 => String::new(),
+                        _ => format!("{}\n    ", mov("x2", value)),
+                    },
+                    store = store(cell, "x0", index),
+                }
+            }
+            Instruction::Clear { offset } => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "x20"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "x21",
+                    ),
+                };
+
+                formatdoc! {"
+                //
+                    {setup}{negative}{store}
+                ",
+                    negative = traps.negative(span, index),
+                    store = store(cell, "xzr", index),
+                }
+            }
+            Instruction::MulAdd { offset, factor } if !options.wrapping => formatdoc! {"
+            //
+                {load_counter}
+                cbz    x0, M{i}
+                {select}
+                {check}{negative}mov    x10, x0
+            M{i}_loop:
+                {add}
+                subs   x10, x10, #1
+                b.ne   M{i}_loop
+            M{i}:
+            ",
+                load_counter = load(cell, "x20"),
+                select = select(offset, bytes),
+                check = check(offset),
+                negative = traps.negative(span, "x20"),
+                add = traps.checked_add(span, "x21", factor),
+            },
+            Instruction::MulAdd { offset, factor } => formatdoc! {"
+            //
+                {load_counter}
+                cbz    x0, M{i}
+                {factor}
+                mul    x3, x0, x2
+                {select}
+                {check}{load}
+                add    x0, x0, x3
+                {store}
+            M{i}:
+            ",
+                load_counter = load(cell, "x20"),
+                factor = mov("x2", factor as i64 as u64),
+                select = select(offset, bytes),
+                check = check(offset),
+                load = load(cell, "x21"),
+                store = store(cell, "x0", "x21"),
+            },
+            // Scans and headers are not labeled `S{i}` and `D{i}` as on other
+            // targets, since those name registers here.
+            Instruction::Scan(stride) => formatdoc! {"
+                //
+                scan{i}:
+                    {load}
+                    cbz    x0, scan{i}_end
+                    {step}
+                    {check}b      scan{i}
+                scan{i}_end:
+            ",
+                load = load(cell, "x20"),
+                step = add_constant("x20", "x20", stride * bytes as isize),
+                check = traps.offset(span, "x20"),
+            },
+            Instruction::Input { offset } => formatdoc! {"
+            //
+                {select}
+                {check}bl     read
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Output { offset } => formatdoc! {"
+            //
+                {select}
+                {check}bl     write
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Debug => {
+                let header = format!("# {} cell ", instruction.span);
+                headers.push_str(&format!("header{i}: .ascii \"{header}\"\n"));
+
+                formatdoc! {"
+                //
+                    {address}
+                    mov    x2, #{length}
+                    bl     dump
+                ", address = address("x1", &format!("header{i}")), length = header.len()}
+            }
+            Instruction::LoopEnd(j) => formatdoc! {"
+                //
+                    {load}
+                    cbnz   x0, L{j}
+                L{i}:
+            ", load = load(cell, "x20")},
+            Instruction::LoopStart(j) => formatdoc! {"
+                L{i}:
+                    {load}
+                    cbz    x0, L{j}
+            ", load = load(cell, "x20")},
+        });
+    }
+
+    asm.push_str(&formatdoc! {"
+        exit:
+            mov    x0, #0
+            mov    x8, #93
+            svc    #0
+
+        .bss
+        .balign 8
+        {tape}inbuf:   .space 1, 0x0
+    ", tape = match options.tape {
+        Tape::Fixed => format!("tape:    .space {tape_size}, 0x0\n"),
+        Tape::Growable | Tape::Bidirectional => String::new(),
+    }});
+
+    if options.tape != Tape::Fixed {
+        asm.push_str(&formatdoc! {"
+
+            .text
+            no_tape:
+                mov    x0, #2
+                {message}
+                mov    x2, #{length}
+                mov    x8, #64
+                svc    #0
+                mov    x0, #1
+                mov    x8, #93
+                svc    #0
+
+            .data
+            no_tape_message:  .ascii \"{NO_TAPE}\\n\"
+        ",
+            message = address("x1", "no_tape_message"),
+            length = NO_TAPE.len() + 1,
+        });
+    }
+
+    asm.push_str(&traps.emit());
+    if !headers.is_empty() {
+        asm.push_str(&dump(options, &headers));
+    }
+
+    asm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::options;
+    use rstest::*;
+
+    #[rstest]
+    #[case(4095, "mov    x2, #4095")]
+    #[case(0xffff, "mov    x2, #65535")]
+    #[case(0x1_0000, "movz   x2, #0, lsl #0\n    movk   x2, #1, lsl #16")]
+    #[case(
+        0x1234_0000_5678,
+        "movz   x2, #22136, lsl #0\n    movk   x2, #4660, lsl #32"
+    )]
+    #[case(
+        u64::MAX,
+        "movz   x2, #65535, lsl #0\n    movk   x2, #65535, lsl #16\n    \
+         movk   x2, #65535, lsl #32\n    movk   x2, #65535, lsl #48"
+    )]
+    fn test_mov(#[case] value: u64, #[case] expected: &str) {
+        assert_eq!(mov("x2", value), expected);
+    }
+
+    #[rstest]
+    #[case(4095, "add    x20, x20, #4095")]
+    #[case(-1, "sub    x20, x20, #1")]
+    #[case(-4095, "sub    x20, x20, #4095")]
+    #[case(4096, "mov    x9, #4096\n    add    x20, x20, x9")]
+    #[case(-4096, "mov    x9, #4096\n    sub    x20, x20, x9")]
+    #[case(
+        -0x1_0001,
+        "movz   x9, #1, lsl #0\n    movk   x9, #1, lsl #16\n    sub    x20, x20, x9"
+    )]
+    fn test_add_constant(#[case] value: isize, #[case] expected: &str) {
+        assert_eq!(add_constant("x20", "x20", value), expected);
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut traps = Traps::new(&options(CellWidth::Bits8, false));
+
+        assert_eq!(traps.emit(), "");
+        assert_eq!(
+            traps.add(InterpreterError::IntegerOverflow(Span::default())),
+            "T0"
+        );
+        assert_eq!(
+            traps.add(InterpreterError::OutOfBounds(Span::default())),
+            "T1"
+        );
+
+        let code = traps.emit();
+        assert!(code.contains("T1:\n    adrp   x1, E1\n"));
+        assert!(
+            code.contains("mov    x8, #64\n    svc    #0\n    mov    x0, x3\n    mov    x8, #93")
+        );
+    }
+
+    #[rstest]
+    #[case(CellWidth::Bits32, false, 1, "adds   w0, w0, w2\n    b.cs   T0")]
+    #[case(CellWidth::Bits32, false, -1, "subs   w0, w0, w2\n    b.cc   T0")]
+    #[case(CellWidth::Bits64, true, 1, "adds   x0, x0, x2\n    b.vs   T0")]
+    #[case(CellWidth::Bits64, true, -1, "subs   x0, x0, x2\n    b.vs   T0")]
+    #[case(CellWidth::Bits16, false, 1, "cmp    x0, x3\n    b.hs   T0")]
+    #[case(
+        CellWidth::Bits8,
+        true,
+        1,
+        "sxtb   x3, w0\n    cmp    x3, x0\n    b.ne   T0"
+    )]
+    fn test_checked_add(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] value: isize,
+        #[case] expected: &str,
+    ) {
+        let mut traps = Traps::new(&options(cell, signed));
+        let code = traps.checked_add(Span::default(), "x21", value);
+
+        assert!(code.contains(expected), "{code}");
+    }
+}
@@ -0,0 +1,627 @@
+use crate::{
+    cell::{Eof, Tape},
+    compiler::{Options, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
+use indoc::formatdoc;
+
+/// The body of `main`, and what the routines before it need to provide.
+struct Writer {
+    options: Options,
+    body: String,
+    depth: usize,
+    /// Whether anything calls `fail`.
+    fails: bool,
+}
+
+impl Writer {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            body: String::new(),
+            depth: 1,
+            fails: false,
+        }
+    }
+
+    /// Appends the lines of `code` at the current depth.
+    fn push(&mut self, code: &str) {
+        for line in code.lines() {
+            self.body.push_str(&"    ".repeat(self.depth));
+            self.body.push_str(line);
+            self.body.push('\n');
+        }
+    }
+
+    /// A statement that prints `error` to stderr and exits with its status.
+    fn fail(&mut self, error: InterpreterError) -> String {
+        self.fails = true;
+
+        format!(
+            "fail({}, {});",
+            literal(&format!("Error: {error}\n")),
+            error.status()
+        )
+    }
+
+    /// Makes the cell at `index` safe to access. A fixed tape is checked with
+    /// bounds checks, a tape that grows is grown, and below a tape that only
+    /// grows to the right is out of bounds.
+    fn reach(&mut self, span: Span, index: &str) {
+        let code = match self.options.tape {
+            Tape::Fixed if self.options.bounds_check => format!(
+                "if ((size_t)({index}) >= size) {}",
+                self.fail(InterpreterError::OutOfBounds(span))
+            ),
+            Tape::Fixed => return,
+            Tape::Growable => format!(
+                "if ({index} < 0) {}\nreach({index});",
+                self.fail(InterpreterError::OutOfBounds(span))
+            ),
+            Tape::Bidirectional => {
+                self.fails = true;
+                format!("reach({index});")
+            }
+        };
+
+        self.push(&code);
+    }
+
+    /// Like [`Writer::reach`], for the cell `offset` away from the data
+    /// pointer, which is on the tape already.
+    fn select(&mut self, span: Span, offset: isize) -> String {
+        if offset != 0 {
+            self.reach(span, &index(offset));
+        }
+
+        format!("tape[{}]", index(offset))
+    }
+
+    /// Adds `value` to `target`, failing if the cell leaves its range.
+    fn checked_add(&mut self, span: Span, target: &str, value: isize) {
+        let magnitude = value.unsigned_abs();
+        let code = match value >= 0 {
+            true => format!(
+                "if ({magnitude}u > up({target})) {}\n{target} += {magnitude}u;",
+                self.fail(InterpreterError::IntegerOverflow(span))
+            ),
+            false => format!(
+                "if ({magnitude}u > down({target})) {}\n{target} -= {magnitude}u;",
+                self.fail(InterpreterError::IntegerUnderflow(span))
+            ),
+        };
+
+        self.push(&code);
+    }
+
+    /// Without wrapping, fails if the signed cell `target` is negative, as
+    /// counting it down to zero would underflow.
+    fn negative(&mut self, span: Span, target: &str) {
+        if self.options.signed && !self.options.wrapping {
+            let fail = self.fail(InterpreterError::IntegerUnderflow(span));
+            self.push(&format!("if ((scell){target} < 0) {fail}"));
+        }
+    }
+}
+
+/// A C string literal holding `text`.
+fn literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+
+    literal
+}
+
+/// The index of the cell `offset` away from the data pointer.
+fn index(offset: isize) -> String {
+    match offset {
+        0 => "p".to_string(),
+        offset if offset > 0 => format!("p + {offset}"),
+        offset => format!("p - {}", offset.unsigned_abs()),
+    }
+}
+
+/// How far a cell can rise and fall before it leaves its range, for checked
+/// arithmetic.
+fn limits(options: &Options) -> String {
+    let bits = options.cell.bits();
+
+    match options.signed {
+        true => formatdoc! {"
+
+            static uint64_t up(cell c) {{
+                return (uint64_t)INT{bits}_MAX - (uint64_t)(int64_t)(scell)c;
+            }}
+
+            static uint64_t down(cell c) {{
+                return (uint64_t)(int64_t)(scell)c - (uint64_t)INT{bits}_MIN;
+            }}
+        "},
+        false => formatdoc! {"
+
+            static uint64_t up(cell c) {{
+                return (cell)-1 - c;
+            }}
+
+            static uint64_t down(cell c) {{
+                return c;
+            }}
+        "},
+    }
+}
+
+/// The `reach` routine, which grows the tape until cell `i` is on it. Cells
+/// keep their place relative to the data pointer, and new ones are zero.
+fn reach(options: &Options) -> String {
+    let origin = match options.tape {
+        Tape::Bidirectional => "\n    origin += shift;",
+        _ => "",
+    };
+
+    formatdoc! {"
+
+        static void reach(ptrdiff_t i) {{
+            size_t before, after, grown, shift;
+            cell *bigger;
+
+            if (i >= 0 && (size_t)i < size)
+                return;
+            before = i < 0 ? (size_t)-i : 0;
+            after = i < 0 ? 0 : (size_t)i + 1 - size;
+            grown = 2 * (size + before + after);
+            shift = i < 0 ? grown - size : 0;
+            bigger = calloc(grown, sizeof(cell));
+            if (!bigger)
+                fail({no_tape}, 1);
+            memcpy(bigger + shift, tape, size * sizeof(cell));
+            free(tape);
+            tape = bigger;
+            size = grown;
+            p += shift;{origin}
+        }}
+    ",
+        no_tape = literal(&format!("{NO_TAPE}\n")),
+    }
+}
+
+/// The `dump` routine behind `Debug`. It writes `header`, then the cells
+/// around the data pointer, to stderr. Cells are numbered from the one the
+/// data pointer started on.
+fn dump(options: &Options) -> String {
+    let (format, value) = match options.signed {
+        true => ("lld", "(long long)(scell)tape[zero + i]"),
+        false => ("llu", "(unsigned long long)tape[zero + i]"),
+    };
+
+    formatdoc! {"
+
+        static void dump(const char *header) {{
+            ptrdiff_t zero = {origin};
+            ptrdiff_t here = p - zero;
+            ptrdiff_t first = here - {radius}, last = here + {radius}, i;
+
+            if (first < -zero)
+                first = -zero;
+            if (last > (ptrdiff_t)size - 1 - zero)
+                last = (ptrdiff_t)size - 1 - zero;
+            fflush(stdout);
+            fprintf(stderr, \"%s%td:\", header, here);
+            for (i = first; i <= last; i++)
+                fprintf(stderr, i == here ? \" [%{format}]\" : \" %{format}\", {value});
+            fputc('\\n', stderr);
+        }}
+    ",
+        origin = if options.tape == Tape::Bidirectional { "origin" } else { "0" },
+        radius = DUMP_RADIUS,
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
+    let bits = options.cell.bits();
+    let mut writer = Writer::new(options);
+    let mut dumps = false;
+    let mut inputs = false;
+
+    for instruction in program {
+        let span = instruction.span;
+
+        match instruction.node {
+            Instruction::Move(distance) => {
+                writer.push(&match distance >= 0 {
+                    true => format!("p += {distance};"),
+                    false => format!("p -= {};", distance.unsigned_abs()),
+                });
+                writer.reach(span, "p");
+            }
+            Instruction::Add { offset, value } if !options.wrapping => {
+                let target = writer.select(span, offset);
+                writer.checked_add(span, &target, value);
+            }
+            Instruction::Add { offset, value } => {
+                let target = writer.select(span, offset);
+                let magnitude = options.cell.truncate(value.unsigned_abs() as u64);
+
+                writer.push(&match value >= 0 {
+                    true => format!("{target} += {magnitude}u;"),
+                    false => format!("{target} -= {magnitude}u;"),
+                });
+            }
+            Instruction::Clear { offset } => {
+                let target = writer.select(span, offset);
+
+                writer.negative(span, &target);
+                writer.push(&format!("{target} = 0;"));
+            }
+            // Unlike the assembly targets, the whole product is added at
+            // once, after checking it against the room left in the cell.
+            Instruction::MulAdd { offset, factor } if !options.wrapping => {
+                let magnitude = factor.unsigned_abs();
+
+                writer.push("if (tape[p]) {");
+                writer.depth += 1;
+                let target = writer.select(span, offset);
+                writer.negative(span, "tape[p]");
+                let code = match factor >= 0 {
+                    true => format!(
+                        "if ({magnitude}u > up({target}) / tape[p]) {}\n\
+                         {target} += (uint64_t)tape[p] * {magnitude}u;",
+                        writer.fail(InterpreterError::IntegerOverflow(span))
+                    ),
+                    false => format!(
+                        "if ({magnitude}u > down({target}) / tape[p]) {}\n\
+                         {target} -= (uint64_t)tape[p] * {magnitude}u;",
+                        writer.fail(InterpreterError::IntegerUnderflow(span))
+                    ),
+                };
+                writer.push(&code);
+                writer.depth -= 1;
+                writer.push("}");
+            }
+            Instruction::MulAdd { offset, factor } => {
+                writer.push("if (tape[p]) {");
+                writer.depth += 1;
+                let target = writer.select(span, offset);
+                writer.push(&format!(
+                    "{target} += (uint64_t)tape[p] * {}u;",
+                    factor as i64 as u64
+                ));
+                writer.depth -= 1;
+                writer.push("}");
+            }
+            Instruction::Scan(stride) => {
+                writer.push("while (tape[p]) {");
+                writer.depth += 1;
+                writer.push(&match stride >= 0 {
+                    true => format!("p += {stride};"),
+                    false => format!("p -= {};", stride.unsigned_abs()),
+                });
+                writer.reach(span, "p");
+                writer.depth -= 1;
+                writer.push("}");
+            }
+            Instruction::Input { offset } => {
+                let target = writer.select(span, offset);
+                inputs = true;
+                writer.push(&format!("input(&{target});"));
+            }
+            Instruction::Output { offset } => {
+                let target = writer.select(span, offset);
+                writer.push(&format!("putchar((unsigned char){target});"));
+            }
+            Instruction::Debug => {
+                dumps = true;
+                writer.push(&format!("dump({});", literal(&format!("# {span} cell "))));
+            }
+            Instruction::LoopStart(_) => {
+                writer.push("while (tape[p]) {");
+                writer.depth += 1;
+            }
+            Instruction::LoopEnd(_) => {
+                writer.depth -= 1;
+                writer.push("}");
+            }
+        }
+    }
+
+    // A tape that grows starts with as many cells as a fixed one would have,
+    // and at least one. A fixed one only needs its size spelled out for
+    // checks and dumps.
+    let (tape, start) = match options.tape {
+        Tape::Fixed => (
+            formatdoc! {"
+                static cell tape[{memory}];{size}
+            ",
+                memory = options.memory.max(1),
+                size = if options.bounds_check || dumps {
+                    format!("\nstatic const size_t size = {};", options.memory.max(1))
+                } else {
+                    String::new()
+                },
+            },
+            String::new(),
+        ),
+        Tape::Growable | Tape::Bidirectional => {
+            writer.fails = true;
+
+            (
+                formatdoc! {"
+                    static cell *tape;
+                    static size_t size = {memory};{origin}
+                ",
+                    memory = options.memory.max(1),
+                    origin = if options.tape == Tape::Bidirectional {
+                        "\nstatic ptrdiff_t origin;"
+                    } else {
+                        ""
+                    },
+                },
+                formatdoc! {"
+                    tape = calloc(size, sizeof(cell));
+                    if (!tape)
+                        fail({}, 1);
+                ", literal(&format!("{NO_TAPE}\n"))},
+            )
+        }
+    };
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => "",
+        Eof::Zero => "\n    else\n        *c = 0;",
+        Eof::MinusOne => "\n    else\n        *c = (cell)-1;",
+    };
+
+    let mut c = formatdoc! {"
+        /* {description} */
+
+        #include <stddef.h>
+        #include <stdint.h>
+        #include <stdio.h>
+        #include <stdlib.h>
+        #include <string.h>
+
+        typedef uint{bits}_t cell;
+        typedef int{bits}_t scell;
+
+        {tape}static ptrdiff_t p;
+    ",
+        description = options.describe(),
+    };
+
+    if writer.fails {
+        c.push_str(indoc::indoc! {"
+
+            static void fail(const char *message, int status) {
+                fflush(stdout);
+                fputs(message, stderr);
+                exit(status);
+            }
+        "});
+    }
+    if options.tape != Tape::Fixed {
+        c.push_str(&reach(options));
+    }
+    if !options.wrapping {
+        c.push_str(&limits(options));
+    }
+    if dumps {
+        c.push_str(&dump(options));
+    }
+
+    // Output is flushed before reading, so that prompts are visible.
+    if inputs {
+        c.push_str(&formatdoc! {"
+
+            static void input(cell *c) {{
+                int ch;
+
+                fflush(stdout);
+                ch = getchar();
+                if (ch != EOF)
+                    *c = (cell)ch;{on_eof}
+            }}
+        "});
+    }
+
+    c.push_str(&formatdoc! {"
+
+        int main(void) {{
+        {start}{body}    return 0;
+        }}
+    ",
+        start = start
+            .lines()
+            .map(|line| format!("    {line}\n"))
+            .collect::<String>(),
+        body = writer.body,
+    });
+
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cell::CellWidth,
+        compiler::tests::{options, program},
+    };
+    use indoc::indoc;
+    use rstest::*;
+
+    fn generate(instruction: Instruction, options: Options) -> String {
+        codegen(&program(&[instruction]), &options)
+    }
+
+    #[rstest]
+    #[case("plain", r#""plain""#)]
+    #[case(r#"say "hi""#, r#""say \"hi\"""#)]
+    #[case(r"C:\tape", r#""C:\\tape""#)]
+    #[case("Error\n", r#""Error\n""#)]
+    fn test_literal(#[case] text: &str, #[case] expected: &str) {
+        assert_eq!(literal(text), expected);
+    }
+
+    #[rstest]
+    #[case(
+        Instruction::Add { offset: 0, value: 3 },
+        "if (3u > up(tape[p])) fail(\"Error: Integer overflow at 1:5\\n\", 4);\n    tape[p] += 3u;\n"
+    )]
+    #[case(
+        Instruction::Add { offset: 0, value: -3 },
+        "if (3u > down(tape[p])) fail(\"Error: Integer underflow at 1:5\\n\", 5);\n    tape[p] -= 3u;\n"
+    )]
+    fn test_checked_add(#[case] instruction: Instruction, #[case] expected: &str) {
+        let c = generate(instruction, options(CellWidth::Bits8, false));
+
+        assert!(c.contains(expected), "{c}");
+        assert!(c.contains("return (cell)-1 - c;"));
+    }
+
+    #[rstest]
+    #[case(
+        Options {
+            bounds_check: true,
+            wrapping: true,
+            ..options(CellWidth::Bits8, false)
+        },
+        "if ((size_t)(p + 1) >= size) \
+         fail(\"Error: Pointer points to out of bounds memory at 1:5\\n\", 3);\n    \
+         tape[p + 1] = 0;\n"
+    )]
+    #[case(
+        options(CellWidth::Bits16, true),
+        "if ((scell)tape[p + 1] < 0) fail(\"Error: Integer underflow at 1:5\\n\", 5);\n    \
+         tape[p + 1] = 0;\n"
+    )]
+    #[case(
+        options(CellWidth::Bits16, false),
+        "int main(void) {\n    tape[p + 1] = 0;\n"
+    )]
+    fn test_clear(#[case] options: Options, #[case] expected: &str) {
+        let c = generate(Instruction::Clear { offset: 1 }, options);
+
+        assert!(c.contains(expected), "{c}");
+    }
+
+    #[rstest]
+    #[case(2, indoc! {r#"
+            if (tape[p]) {
+                if (2u > up(tape[p + 1]) / tape[p]) fail("Error: Integer overflow at 1:5\n", 4);
+                tape[p + 1] += (uint64_t)tape[p] * 2u;
+            }
+    "#})]
+    #[case(-3, indoc! {r#"
+            if (tape[p]) {
+                if (3u > down(tape[p + 1]) / tape[p]) fail("Error: Integer underflow at 1:5\n", 5);
+                tape[p + 1] -= (uint64_t)tape[p] * 3u;
+            }
+    "#})]
+    fn test_checked_mul_add(#[case] factor: isize, #[case] expected: &str) {
+        let c = generate(
+            Instruction::MulAdd { offset: 1, factor },
+            options(CellWidth::Bits32, false),
+        );
+
+        assert!(c.contains(&indent(expected)), "{c}");
+    }
+
+    #[test]
+    fn test_signed_mul_add() {
+        let c = generate(
+            Instruction::MulAdd {
+                offset: 1,
+                factor: 1,
+            },
+            options(CellWidth::Bits8, true),
+        );
+
+        assert!(c.contains(
+            "if (tape[p]) {\n        \
+             if ((scell)tape[p] < 0) fail(\"Error: Integer underflow at 1:5\\n\", 5);\n"
+        ));
+        assert!(c.contains("return (uint64_t)INT8_MAX - (uint64_t)(int64_t)(scell)c;"));
+    }
+
+    #[test]
+    fn test_wrapping_mul_add() {
+        let options = Options {
+            wrapping: true,
+            ..options(CellWidth::Bits64, false)
+        };
+        let c = generate(
+            Instruction::MulAdd {
+                offset: -1,
+                factor: -2,
+            },
+            options,
+        );
+
+        assert!(c.contains(
+            "if (tape[p]) {\n        \
+             tape[p - 1] += (uint64_t)tape[p] * 18446744073709551614u;\n    }\n"
+        ));
+        assert!(!c.contains("up("));
+    }
+
+    #[rstest]
+    #[case(Tape::Fixed, 2, "while (tape[p]) {\n        p += 2;\n    }\n")]
+    #[case(
+        Tape::Growable,
+        -2,
+        "while (tape[p]) {\n        p -= 2;\n        \
+         if (p < 0) fail(\"Error: Pointer points to out of bounds memory at 1:5\\n\", 3);\n        \
+         reach(p);\n    }\n"
+    )]
+    #[case(
+        Tape::Bidirectional,
+        -1,
+        "while (tape[p]) {\n        p -= 1;\n        reach(p);\n    }\n"
+    )]
+    fn test_scan(#[case] tape: Tape, #[case] stride: isize, #[case] expected: &str) {
+        let options = Options {
+            tape,
+            wrapping: true,
+            ..options(CellWidth::Bits8, false)
+        };
+        let c = generate(Instruction::Scan(stride), options);
+
+        assert!(c.contains(expected), "{c}");
+    }
+
+    #[rstest]
+    #[case(Tape::Growable, false)]
+    #[case(Tape::Bidirectional, true)]
+    fn test_reach(#[case] tape: Tape, #[case] moves_origin: bool) {
+        let options = Options {
+            tape,
+            memory: 0,
+            ..options(CellWidth::Bits8, false)
+        };
+        let routine = reach(&options);
+
+        assert!(routine.contains("if (i >= 0 && (size_t)i < size)\n        return;"));
+        assert!(routine.contains(&format!("fail(\"{NO_TAPE}\\n\", 1);")));
+        assert!(routine.contains("p += shift;"));
+        assert_eq!(routine.contains("origin += shift;"), moves_origin);
+
+        // A tape that grows starts with at least one cell.
+        let c = generate(Instruction::Output { offset: 1 }, options);
+        assert!(c.contains("static size_t size = 1;"));
+        assert!(c.contains("reach(p + 1);\n    putchar((unsigned char)tape[p + 1]);"));
+    }
+
+    /// `code` indented as one level of `main`'s body.
+    fn indent(code: &str) -> String {
+        code.lines().map(|line| format!("    {line}\n")).collect()
+    }
+}
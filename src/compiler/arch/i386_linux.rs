@@ -0,0 +1,956 @@
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::{checked_steps, Options, GUARD, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
+use indoc::{formatdoc, indoc};
+
+/// 1 GiB, a third of the address space a process has, which leaves room for
+/// the rest of the program.
+const RESERVED: usize = 1 << 30;
+
+/// The checks described in [`super`], which jump to stubs that load the
+/// message into ecx and edx and the status into edi.
+struct Traps {
+    options: Options,
+    stubs: String,
+    messages: String,
+    count: usize,
+}
+
+impl Traps {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            stubs: String::new(),
+            messages: String::new(),
+            count: 0,
+        }
+    }
+
+    /// The size of the tape in bytes, to compare offsets against. Even a
+    /// reserved region fits an immediate here.
+    fn limit(&self) -> usize {
+        self.options.layout(RESERVED).0
+    }
+
+    /// Checks the data pointer, whose byte offset is in esi.
+    fn pointer(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "    cmp        esi, {}\n    jae        {}\n",
+                self.limit(),
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    /// Checks the cell `offset` away from the data pointer, leaving its byte
+    /// offset in ebp.
+    fn offset(&mut self, span: Span, offset: isize) -> String {
+        match offset * self.options.cell.bytes() as isize {
+            displacement if self.options.bounds_check && displacement != 0 => format!(
+                "lea        ebp, [esi + {displacement}]\n    \
+                 cmp        ebp, {}\n    \
+                 jae        {}\n    ",
+                self.limit(),
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            _ => String::new(),
+        }
+    }
+
+    /// Checks that `repne scas` found a zero cell before the edge of the tape.
+    fn scan(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "jne        {}\n    ",
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn add(&mut self, error: InterpreterError) -> String {
+        let n = self.count;
+        let message = format!("Error: {error}");
+
+        self.count += 1;
+        self.messages
+            .push_str(&format!("    E{n}          db \"{message}\", 10\n"));
+        self.stubs.push_str(&formatdoc! {"
+            T{n}:
+                mov        ecx, E{n}
+                mov        edx, {length}
+                mov        edi, {status}
+                jmp        trap
+        ", length = message.len() + 1, status = error.status()});
+
+        format!("T{n}")
+    }
+
+    /// Adds `value` to the cell at `target`, checked with `jc`, or `jo` for
+    /// signed cells.
+    fn checked_add(&mut self, span: Span, target: &str, value: isize) -> String {
+        let (size, _) = operands(self.options.cell);
+        let (op, trap) = match value >= 0 {
+            true => ("add", self.add(InterpreterError::IntegerOverflow(span))),
+            false => ("sub", self.add(InterpreterError::IntegerUnderflow(span))),
+        };
+        let jump = if self.options.signed { "jo" } else { "jc" };
+
+        match checked_steps(value.unsigned_abs() as u64, &self.options) {
+            Some(steps) => steps
+                .into_iter()
+                .map(|step| match self.options.cell {
+                    // The flags of the high word are those of the whole cell.
+                    CellWidth::Bits64 => {
+                        format!("{}\n    {jump}         {trap}", add_words(op, target, step))
+                    }
+                    _ => {
+                        format!("{op}        {size} [{target}], {step}\n    {jump}         {trap}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n    "),
+            None => format!("jmp        {trap}"),
+        }
+    }
+
+    /// Checks that the signed cell at `target` is not negative.
+    fn negative(&mut self, span: Span, target: &str) -> String {
+        // The sign of a 64-bit cell is in its high word.
+        let sign = match self.options.cell {
+            CellWidth::Bits64 => format!("dword [{target} + 4]"),
+            cell => format!("{} [{target}]", operands(cell).0),
+        };
+
+        match self.options.signed && !self.options.wrapping {
+            true => format!(
+                "cmp        {sign}, 0\n    jl         {}\n    ",
+                self.add(InterpreterError::IntegerUnderflow(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn emit(&self) -> String {
+        if self.count == 0 {
+            return String::new();
+        }
+
+        formatdoc! {"
+
+            {stubs}
+            trap:
+                mov        eax, 4
+                mov        ebx, 2
+                int        0x80
+                mov        eax, 1
+                mov        ebx, edi
+                int        0x80
+
+            section .data
+            {messages}
+            section .text
+        ", stubs = self.stubs, messages = self.messages}
+    }
+}
+
+/// Loads the cell whose index is in esi into edx:eax, extended to 64 bits.
+/// Cell 0 is `origin` bytes into the tape at ebx.
+fn load_dumped(cell: CellWidth, signed: bool, origin: usize) -> String {
+    let load = match (cell, signed) {
+        (CellWidth::Bits8, false) => format!("movzx      eax, byte [ebx + esi + {origin}]"),
+        (CellWidth::Bits8, true) => format!("movsx      eax, byte [ebx + esi + {origin}]"),
+        (CellWidth::Bits16, false) => format!("movzx      eax, word [ebx + esi*2 + {origin}]"),
+        (CellWidth::Bits16, true) => format!("movsx      eax, word [ebx + esi*2 + {origin}]"),
+        (CellWidth::Bits32, _) => format!("mov        eax, dword [ebx + esi*4 + {origin}]"),
+        (CellWidth::Bits64, _) => {
+            return formatdoc! {"
+                mov        eax, dword [ebx + esi*8 + {origin}]
+                    mov        edx, dword [ebx + esi*8 + {high}]",
+                high = origin + 4,
+            }
+        }
+    };
+    let extend = if signed { "cdq" } else { "xor        edx, edx" };
+
+    format!("{load}\n    {extend}")
+}
+
+/// The `dump` routine, with the data it needs. It takes the header at edx,
+/// ecx bytes long, and prints every cell as 64 bits in edx:eax.
+fn dump(options: &Options, headers: &str) -> String {
+    let bytes = options.cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    formatdoc! {"
+
+        dump:
+            pushad
+            mov        ebp, esi
+            mov        esi, edx
+            mov        edi, dumpbuf
+            rep movsb
+            sub        ebp, {origin}
+            sar        ebp, {shift}
+            mov        eax, ebp
+            cdq
+            call       dump_signed
+            mov        byte [edi], ':'
+            inc        edi
+            lea        esi, [ebp - {radius}]
+            mov        eax, {first}
+            cmp        esi, eax
+            jge        dump_end
+            mov        esi, eax
+        dump_end:
+            lea        eax, [ebp + {radius}]
+            mov        ecx, {last}
+            cmp        eax, ecx
+            jle        dump_last
+            mov        eax, ecx
+        dump_last:
+            push       eax
+        dump_cell:
+            mov        byte [edi], ' '
+            inc        edi
+            cmp        esi, ebp
+            jne        dump_value
+            mov        byte [edi], '['
+            inc        edi
+        dump_value:
+            {load}
+            call       {number}
+            cmp        esi, ebp
+            jne        dump_next
+            mov        byte [edi], ']'
+            inc        edi
+        dump_next:
+            inc        esi
+            cmp        esi, [esp]
+            jle        dump_cell
+            pop        eax
+            mov        byte [edi], 10
+            inc        edi
+            mov        edx, edi
+            sub        edx, dumpbuf
+            mov        ecx, dumpbuf
+            mov        ebx, 2
+            mov        eax, 4
+            int        0x80
+            popad
+            ret
+
+        ; Appends edx:eax in decimal at edi, read as signed or unsigned. The
+        ; high word is divided first, and its remainder carried into the low.
+        dump_signed:
+            test       edx, edx
+            jns        dump_number
+            mov        byte [edi], '-'
+            inc        edi
+            neg        eax
+            adc        edx, 0
+            neg        edx
+        dump_number:
+            push       ebx
+            push       esi
+            mov        esi, digits + 20
+            mov        ebx, 10
+            mov        ecx, edx
+        dump_digit:
+            xchg       eax, ecx
+            xor        edx, edx
+            div        ebx
+            xchg       eax, ecx
+            div        ebx
+            add        dl, '0'
+            dec        esi
+            mov        [esi], dl
+            mov        edx, eax
+            or         edx, ecx
+            jnz        dump_digit
+            mov        ecx, digits + 20
+            sub        ecx, esi
+            rep movsb
+            pop        esi
+            pop        ebx
+            ret
+
+        section .data
+        {headers}
+        section .bss
+            dumpbuf     resb 512
+            digits      resb 20
+    ",
+        shift = bytes.trailing_zeros(),
+        radius = DUMP_RADIUS,
+        first = -((origin / bytes) as isize),
+        last = (tape_size - origin) / bytes - 1,
+        load = load_dumped(options.cell, options.signed, origin),
+        number = if options.signed { "dump_signed" } else { "dump_number" },
+    }
+}
+
+/// Operand size keyword and matching scratch register for a cell. A 64-bit
+/// cell is handled as two dwords, low word first.
+fn operands(cell: CellWidth) -> (&'static str, &'static str) {
+    match cell {
+        CellWidth::Bits8 => ("byte", "al"),
+        CellWidth::Bits16 => ("word", "ax"),
+        CellWidth::Bits32 | CellWidth::Bits64 => ("dword", "eax"),
+    }
+}
+
+/// Adds or subtracts the 64-bit `value` to the two words at `target`,
+/// carrying into the high word.
+fn add_words(op: &str, target: &str, value: u64) -> String {
+    formatdoc! {"
+        {op}        dword [{target}], {low}
+            {carry}        dword [{target} + 4], {high}",
+        low = value & 0xffff_ffff,
+        carry = if op == "add" { "adc" } else { "sbb" },
+        high = value >> 32,
+    }
+}
+
+/// Sets the cell at `target` to `value`, in both words of a 64-bit cell.
+fn set(cell: CellWidth, target: &str, value: isize) -> String {
+    match cell {
+        CellWidth::Bits64 => formatdoc! {"
+            mov        dword [{target}], {value}
+                mov        dword [{target} + 4], {high}",
+            high = if value < 0 { -1 } else { 0 },
+        },
+        _ => format!("mov        {} [{target}], {value}", operands(cell).0),
+    }
+}
+
+/// The string instruction that compares `eax` against a cell at `edi`, if
+/// there is one for its width.
+fn scas(cell: CellWidth) -> Option<&'static str> {
+    match cell {
+        CellWidth::Bits8 => Some("scasb"),
+        CellWidth::Bits16 => Some("scasw"),
+        CellWidth::Bits32 => Some("scasd"),
+        CellWidth::Bits64 => None,
+    }
+}
+
+/// Loads the current cell into eax, zero-extended, with the high word of a
+/// 64-bit cell in edx.
+fn load_counter(cell: CellWidth) -> &'static str {
+    match cell {
+        CellWidth::Bits8 => "movzx      eax, byte [ebx + esi]",
+        CellWidth::Bits16 => "movzx      eax, word [ebx + esi]",
+        CellWidth::Bits32 => "mov        eax, dword [ebx + esi]",
+        CellWidth::Bits64 => {
+            "mov        eax, dword [ebx + esi]\n    mov        edx, dword [ebx + esi + 4]"
+        }
+    }
+}
+
+/// Sets the zero flag if the counter loaded by [`load_counter`] is zero.
+fn test_counter(cell: CellWidth) -> &'static str {
+    match cell {
+        CellWidth::Bits64 => "mov        ecx, eax\n    or         ecx, edx",
+        _ => "test       eax, eax",
+    }
+}
+
+/// Sets the zero flag if the current cell is zero.
+fn test_zero(cell: CellWidth) -> String {
+    match cell {
+        CellWidth::Bits64 => {
+            "mov        eax, dword [ebx + esi]\n    or         eax, dword [ebx + esi + 4]".into()
+        }
+        _ => format!("cmp        {} [ebx + esi], 0", operands(cell).0),
+    }
+}
+
+/// Memory operand for the cell at `offset` from the data pointer.
+fn cell_ref(offset: isize, bytes: usize) -> String {
+    match offset * bytes as isize {
+        0 => "ebx + esi".to_string(),
+        displacement if displacement > 0 => format!("ebx + esi + {displacement}"),
+        displacement => format!("ebx + esi - {}", -displacement),
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
+    let cell = options.cell;
+    let (size, scratch) = operands(cell);
+    let bytes = cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => String::new(),
+        Eof::Zero => format!("{}\n    ", set(cell, "ebx + ebp", 0)),
+        Eof::MinusOne => format!("{}\n    ", set(cell, "ebx + ebp", -1)),
+    };
+    let store = match cell {
+        CellWidth::Bits64 => {
+            "mov        dword [ebx + ebp], eax\n    mov        dword [ebx + ebp + 4], 0".into()
+        }
+        _ => format!("mov        {size} [ebx + ebp], {scratch}"),
+    };
+
+    // ebx holds the address of the tape.
+    let (tape, start) = match options.tape {
+        Tape::Fixed => (
+            format!("    tape        resb {tape_size}\n"),
+            "mov        ebx, tape\n".to_string(),
+        ),
+        Tape::Growable | Tape::Bidirectional => (
+            String::new(),
+            formatdoc! {"
+                mov        eax, 192
+                    xor        ebx, ebx
+                    mov        ecx, {mapped}
+                    xor        edx, edx
+                    mov        esi, 0x4022
+                    mov        edi, -1
+                    xor        ebp, ebp
+                    int        0x80
+                    cmp        eax, -4096
+                    ja         no_tape
+                    lea        ebx, [eax + {GUARD}]
+                    mov        ecx, {tape_size}
+                    mov        edx, 3
+                    mov        eax, 125
+                    int        0x80
+                    test       eax, eax
+                    jnz        no_tape
+                ",
+                mapped = tape_size + 2 * GUARD,
+            },
+        ),
+    };
+
+    // `read` and `write` act on the cell whose byte offset is in ebp. The
+    // system calls take their first argument in ebx, so the tape is kept
+    // aside meanwhile.
+    let mut asm: String = formatdoc! {"
+        ; {description}
+
+        section .bss
+        {tape}    inbuf       resb 1
+
+        section .text
+        global _start
+
+        write:
+            push       ebx
+            lea        ecx, [ebx + ebp]
+            mov        ebx, 1
+            mov        edx, 1
+            mov        eax, 4
+            int        0x80
+            pop        ebx
+            ret
+
+        read:
+            push       ebx
+            mov        ebx, 0
+            mov        ecx, inbuf
+            mov        edx, 1
+            mov        eax, 3
+            int        0x80
+            pop        ebx
+            cmp        eax, 1
+            jne        read_eof
+            movzx      eax, byte [inbuf]
+            {store}
+            ret
+        read_eof:
+            {on_eof}ret
+
+        _start:
+            {start}    mov        esi, {origin}
+    ",
+        description = options.describe(),
+    }
+    .to_string();
+
+    // Points ebp at the cell `offset` away from the data pointer.
+    let select = |offset: isize| match offset * bytes as isize {
+        0 => "mov        ebp, esi".to_string(),
+        displacement => format!("lea        ebp, [esi + {displacement}]"),
+    };
+
+    // The header of each `Debug` dump, in the data section.
+    let mut headers = String::new();
+    let mut traps = Traps::new(options);
+
+    for (i, instruction) in program.iter().enumerate() {
+        let span = instruction.span;
+
+        asm.push_str(&match instruction.node {
+            Instruction::Move(distance) if distance >= 0 => formatdoc! {"
+            ;
+                add        esi, {offset}
+            {check}",
+                offset = distance as usize * bytes,
+                check = traps.pointer(span),
+            },
+            Instruction::Move(distance) => formatdoc! {"
+            ;
+                sub        esi, {offset}
+            {check}",
+                offset = distance.unsigned_abs() * bytes,
+                check = traps.pointer(span),
+            },
+            Instruction::Add { offset, value } if !options.wrapping => formatdoc! {"
+            ;
+                {check}{add}
+            ",
+                check = traps.offset(span, offset),
+                add = traps.checked_add(span, &cell_ref(offset, bytes), value),
+            },
+            Instruction::Add { offset, value } => {
+                let op = if value >= 0 { "add" } else { "sub" };
+                let value = cell.truncate(value.unsigned_abs() as u64);
+                let target = cell_ref(offset, bytes);
+                let check = traps.offset(span, offset);
+
+                // A 64-bit cell is added a word at a time.
+                if cell == CellWidth::Bits64 {
+                    formatdoc! {"
+                    ;
+                        {check}{add}
+                    ", add = add_words(op, &target, value)}
+                } else {
+                    formatdoc! {"
+                    ;
+                        {check}{op}        {size} [{target}], {value}
+                    "}
+                }
+            }
+            Instruction::Clear { offset } => {
+                let target = cell_ref(offset, bytes);
+
+                formatdoc! {"
+                ;
+                    {check}{negative}{clear}
+                ",
+                    check = traps.offset(span, offset),
+                    negative = traps.negative(span, &target),
+                    clear = set(cell, &target, 0),
+                }
+            }
+            // The count is in ecx, with the high word of a 64-bit cell in edx.
+            Instruction::MulAdd { offset, factor } if !options.wrapping => formatdoc! {"
+            ;
+                {load}
+                {test}
+                jz         M{i}
+                {check}{negative}mov        ecx, eax
+            M{i}_loop:
+                {add}
+                {count}
+                jnz        M{i}_loop
+            M{i}:
+            ",
+                load = load_counter(cell),
+                test = test_counter(cell),
+                check = traps.offset(span, offset),
+                negative = traps.negative(span, "ebx + esi"),
+                add = traps.checked_add(span, &cell_ref(offset, bytes), factor),
+                count = match cell {
+                    CellWidth::Bits64 => {
+                        "sub        ecx, 1\n    sbb        edx, 0\n    mov        eax, ecx\n    or         eax, edx"
+                    }
+                    _ => "dec        ecx",
+                },
+            },
+            // The low 64 bits of a 64 by 64-bit product are the product of
+            // the low words, plus the cross products in the high word.
+            Instruction::MulAdd { offset, factor } if cell == CellWidth::Bits64 => {
+                let factor = factor as i64 as u64;
+
+                formatdoc! {"
+                ;
+                    {load}
+                    {test}
+                    jz         M{i}
+                    {check}imul       ecx, edx, {low}
+                    imul       edx, eax, {high}
+                    add        ecx, edx
+                    mov        edx, {low}
+                    mul        edx
+                    add        edx, ecx
+                    add        dword [{target}], eax
+                    adc        dword [{target} + 4], edx
+                M{i}:
+                ",
+                    load = load_counter(cell),
+                    test = test_counter(cell),
+                    check = traps.offset(span, offset),
+                    low = factor & 0xffff_ffff,
+                    high = factor >> 32,
+                    target = cell_ref(offset, bytes),
+                }
+            }
+            Instruction::MulAdd { offset, factor } => {
+                // `imul` serves unsigned cells too.
+                let multiply = match factor {
+                    1 => String::new(),
+                    _ => format!("imul       eax, eax, {}\n    ", factor as i32),
+                };
+
+                formatdoc! {"
+                ;
+                    {load}
+                    test       eax, eax
+                    jz         M{i}
+                    {check}{multiply}add        {size} [{target}], {scratch}
+                M{i}:
+                ",
+                    check = traps.offset(span, offset),
+                    load = load_counter(cell),
+                    target = cell_ref(offset, bytes),
+                }
+            }
+            // `repne scas` stops just past the first zero cell, or at the
+            // edge of the tape if there is none. There is no 64-bit form.
+            Instruction::Scan(1) if scas(cell).is_some() => formatdoc! {"
+            ;
+                lea        edi, [ebx + esi]
+                mov        ecx, {tape_size}
+                sub        ecx, esi
+                shr        ecx, {shift}
+                xor        eax, eax
+                repne      {scas}
+                {not_found}lea        esi, [edi - {bytes}]
+                sub        esi, ebx
+            ",
+                tape_size = tape_size,
+                shift = bytes.trailing_zeros(),
+                scas = scas(cell).unwrap(),
+                not_found = traps.scan(span),
+            },
+            Instruction::Scan(-1) if scas(cell).is_some() => formatdoc! {"
+            ;
+                lea        edi, [ebx + esi]
+                lea        ecx, [esi + {bytes}]
+                shr        ecx, {shift}
+                xor        eax, eax
+                std
+                repne      {scas}
+                cld
+                {not_found}lea        esi, [edi + {bytes}]
+                sub        esi, ebx
+            ",
+                shift = bytes.trailing_zeros(),
+                scas = scas(cell).unwrap(),
+                not_found = traps.scan(span),
+            },
+            Instruction::Scan(stride) => formatdoc! {"
+            S{i}:
+                {test}
+                je         S{i}_end
+                {check}{op}        esi, {distance}
+                jmp        S{i}
+            S{i}_end:
+            ",
+                test = test_zero(cell),
+                check = traps.offset(span, stride),
+                op = if stride > 0 { "add" } else { "sub" },
+                distance = stride.unsigned_abs() * bytes,
+            },
+            Instruction::Input { offset } => formatdoc! {"
+            ;
+                {check}{select}
+                call       read
+            ", check = traps.offset(span, offset), select = select(offset)},
+            Instruction::Output { offset } => formatdoc! {"
+            ;
+                {check}{select}
+                call       write
+            ", check = traps.offset(span, offset), select = select(offset)},
+            Instruction::LoopEnd(j) => formatdoc! {"
+            ;
+                {test}
+                jne        L{j}
+            L{i}:
+            ", test = test_zero(cell)},
+            Instruction::Debug => {
+                let header = format!("# {} cell ", instruction.span);
+                headers.push_str(&format!("    D{i}          db \"{header}\"\n"));
+
+                formatdoc! {"
+                ;
+                    mov        edx, D{i}
+                    mov        ecx, {length}
+                    call       dump
+                ", length = header.len()}
+            }
+            Instruction::LoopStart(j) => formatdoc! {"
+            L{i}:
+                {test}
+                je         L{j}
+            ", test = test_zero(cell)},
+        });
+    }
+
+    asm.push_str(indoc! {"
+        exit:
+            mov        eax, 1
+            mov        ebx, 0
+            int        0x80
+    "});
+
+    if options.tape != Tape::Fixed {
+        asm.push_str(&formatdoc! {"
+
+            no_tape:
+                mov        eax, 4
+                mov        ebx, 2
+                mov        ecx, no_tape_message
+                mov        edx, {length}
+                int        0x80
+                mov        eax, 1
+                mov        ebx, 1
+                int        0x80
+
+            section .data
+                no_tape_message db \"{NO_TAPE}\", 10
+            section .text
+        ", length = NO_TAPE.len() + 1});
+    }
+
+    asm.push_str(&traps.emit());
+    if !headers.is_empty() {
+        asm.push_str(&dump(options, &headers));
+    }
+
+    asm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::{options, program};
+    use rstest::*;
+
+    #[rstest]
+    #[case(
+        1,
+        "repne      scasb\n    jne        T0\n    lea        esi, [edi - 1]"
+    )]
+    #[case(-1, "cld\n    jne        T0\n    lea        esi, [edi + 1]")]
+    fn test_scan_check(#[case] stride: isize, #[case] expected: &str) {
+        let options = Options {
+            wrapping: true,
+            bounds_check: true,
+            ..options(CellWidth::Bits8, false)
+        };
+        let asm = codegen(&program(&[Instruction::Scan(stride)]), &options);
+
+        // `repne scas` runs out at the edge of the tape without finding a zero.
+        assert!(asm.contains(expected), "{asm}");
+    }
+
+    #[rstest]
+    #[case(
+        "add",
+        0x1_0000_0002,
+        "add        dword [ebx + esi], 2\n    adc        dword [ebx + esi + 4], 1"
+    )]
+    #[case(
+        "sub",
+        u64::MAX,
+        "sub        dword [ebx + esi], 4294967295\n    sbb        dword [ebx + esi + 4], 4294967295"
+    )]
+    #[case(
+        "add",
+        1,
+        "add        dword [ebx + esi], 1\n    adc        dword [ebx + esi + 4], 0"
+    )]
+    fn test_add_words(#[case] op: &str, #[case] value: u64, #[case] expected: &str) {
+        assert_eq!(add_words(op, "ebx + esi", value), expected);
+    }
+
+    #[rstest]
+    #[case(
+        CellWidth::Bits64,
+        -1,
+        "mov        dword [ebx + ebp], -1\n    mov        dword [ebx + ebp + 4], -1"
+    )]
+    #[case(
+        CellWidth::Bits64,
+        0,
+        "mov        dword [ebx + ebp], 0\n    mov        dword [ebx + ebp + 4], 0"
+    )]
+    #[case(CellWidth::Bits16, -1, "mov        word [ebx + ebp], -1")]
+    fn test_set(#[case] cell: CellWidth, #[case] value: isize, #[case] expected: &str) {
+        assert_eq!(set(cell, "ebx + ebp", value), expected);
+    }
+
+    #[test]
+    fn test_add_64() {
+        let options = Options {
+            wrapping: true,
+            ..options(CellWidth::Bits64, false)
+        };
+        let asm = codegen(
+            &program(&[Instruction::Add {
+                offset: 1,
+                value: -1,
+            }]),
+            &options,
+        );
+
+        assert!(asm.contains(
+            "sub        dword [ebx + esi + 8], 1\n    sbb        dword [ebx + esi + 8 + 4], 0"
+        ));
+    }
+
+    #[test]
+    fn test_mul_add_64() {
+        let options = Options {
+            wrapping: true,
+            ..options(CellWidth::Bits64, false)
+        };
+        let asm = codegen(
+            &program(&[Instruction::MulAdd {
+                offset: 1,
+                factor: -2,
+            }]),
+            &options,
+        );
+
+        assert!(asm.contains(indoc! {"
+            mov        eax, dword [ebx + esi]
+                mov        edx, dword [ebx + esi + 4]
+                mov        ecx, eax
+                or         ecx, edx
+                jz         M0
+                imul       ecx, edx, 4294967294
+                imul       edx, eax, 4294967295
+                add        ecx, edx
+                mov        edx, 4294967294
+                mul        edx
+                add        edx, ecx
+                add        dword [ebx + esi + 8], eax
+                adc        dword [ebx + esi + 8 + 4], edx
+            M0:
+        "}));
+    }
+
+    #[rstest]
+    #[case(false, "call       dump_number")]
+    #[case(true, "call       dump_signed")]
+    fn test_dump_64(#[case] signed: bool, #[case] number: &str) {
+        let asm = codegen(
+            &program(&[Instruction::Debug]),
+            &options(CellWidth::Bits64, signed),
+        );
+
+        assert!(asm.contains(&format!(
+            "dump_value:\n    {}",
+            load_dumped(CellWidth::Bits64, signed, 0)
+        )));
+        assert!(asm.contains(number));
+        // The high word is divided first, and its remainder carried into the low.
+        assert!(asm.contains(indoc! {"
+            dump_digit:
+                xchg       eax, ecx
+                xor        edx, edx
+                div        ebx
+                xchg       eax, ecx
+                div        ebx
+        "}));
+        assert!(asm.contains("neg        eax\n    adc        edx, 0\n    neg        edx"));
+    }
+
+    #[test]
+    fn test_growable_tape() {
+        let options = Options {
+            tape: Tape::Growable,
+            ..options(CellWidth::Bits8, false)
+        };
+        let asm = codegen(&[], &options);
+
+        assert!(asm.contains(&formatdoc! {"
+            mov        eax, 192
+                xor        ebx, ebx
+                mov        ecx, {mapped}
+                xor        edx, edx
+                mov        esi, 0x4022
+                mov        edi, -1
+                xor        ebp, ebp
+                int        0x80
+                cmp        eax, -4096
+                ja         no_tape
+                lea        ebx, [eax + {GUARD}]
+                mov        ecx, {RESERVED}
+                mov        edx, 3
+                mov        eax, 125
+                int        0x80
+                test       eax, eax
+                jnz        no_tape
+                mov        esi, 0
+        ", mapped = RESERVED + 2 * GUARD}));
+        assert!(asm.contains(&format!("no_tape_message db \"{NO_TAPE}\", 10")));
+        assert!(!asm.contains("tape        resb"));
+    }
+
+    #[test]
+    fn test_fixed_tape() {
+        let asm = codegen(&[], &options(CellWidth::Bits16, false));
+
+        assert!(asm.contains("tape        resb 60000\n"));
+        assert!(!asm.contains("mov        eax, 192"));
+        assert!(!asm.contains("no_tape"));
+    }
+
+    #[rstest]
+    #[case(
+        CellWidth::Bits8,
+        false,
+        1,
+        "add        byte [ebx + esi], 1\n    jc         T0"
+    )]
+    #[case(CellWidth::Bits16, false, -1, "sub        word [ebx + esi], 1\n    jc         T0")]
+    #[case(
+        CellWidth::Bits32,
+        true,
+        1,
+        "add        dword [ebx + esi], 1\n    jo         T0"
+    )]
+    #[case(
+        CellWidth::Bits64,
+        true,
+        -1,
+        "sub        dword [ebx + esi], 1\n    sbb        dword [ebx + esi + 4], 0\n    jo         T0"
+    )]
+    fn test_checked_add(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] value: isize,
+        #[case] expected: &str,
+    ) {
+        let mut traps = Traps::new(&options(cell, signed));
+
+        assert_eq!(
+            traps.checked_add(Span::default(), "ebx + esi", value),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_negative_64() {
+        let mut traps = Traps::new(&options(CellWidth::Bits64, true));
+
+        // The sign is in the high word.
+        assert_eq!(
+            traps.negative(Span::default(), "ebx + esi"),
+            "cmp        dword [ebx + esi + 4], 0\n    jl         T0\n    "
+        );
+    }
+}
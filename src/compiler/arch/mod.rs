@@ -1,18 +1,88 @@
+//! Code generation for each target.
+//!
+//! Every target turns [`Instruction`](crate::ir::Instruction)s into code that
+//! behaves like the interpreter under the same [`Options`](super::Options).
+//! The checks that the options ask for take the same shape on each of them:
+//!
+//! - A check comes right before the memory access or the add it guards. When
+//!   it fails, it jumps to a stub, out of the way of the code that runs, which
+//!   picks the message and status of an
+//!   [`InterpreterError`](crate::interpreter::InterpreterError) and goes on to
+//!   a routine shared by every stub. That routine writes `Error: {message}` to
+//!   stderr and exits with the status.
+//! - The data pointer is a byte offset into the tape, compared unsigned
+//!   against the size of the tape. Below the tape, it wraps around to a huge
+//!   offset, so one comparison catches both edges.
+//! - An add that could take a cell out of its range is split by
+//!   `checked_steps` into steps that each carry or overflow exactly when it
+//!   does.
+//! - A multiply loop leaves its target alone when the counter is zero, as the
+//!   original loop would not have touched it.
+//! - Without wrapping, a multiply loop adds its factor once per iteration of
+//!   the original loop, so that each add is checked like any other. A signed
+//!   counter below zero underflows straight away, as counting it down to zero
+//!   would.
+//! - With wrapping, only the low bits of a product matter, so one 64-bit
+//!   multiply is right for every cell width.
+//!
+//! The assembly targets gather their checks and stubs in a `Traps` struct.
+//! Its `add` queues the stub for an error and returns the stub's label, and
+//! `emit` writes the stubs and their routine after the program, or nothing
+//! if no check could fail.
+//!
+//! Each `Debug` instruction calls a `dump` routine, which writes the header
+//! of the instruction and then the cells around the data pointer to stderr in
+//! one write, as the interpreter's `#` does. Cells are numbered from the one
+//! the data pointer started on.
+//!
+//! A tape that grows is a region of `RESERVED` bytes of address space,
+//! between two guard regions of `GUARD` bytes. The kernel fills it with
+//! zeroed pages as they are touched, so only the pages in use take memory.
+//! Each target picks `RESERVED` to fit its address space.
+
 use anyhow::anyhow;
+#[cfg(feature = "cli")]
 use clap::{builder::PossibleValue, ValueEnum};
 
 pub mod aarch32_linux;
+pub mod aarch64_linux;
+pub mod c;
+pub mod i386_linux;
+pub mod riscv64_linux;
 pub mod x86_64_linux;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Target {
     Aarch32Linux,
+    Aarch64Linux,
+    /// Portable C, for the system's C compiler.
+    C,
+    I386Linux,
+    Riscv64Linux,
     X86_64Linux,
 }
 
+impl Target {
+    /// The extension of the generated source.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Target::C => "c",
+            _ => "s",
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
 impl ValueEnum for Target {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Aarch32Linux, Self::X86_64Linux]
+        &[
+            Self::Aarch32Linux,
+            Self::Aarch64Linux,
+            Self::C,
+            Self::I386Linux,
+            Self::Riscv64Linux,
+            Self::X86_64Linux,
+        ]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
@@ -24,6 +94,10 @@ impl From<Target> for &str {
     fn from(value: Target) -> Self {
         match value {
             Target::Aarch32Linux => "aarch32-linux",
+            Target::Aarch64Linux => "aarch64-linux",
+            Target::C => "c",
+            Target::I386Linux => "i386-linux",
+            Target::Riscv64Linux => "riscv64-linux",
             Target::X86_64Linux => "x86_64-linux",
         }
     }
@@ -34,7 +108,11 @@ impl TryFrom<&str> for Target {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
-            "aarch64-linux" => Ok(Target::Aarch32Linux),
+            "aarch32-linux" => Ok(Target::Aarch32Linux),
+            "aarch64-linux" => Ok(Target::Aarch64Linux),
+            "c" => Ok(Target::C),
+            "i386-linux" => Ok(Target::I386Linux),
+            "riscv64-linux" => Ok(Target::Riscv64Linux),
             "x86_64-linux" => Ok(Target::X86_64Linux),
             _ => Err(anyhow!(
                 "{} is not a valid architecture or it is not implemented",
@@ -43,3 +121,26 @@ impl TryFrom<&str> for Target {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("aarch32-linux")]
+    #[case("aarch64-linux")]
+    #[case("c")]
+    #[case("i386-linux")]
+    #[case("riscv64-linux")]
+    fn test_target(#[case] name: &str) {
+        let target = Target::try_from(name).unwrap();
+
+        assert_eq!(<&str>::from(target), name);
+    }
+
+    #[test]
+    fn test_unknown_target() {
+        assert!(Target::try_from("aarch64-darwin").is_err());
+    }
+}
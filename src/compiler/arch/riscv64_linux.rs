@@ -0,0 +1,795 @@
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::{checked_steps, Options, GUARD, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
+use indoc::formatdoc;
+
+/// 16 GiB, small enough for the 39-bit virtual addresses of most boards.
+const RESERVED: usize = 1 << 34;
+
+/// The checks described in [`super`], which jump to stubs that load the
+/// message into a1 and a2 and the status into a3.
+struct Traps {
+    options: Options,
+    stubs: String,
+    messages: String,
+    count: usize,
+}
+
+impl Traps {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            stubs: String::new(),
+            messages: String::new(),
+            count: 0,
+        }
+    }
+
+    /// Stubs are not labeled `T{n}` as on other targets, since that names a
+    /// register here.
+    fn add(&mut self, error: InterpreterError) -> String {
+        let n = self.count;
+        let message = format!("Error: {error}");
+
+        self.count += 1;
+        self.messages
+            .push_str(&format!("message{n}:  .ascii \"{message}\\n\"\n"));
+        self.stubs.push_str(&formatdoc! {"
+            fail{n}:
+                la     a1, message{n}
+                li     a2, {length}
+                li     a3, {status}
+                j      trap
+        ", length = message.len() + 1, status = error.status()});
+
+        format!("fail{n}")
+    }
+
+    /// Checks the data pointer, whose byte offset is in s2.
+    fn pointer(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!("    {}\n", self.offset(span, "s2").trim_end()),
+            false => String::new(),
+        }
+    }
+
+    /// Checks the byte offset in `register` against the size of the tape in
+    /// s4.
+    fn offset(&mut self, span: Span, register: &str) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "{}\n    ",
+                branch(
+                    "bgeu",
+                    &format!("{register}, s4"),
+                    &self.add(InterpreterError::OutOfBounds(span))
+                )
+            ),
+            false => String::new(),
+        }
+    }
+
+    /// Adds `value` to the cell at byte offset `index`. There are no flags,
+    /// so each step is checked by comparing results.
+    fn checked_add(&mut self, span: Span, index: &str, value: isize) -> String {
+        let Options { cell, signed, .. } = self.options;
+        let magnitude = value.unsigned_abs() as u64;
+        let (op, trap) = match value >= 0 {
+            true => ("add", self.add(InterpreterError::IntegerOverflow(span))),
+            false => ("sub", self.add(InterpreterError::IntegerUnderflow(span))),
+        };
+
+        let steps = match (cell, checked_steps(magnitude, &self.options)) {
+            (_, None) => return format!("j      {trap}"),
+            // Cells narrower than a register are checked against their range.
+            (CellWidth::Bits8 | CellWidth::Bits16 | CellWidth::Bits32, _) => {
+                let shift = 64 - cell.bits();
+                let range = match signed {
+                    true => format!(
+                        "slli   t2, a0, {shift}\n    srai   t2, t2, {shift}\n    {}",
+                        branch("bne", "t2, a0", &trap)
+                    ),
+                    false => format!(
+                        "{}\n    {}",
+                        li("t2", 1u64 << cell.bits()),
+                        branch("bgeu", "a0, t2", &trap)
+                    ),
+                };
+
+                format!(
+                    "{}\n    {op}    a0, a0, t1\n    {range}",
+                    li("t1", magnitude)
+                )
+            }
+            // A 64-bit cell left its range if a step moved it the wrong way.
+            (CellWidth::Bits64, Some(steps)) => {
+                let wrong_way = match (signed, op) {
+                    (true, "add") => "blt",
+                    (true, _) => "bgt",
+                    (false, "add") => "bltu",
+                    (false, _) => "bgtu",
+                };
+
+                steps
+                    .into_iter()
+                    .map(|step| {
+                        format!(
+                            "{}\n    {op}    t3, a0, t1\n    {}\n    mv     a0, t3",
+                            li("t1", step),
+                            branch(wrong_way, "t3, a0", &trap)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n    ")
+            }
+        };
+
+        format!(
+            "{}\n    {steps}\n    {}",
+            load_extended(cell, signed, index),
+            store(cell, "a0", index)
+        )
+    }
+
+    /// Checks that the signed cell at byte offset `index` is not negative.
+    fn negative(&mut self, span: Span, index: &str) -> String {
+        let Options { cell, signed, .. } = self.options;
+
+        match signed && !self.options.wrapping {
+            true => format!(
+                "{}\n    {}\n    ",
+                load_extended(cell, signed, index),
+                branch(
+                    "bltz",
+                    "a0",
+                    &self.add(InterpreterError::IntegerUnderflow(span))
+                )
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn emit(&self) -> String {
+        if self.count == 0 {
+            return String::new();
+        }
+
+        formatdoc! {"
+
+            .text
+            {stubs}
+            trap:
+                li     a0, 2
+                li     a7, 64
+                ecall
+                mv     a0, a3
+                li     a7, 93
+                ecall
+
+            .data
+            {messages}"
+        , stubs = self.stubs, messages = self.messages}
+    }
+}
+
+/// Branches to `target` if `condition` holds for `operands`, however far away
+/// it is. A conditional branch only reaches 4 KiB, so the opposite condition
+/// branches over a jump instead.
+fn branch(condition: &str, operands: &str, target: &str) -> String {
+    let opposite = match condition {
+        "beqz" => "bnez",
+        "bnez" => "beqz",
+        "bltz" => "bgez",
+        "bne" => "beq",
+        "blt" => "bge",
+        "bgt" => "ble",
+        "bltu" => "bgeu",
+        "bgeu" => "bltu",
+        "bgtu" => "bleu",
+        _ => unreachable!("no opposite for {condition}"),
+    };
+
+    format!("{opposite:<6} {operands}, 1f\n    j      {target}\n1:")
+}
+
+/// Sets `register` to `value`, read as a 64-bit pattern.
+fn li(register: &str, value: u64) -> String {
+    format!("li     {register}, {}", value as i64)
+}
+
+/// Sets `register` to `source` plus or minus a constant that may not fit an
+/// immediate.
+fn add_constant(register: &str, source: &str, value: isize) -> String {
+    match value {
+        -2048..=2047 => format!("addi   {register}, {source}, {value}"),
+        _ => format!(
+            "{}\n    add    {register}, {source}, t1",
+            li("t1", value as i64 as u64)
+        ),
+    }
+}
+
+/// Loads the cell at byte offset `index` into a0, zero-extended. t0 is left
+/// holding its address.
+fn load(cell: CellWidth, index: &str) -> String {
+    let op = match cell {
+        CellWidth::Bits8 => "lbu ",
+        CellWidth::Bits16 => "lhu ",
+        CellWidth::Bits32 => "lwu ",
+        CellWidth::Bits64 => "ld  ",
+    };
+
+    format!("add    t0, s1, {index}\n    {op}   a0, 0(t0)")
+}
+
+/// Stores the low bits of `register` into the cell at byte offset `index`.
+fn store(cell: CellWidth, register: &str, index: &str) -> String {
+    let op = match cell {
+        CellWidth::Bits8 => "sb",
+        CellWidth::Bits16 => "sh",
+        CellWidth::Bits32 => "sw",
+        CellWidth::Bits64 => "sd",
+    };
+
+    format!("add    t0, s1, {index}\n    {op}     {register}, 0(t0)")
+}
+
+/// Like [`load`], but sign-extends cells if they are `signed`.
+fn load_extended(cell: CellWidth, signed: bool, index: &str) -> String {
+    let op = match (cell, signed) {
+        (CellWidth::Bits8, true) => "lb  ",
+        (CellWidth::Bits16, true) => "lh  ",
+        (CellWidth::Bits32, true) => "lw  ",
+        _ => return load(cell, index),
+    };
+
+    format!("add    t0, s1, {index}\n    {op}   a0, 0(t0)")
+}
+
+/// Sets s3 to the byte offset of the cell `offset` away from the data
+/// pointer.
+fn select(offset: isize, bytes: usize) -> String {
+    match offset * bytes as isize {
+        0 => "mv     s3, s2".to_string(),
+        displacement => add_constant("s3", "s2", displacement),
+    }
+}
+
+/// Loads the cell whose index is in a6 into a0, extended to 64 bits. a4
+/// holds the address of cell 0.
+fn load_dumped(cell: CellWidth, signed: bool) -> String {
+    let op = match (cell, signed) {
+        (CellWidth::Bits8, false) => "lbu ",
+        (CellWidth::Bits8, true) => "lb  ",
+        (CellWidth::Bits16, false) => "lhu ",
+        (CellWidth::Bits16, true) => "lh  ",
+        (CellWidth::Bits32, false) => "lwu ",
+        (CellWidth::Bits32, true) => "lw  ",
+        (CellWidth::Bits64, _) => "ld  ",
+    };
+
+    format!(
+        "slli   t1, a6, {}\n    add    t1, a4, t1\n    {op}   a0, 0(t1)",
+        cell.bytes().trailing_zeros()
+    )
+}
+
+/// The `dump` routine, with the data it needs. It takes the header at a1, a2
+/// bytes long.
+fn dump(options: &Options, headers: &str) -> String {
+    let bytes = options.cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    formatdoc! {"
+
+        .text
+        dump:
+            addi   sp, sp, -16
+            sd     ra, 0(sp)
+            la     a3, dumpbuf
+        dump_header:
+            lbu    t1, 0(a1)
+            sb     t1, 0(a3)
+            addi   a1, a1, 1
+            addi   a3, a3, 1
+            addi   a2, a2, -1
+            bnez   a2, dump_header
+            {origin}
+            add    a4, s1, t1
+            sub    a5, s2, t1
+            srai   a5, a5, {shift}
+            mv     a0, a5
+            call   dump_signed
+            li     t1, ':'
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+            addi   a6, a5, -{radius}
+            {first}
+            bge    a6, t1, dump_last
+            mv     a6, t1
+        dump_last:
+            addi   a7, a5, {radius}
+            {last}
+            ble    a7, t1, dump_cell
+            mv     a7, t1
+        dump_cell:
+            li     t1, ' '
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+            bne    a6, a5, dump_value
+            li     t1, '['
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+        dump_value:
+            {load}
+            call   {number}
+            bne    a6, a5, dump_next
+            li     t1, ']'
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+        dump_next:
+            addi   a6, a6, 1
+            ble    a6, a7, dump_cell
+            li     t1, 10
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+            la     a1, dumpbuf
+            sub    a2, a3, a1
+            li     a0, 2
+            li     a7, 64
+            ecall
+            ld     ra, 0(sp)
+            addi   sp, sp, 16
+            ret
+
+        # Appends a0 in decimal at a3, read as signed or unsigned.
+        dump_signed:
+            bgez   a0, dump_number
+            li     t1, '-'
+            sb     t1, 0(a3)
+            addi   a3, a3, 1
+            neg    a0, a0
+        dump_number:
+            la     t2, digits
+            addi   t2, t2, 20
+            mv     t3, t2
+            li     t4, 10
+        dump_digit:
+            remu   t1, a0, t4
+            divu   a0, a0, t4
+            addi   t1, t1, '0'
+            addi   t2, t2, -1
+            sb     t1, 0(t2)
+            bnez   a0, dump_digit
+        dump_copy:
+            lbu    t1, 0(t2)
+            sb     t1, 0(a3)
+            addi   t2, t2, 1
+            addi   a3, a3, 1
+            bne    t2, t3, dump_copy
+            ret
+
+        .data
+        {headers}
+        .bss
+        dumpbuf: .space 512, 0x0
+        digits:  .space 20, 0x0
+    ",
+        origin = li("t1", origin as u64),
+        shift = bytes.trailing_zeros(),
+        radius = DUMP_RADIUS,
+        first = li("t1", (origin / bytes).wrapping_neg() as u64),
+        last = li("t1", ((tape_size - origin) / bytes - 1) as u64),
+        load = load_dumped(options.cell, options.signed),
+        number = if options.signed { "dump_signed" } else { "dump_number" },
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
+    let mut asm = String::new();
+    let cell = options.cell;
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => String::new(),
+        Eof::Zero => format!("{}\n    ", store(cell, "zero", "s3")),
+        Eof::MinusOne => format!("li     a0, -1\n    {}\n    ", store(cell, "a0", "s3")),
+    };
+    let bytes = cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    // s1 holds the address of the tape, and s4 its size.
+    let start = match options.tape {
+        Tape::Fixed => "la     s1, tape\n".to_string(),
+        Tape::Growable | Tape::Bidirectional => formatdoc! {"
+            li     a0, 0
+                {mapped}
+                li     a2, 0
+                li     a3, 0x4022
+                li     a4, -1
+                li     a5, 0
+                li     a7, 222
+                ecall
+                li     t1, -4096
+                {mapped_check}
+                {guard}
+                add    s1, a0, t1
+                mv     a0, s1
+                {size}
+                li     a2, 3
+                li     a7, 226
+                ecall
+                {protected_check}
+            ",
+            mapped = li("a1", (tape_size + 2 * GUARD) as u64),
+            mapped_check = branch("bgeu", "a0, t1", "no_tape"),
+            protected_check = branch("bnez", "a0", "no_tape"),
+            guard = li("t1", GUARD as u64),
+            size = li("a1", tape_size as u64),
+        },
+    };
+
+    // `read` and `write` act on the cell whose byte offset is in s3. Linker
+    // relaxation is off, as nothing sets up the global pointer it relies on.
+    asm.push_str(&formatdoc! {"
+        # {description}
+
+        .option norelax
+        .global _start
+
+        .text
+        write:
+            li     a0, 1
+            add    a1, s1, s3
+            li     a2, 1
+            li     a7, 64
+            ecall
+            ret
+
+        read:
+            li     a0, 0
+            la     a1, inbuf
+            li     a2, 1
+            li     a7, 63
+            ecall
+            li     t1, 1
+            bne    a0, t1, read_eof
+            lbu    a0, 0(a1)
+            {store}
+            ret
+        read_eof:
+            {on_eof}ret
+
+        _start:
+            {start}    {limit}
+            {origin}
+    ",
+        description = options.describe(),
+        store = store(cell, "a0", "s3"),
+        limit = li("s4", tape_size as u64),
+        origin = li("s2", origin as u64),
+    });
+
+    // The header of each `Debug` dump, in the data section.
+    let mut headers = String::new();
+    let mut traps = Traps::new(options);
+
+    for (i, instruction) in program.iter().enumerate() {
+        let span = instruction.span;
+
+        // Checks the cell an instruction selected into s3, if it is not the
+        // current one.
+        let mut check = |offset: isize| match offset {
+            0 => String::new(),
+            _ => traps.offset(span, "s3"),
+        };
+
+        asm.push_str(&match instruction.node {
+            Instruction::Move(distance) => formatdoc! {"
+            #
+                {add}
+            {check}",
+                add = add_constant("s2", "s2", distance * bytes as isize),
+                check = traps.pointer(span),
+            },
+            Instruction::Add { offset, value } if !options.wrapping => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "s2"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "s3",
+                    ),
+                };
+
+                formatdoc! {"
+                #
+                    {setup}{add}
+                ", add = traps.checked_add(span, index, value)}
+            }
+            Instruction::Add { offset, value } => {
+                let op = if value >= 0 { "add" } else { "sub" };
+                let value = cell.truncate(value.unsigned_abs() as u64);
+                let (setup, index) = match offset {
+                    0 => (String::new(), "s2"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "s3",
+                    ),
+                };
+                let add = match (value, op) {
+                    (0..2048, "add") => format!("addi   a0, a0, {value}"),
+                    (0..2048, _) => format!("addi   a0, a0, -{value}"),
+                    _ => format!("{}\n    {op}    a0, a0, t1", li("t1", value)),
+                };
+
+                formatdoc! {"
+                #
+                    {setup}{load}
+                    {add}
+                    {store}
+                ",
+                    load = load(cell, index),
+                    store = store(cell, "a0", index),
+                }
+            }
+            Instruction::Clear { offset } => {
+                let (setup, index) = match offset {
+                    0 => (String::new(), "s2"),
+                    _ => (
+                        format!("{}\n    {}", select(offset, bytes), check(offset)),
+                        "s3",
+                    ),
+                };
+
+                formatdoc! {"
+                #
+                    {setup}{negative}{store}
+                ",
+                    negative = traps.negative(span, index),
+                    store = store(cell, "zero", index),
+                }
+            }
+            Instruction::MulAdd { offset, factor } if !options.wrapping => formatdoc! {"
+            #
+                {load_counter}
+                beqz   a0, M{i}
+                {select}
+                {check}{negative}mv     t4, a0
+            M{i}_loop:
+                {add}
+                addi   t4, t4, -1
+                bnez   t4, M{i}_loop
+            M{i}:
+            ",
+                load_counter = load(cell, "s2"),
+                select = select(offset, bytes),
+                check = check(offset),
+                negative = traps.negative(span, "s2"),
+                add = traps.checked_add(span, "s3", factor),
+            },
+            Instruction::MulAdd { offset, factor } => formatdoc! {"
+            #
+                {load_counter}
+                beqz   a0, M{i}
+                {factor}
+                mul    t3, a0, t1
+                {select}
+                {check}{load}
+                add    a0, a0, t3
+                {store}
+            M{i}:
+            ",
+                load_counter = load(cell, "s2"),
+                factor = li("t1", factor as i64 as u64),
+                select = select(offset, bytes),
+                check = check(offset),
+                load = load(cell, "s3"),
+                store = store(cell, "a0", "s3"),
+            },
+            // Scans are not labeled `S{i}` as on other targets, since that
+            // names a register here.
+            Instruction::Scan(stride) => formatdoc! {"
+                #
+                scan{i}:
+                    {load}
+                    beqz   a0, scan{i}_end
+                    {step}
+                    {check}j      scan{i}
+                scan{i}_end:
+            ",
+                load = load(cell, "s2"),
+                step = add_constant("s2", "s2", stride * bytes as isize),
+                check = traps.offset(span, "s2"),
+            },
+            Instruction::Input { offset } => formatdoc! {"
+            #
+                {select}
+                {check}call   read
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Output { offset } => formatdoc! {"
+            #
+                {select}
+                {check}call   write
+            ", select = select(offset, bytes), check = check(offset)},
+            Instruction::Debug => {
+                let header = format!("# {} cell ", instruction.span);
+                headers.push_str(&format!("header{i}: .ascii \"{header}\"\n"));
+
+                formatdoc! {"
+                #
+                    la     a1, header{i}
+                    li     a2, {length}
+                    call   dump
+                ", length = header.len()}
+            }
+            Instruction::LoopEnd(j) => formatdoc! {"
+                #
+                    {load}
+                    {branch}
+                L{i}:
+            ", load = load(cell, "s2"), branch = branch("bnez", "a0", &format!("L{j}"))},
+            Instruction::LoopStart(j) => formatdoc! {"
+                L{i}:
+                    {load}
+                    {branch}
+            ", load = load(cell, "s2"), branch = branch("beqz", "a0", &format!("L{j}"))},
+        });
+    }
+
+    asm.push_str(&formatdoc! {"
+        exit:
+            li     a0, 0
+            li     a7, 93
+            ecall
+
+        .bss
+        .balign 8
+        {tape}inbuf:   .space 1, 0x0
+    ", tape = match options.tape {
+        Tape::Fixed => format!("tape:    .space {tape_size}, 0x0\n"),
+        Tape::Growable | Tape::Bidirectional => String::new(),
+    }});
+
+    if options.tape != Tape::Fixed {
+        asm.push_str(&formatdoc! {"
+
+            .text
+            no_tape:
+                li     a0, 2
+                la     a1, no_tape_message
+                li     a2, {length}
+                li     a7, 64
+                ecall
+                li     a0, 1
+                li     a7, 93
+                ecall
+
+            .data
+            no_tape_message:  .ascii \"{NO_TAPE}\\n\"
+        ", length = NO_TAPE.len() + 1});
+    }
+
+    asm.push_str(&traps.emit());
+    if !headers.is_empty() {
+        asm.push_str(&dump(options, &headers));
+    }
+
+    asm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::options;
+    use rstest::*;
+
+    #[rstest]
+    #[case(2047, "li     t1, 2047")]
+    #[case(4096, "li     t1, 4096")]
+    #[case(0x1234_5678_9abc, "li     t1, 20015998343868")]
+    #[case(1 << 63, "li     t1, -9223372036854775808")]
+    #[case(u64::MAX, "li     t1, -1")]
+    fn test_li(#[case] value: u64, #[case] expected: &str) {
+        assert_eq!(li("t1", value), expected);
+    }
+
+    #[rstest]
+    #[case(2047, "addi   s3, s2, 2047")]
+    #[case(-2048, "addi   s3, s2, -2048")]
+    #[case(2048, "li     t1, 2048\n    add    s3, s2, t1")]
+    #[case(-2049, "li     t1, -2049\n    add    s3, s2, t1")]
+    fn test_add_constant(#[case] value: isize, #[case] expected: &str) {
+        assert_eq!(add_constant("s3", "s2", value), expected);
+    }
+
+    #[rstest]
+    #[case("bgeu", "bltu")]
+    #[case("bltu", "bgeu")]
+    #[case("bgtu", "bleu")]
+    #[case("blt", "bge")]
+    #[case("bgt", "ble")]
+    #[case("bne", "beq")]
+    fn test_branch(#[case] condition: &str, #[case] opposite: &str) {
+        assert_eq!(
+            branch(condition, "a0, t2", "fail7"),
+            format!("{opposite:<6} a0, t2, 1f\n    j      fail7\n1:")
+        );
+    }
+
+    #[rstest]
+    #[case("beqz", "bnez   a0, 1f")]
+    #[case("bnez", "beqz   a0, 1f")]
+    #[case("bltz", "bgez   a0, 1f")]
+    fn test_branch_zero(#[case] condition: &str, #[case] expected: &str) {
+        assert!(branch(condition, "a0", "L3").starts_with(expected));
+    }
+
+    #[test]
+    fn test_loop_branches() {
+        let program = [Instruction::LoopStart(1), Instruction::LoopEnd(0)].map(|node| Spanned {
+            node,
+            span: Span::default(),
+        });
+        let asm = codegen(&program, &options(CellWidth::Bits8, false));
+
+        assert!(asm.contains("L0:\n    add    t0, s1, s2\n    lbu    a0, 0(t0)\n    bnez   a0, 1f\n    j      L1\n1:"));
+        assert!(asm.contains("beqz   a0, 1f\n    j      L0\n1:\nL1:"));
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut traps = Traps::new(&options(CellWidth::Bits8, false));
+
+        assert_eq!(traps.emit(), "");
+        assert_eq!(
+            traps.add(InterpreterError::IntegerUnderflow(Span::default())),
+            "fail0"
+        );
+
+        let code = traps.emit();
+        assert!(code.contains("fail0:\n    la     a1, message0\n"));
+        assert!(code.contains("li     a7, 64\n    ecall\n    mv     a0, a3\n    li     a7, 93"));
+    }
+
+    #[rstest]
+    #[case(
+        CellWidth::Bits64,
+        false,
+        1,
+        "add    t3, a0, t1\n    bgeu   t3, a0, 1f\n    j      fail0"
+    )]
+    #[case(CellWidth::Bits64, false, -1, "sub    t3, a0, t1\n    bleu   t3, a0, 1f\n    j      fail0")]
+    #[case(
+        CellWidth::Bits64,
+        true,
+        1,
+        "add    t3, a0, t1\n    bge    t3, a0, 1f\n    j      fail0"
+    )]
+    #[case(CellWidth::Bits64, true, -1, "sub    t3, a0, t1\n    ble    t3, a0, 1f\n    j      fail0")]
+    #[case(
+        CellWidth::Bits8,
+        false,
+        1,
+        "li     t2, 256\n    bltu   a0, t2, 1f\n    j      fail0"
+    )]
+    #[case(
+        CellWidth::Bits32,
+        true,
+        1,
+        "slli   t2, a0, 32\n    srai   t2, t2, 32\n    beq    t2, a0, 1f\n    j      fail0"
+    )]
+    fn test_checked_add(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] value: isize,
+        #[case] expected: &str,
+    ) {
+        let mut traps = Traps::new(&options(cell, signed));
+        let code = traps.checked_add(Span::default(), "s3", value);
+
+        assert!(code.contains(expected), "{code}");
+    }
+}
@@ -1,18 +1,374 @@
-use crate::parser::Token;
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    compiler::{checked_steps, Options, GUARD, NO_TAPE},
+    interpreter::InterpreterError,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
 use indoc::{formatdoc, indoc};
 
-pub fn codegen(program: &[Token], memory: usize) -> String {
+/// 64 GiB, well within the 47 bits of address space a process has.
+const RESERVED: usize = 1 << 36;
+
+/// The checks described in [`super`], which jump to stubs that load the
+/// message into rsi and rdx and the status into r14.
+struct Traps {
+    options: Options,
+    stubs: String,
+    messages: String,
+    count: usize,
+}
+
+impl Traps {
+    fn new(options: &Options) -> Self {
+        Self {
+            options: *options,
+            stubs: String::new(),
+            messages: String::new(),
+            count: 0,
+        }
+    }
+
+    /// The size of the tape in bytes, to compare offsets against. A reserved
+    /// region is too large for an immediate, so it is kept in r15.
+    fn limit(&self) -> String {
+        match self.options.tape {
+            Tape::Fixed => self.options.layout(RESERVED).0.to_string(),
+            Tape::Growable | Tape::Bidirectional => "r15".to_string(),
+        }
+    }
+
+    /// Checks the data pointer, whose byte offset is in r12.
+    fn pointer(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "    cmp        r12, {}\n    jae        {}\n",
+                self.limit(),
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    /// Checks the cell `offset` away from the data pointer, leaving its byte
+    /// offset in r13.
+    fn offset(&mut self, span: Span, offset: isize) -> String {
+        match offset * self.options.cell.bytes() as isize {
+            displacement if self.options.bounds_check && displacement != 0 => format!(
+                "lea        r13, [r12 + {displacement}]\n    \
+                 cmp        r13, {}\n    \
+                 jae        {}\n    ",
+                self.limit(),
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            _ => String::new(),
+        }
+    }
+
+    /// Checks that `repne scas` found a zero cell before the edge of the tape.
+    fn scan(&mut self, span: Span) -> String {
+        match self.options.bounds_check {
+            true => format!(
+                "jne        {}\n    ",
+                self.add(InterpreterError::OutOfBounds(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn add(&mut self, error: InterpreterError) -> String {
+        let n = self.count;
+        let message = format!("Error: {error}");
+
+        self.count += 1;
+        self.messages
+            .push_str(&format!("    E{n}          db \"{message}\", 10\n"));
+        self.stubs.push_str(&formatdoc! {"
+            T{n}:
+                mov        rsi, E{n}
+                mov        rdx, {length}
+                mov        r14, {status}
+                jmp        trap
+        ", length = message.len() + 1, status = error.status()});
+
+        format!("T{n}")
+    }
+
+    /// Adds `value` to the cell at `target`, checked with `jc`, or `jo` for
+    /// signed cells.
+    fn checked_add(&mut self, span: Span, target: &str, value: isize) -> String {
+        let (size, scratch) = operands(self.options.cell);
+        let (op, trap) = match value >= 0 {
+            true => ("add", self.add(InterpreterError::IntegerOverflow(span))),
+            false => ("sub", self.add(InterpreterError::IntegerUnderflow(span))),
+        };
+        let jump = if self.options.signed { "jo" } else { "jc" };
+
+        match checked_steps(value.unsigned_abs() as u64, &self.options) {
+            Some(steps) => steps
+                .into_iter()
+                .map(|step| match step > i32::MAX as u64 {
+                    // The immediate of add/sub is sign-extended from 32 bits, so
+                    // larger steps go through the scratch register of the cell.
+                    true => format!(
+                        "mov        {scratch}, {step}\n    \
+                         {op}        {size} [{target}], {scratch}\n    \
+                         {jump}         {trap}"
+                    ),
+                    false => {
+                        format!("{op}        {size} [{target}], {step}\n    {jump}         {trap}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n    "),
+            None => format!("jmp        {trap}"),
+        }
+    }
+
+    /// Checks that the signed cell at `target` is not negative.
+    fn negative(&mut self, span: Span, target: &str) -> String {
+        match self.options.signed && !self.options.wrapping {
+            true => format!(
+                "cmp        {} [{target}], 0\n    jl         {}\n    ",
+                operands(self.options.cell).0,
+                self.add(InterpreterError::IntegerUnderflow(span))
+            ),
+            false => String::new(),
+        }
+    }
+
+    fn emit(&self) -> String {
+        if self.count == 0 {
+            return String::new();
+        }
+
+        formatdoc! {"
+
+            {stubs}
+            trap:
+                mov        rax, 1
+                mov        rdi, 2
+                syscall
+                mov        rax, 60
+                mov        rdi, r14
+                syscall
+
+            section .data
+            {messages}
+            section .text
+        ", stubs = self.stubs, messages = self.messages}
+    }
+}
+
+/// Loads the cell whose index is in r9 into rax, extended to 64 bits. r11
+/// holds the address of cell 0.
+fn load_dumped(cell: CellWidth, signed: bool) -> &'static str {
+    match (cell, signed) {
+        (CellWidth::Bits8, false) => "movzx      rax, byte [r11 + r9]",
+        (CellWidth::Bits8, true) => "movsx      rax, byte [r11 + r9]",
+        (CellWidth::Bits16, false) => "movzx      rax, word [r11 + r9*2]",
+        (CellWidth::Bits16, true) => "movsx      rax, word [r11 + r9*2]",
+        (CellWidth::Bits32, false) => "mov        eax, dword [r11 + r9*4]",
+        (CellWidth::Bits32, true) => "movsxd     rax, dword [r11 + r9*4]",
+        (CellWidth::Bits64, _) => "mov        rax, qword [r11 + r9*8]",
+    }
+}
+
+/// The `dump` routine, with the data it needs. It takes the header at rsi,
+/// rcx bytes long.
+fn dump(options: &Options, headers: &str) -> String {
+    let bytes = options.cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    formatdoc! {"
+
+        dump:
+            mov        rdi, dumpbuf
+            rep movsb
+            mov        r11, {origin}
+            add        r11, rbx
+            lea        r8, [rbx + r12]
+            sub        r8, r11
+            sar        r8, {shift}
+            mov        rax, r8
+            call       dump_signed
+            mov        byte [rdi], ':'
+            inc        rdi
+            lea        r9, [r8 - {radius}]
+            mov        rax, {first}
+            cmp        r9, rax
+            jge        dump_end
+            mov        r9, rax
+        dump_end:
+            lea        r10, [r8 + {radius}]
+            mov        rax, {last}
+            cmp        r10, rax
+            jle        dump_cell
+            mov        r10, rax
+        dump_cell:
+            mov        byte [rdi], ' '
+            inc        rdi
+            cmp        r9, r8
+            jne        dump_value
+            mov        byte [rdi], '['
+            inc        rdi
+        dump_value:
+            {load}
+            call       {number}
+            cmp        r9, r8
+            jne        dump_next
+            mov        byte [rdi], ']'
+            inc        rdi
+        dump_next:
+            inc        r9
+            cmp        r9, r10
+            jle        dump_cell
+            mov        byte [rdi], 10
+            inc        rdi
+            mov        rdx, rdi
+            sub        rdx, dumpbuf
+            mov        rsi, dumpbuf
+            mov        rdi, 2
+            mov        rax, 1
+            syscall
+            ret
+
+        ; Appends rax in decimal at rdi, read as signed or unsigned.
+        dump_signed:
+            test       rax, rax
+            jns        dump_number
+            mov        byte [rdi], '-'
+            inc        rdi
+            neg        rax
+        dump_number:
+            mov        rsi, digits + 20
+            mov        rcx, 10
+        dump_digit:
+            xor        rdx, rdx
+            div        rcx
+            add        dl, '0'
+            dec        rsi
+            mov        [rsi], dl
+            test       rax, rax
+            jnz        dump_digit
+            mov        rcx, digits + 20
+            sub        rcx, rsi
+            rep movsb
+            ret
+
+        section .data
+        {headers}
+        section .bss
+            dumpbuf     resb 512
+            digits      resb 20
+    ",
+        shift = bytes.trailing_zeros(),
+        radius = DUMP_RADIUS,
+        first = -((origin / bytes) as isize),
+        last = (tape_size - origin) / bytes - 1,
+        load = load_dumped(options.cell, options.signed),
+        number = if options.signed { "dump_signed" } else { "dump_number" },
+    }
+}
+
+/// Operand size keyword and matching scratch register for a cell.
+fn operands(cell: CellWidth) -> (&'static str, &'static str) {
+    match cell {
+        CellWidth::Bits8 => ("byte", "r11b"),
+        CellWidth::Bits16 => ("word", "r11w"),
+        CellWidth::Bits32 => ("dword", "r11d"),
+        CellWidth::Bits64 => ("qword", "r11"),
+    }
+}
+
+/// The string instruction that compares `rax` against a cell at `rdi`.
+fn scas(cell: CellWidth) -> &'static str {
+    match cell {
+        CellWidth::Bits8 => "scasb",
+        CellWidth::Bits16 => "scasw",
+        CellWidth::Bits32 => "scasd",
+        CellWidth::Bits64 => "scasq",
+    }
+}
+
+/// Loads the current cell into r11, zero-extended.
+fn load_counter(cell: CellWidth) -> &'static str {
+    match cell {
+        CellWidth::Bits8 => "movzx      r11, byte [rbx + r12]",
+        CellWidth::Bits16 => "movzx      r11, word [rbx + r12]",
+        CellWidth::Bits32 => "mov        r11d, dword [rbx + r12]",
+        CellWidth::Bits64 => "mov        r11, qword [rbx + r12]",
+    }
+}
+
+/// Memory operand for the cell at `offset` from the data pointer.
+fn cell_ref(offset: isize, bytes: usize) -> String {
+    match offset * bytes as isize {
+        0 => "rbx + r12".to_string(),
+        displacement if displacement > 0 => format!("rbx + r12 + {displacement}"),
+        displacement => format!("rbx + r12 - {}", -displacement),
+    }
+}
+
+pub fn codegen(program: &[Spanned<Instruction>], options: &Options) -> String {
+    let cell = options.cell;
+    let (size, scratch) = operands(cell);
+    let bytes = cell.bytes();
+    let (tape_size, origin) = options.layout(RESERVED);
+
+    let on_eof = match options.eof {
+        Eof::Unchanged => String::new(),
+        Eof::Zero => format!("mov        {size} [rbx + r13], 0\n    "),
+        Eof::MinusOne => format!("mov        {size} [rbx + r13], -1\n    "),
+    };
+
+    // rbx holds the address of the tape, and r15 its size if it grows.
+    let (tape, start) = match options.tape {
+        Tape::Fixed => (
+            format!("    tape        resb {tape_size}\n"),
+            "mov        rbx, tape\n".to_string(),
+        ),
+        Tape::Growable | Tape::Bidirectional => (
+            String::new(),
+            formatdoc! {"
+                mov        rax, 9
+                    xor        edi, edi
+                    mov        rsi, {mapped}
+                    xor        edx, edx
+                    mov        r10, 0x4022
+                    mov        r8, -1
+                    xor        r9, r9
+                    syscall
+                    cmp        rax, -4096
+                    ja         no_tape
+                    lea        rbx, [rax + {GUARD}]
+                    mov        rdi, rbx
+                    mov        rsi, {tape_size}
+                    mov        rdx, 3
+                    mov        rax, 10
+                    syscall
+                    test       rax, rax
+                    jnz        no_tape
+                    mov        r15, {tape_size}
+                ",
+                mapped = tape_size + 2 * GUARD,
+            },
+        ),
+    };
+
+    // `read` and `write` act on the cell whose byte offset is in r13.
     let mut asm: String = formatdoc! {"
+        ; {description}
+
         section .bss
-            tape        resb {memory}
+        {tape}    inbuf       resb 1
 
         section .text
         global _start
 
         write:
             mov        rdi, 1
-            mov        rsi, tape
-            add        rsi, r12
+            lea        rsi, [rbx + r13]
             mov        rdx, 1
             mov        rax, 1
             syscall
@@ -20,64 +376,209 @@ pub fn codegen(program: &[Token], memory: usize) -> String {
 
         read:
             mov        rdi, 0
-            mov        rsi, tape
-            add        rsi, r12
+            mov        rsi, inbuf
             mov        rdx, 1
             mov        rax, 0
             syscall
+            cmp        rax, 1
+            jne        read_eof
+            movzx      r11, byte [inbuf]
+            mov        {size} [rbx + r13], {scratch}
             ret
+        read_eof:
+            {on_eof}ret
 
         _start:
-            mov        r12, 0
-    "}
+            {start}    mov        r12, {origin}
+    ",
+        description = options.describe(),
+    }
     .to_string();
 
-    for (i, t) in program.iter().enumerate() {
-        asm.push_str(&match t {
-            Token::Right(operand) => formatdoc! {"
+    // Points r13 at the cell `offset` away from the data pointer.
+    let select = |offset: isize| match offset * bytes as isize {
+        0 => "mov        r13, r12".to_string(),
+        displacement => format!("lea        r13, [r12 + {displacement}]"),
+    };
+
+    // The header of each `Debug` dump, in the data section.
+    let mut headers = String::new();
+    let mut traps = Traps::new(options);
+
+    for (i, instruction) in program.iter().enumerate() {
+        let span = instruction.span;
+
+        asm.push_str(&match instruction.node {
+            Instruction::Move(distance) if distance >= 0 => formatdoc! {"
             ;
-                add        r12, {operand}
-            "}
-            .to_string(),
-            Token::Left(operand) => formatdoc! {"
+                add        r12, {offset}
+            {check}",
+                offset = distance as usize * bytes,
+                check = traps.pointer(span),
+            },
+            Instruction::Move(distance) => formatdoc! {"
             ;
-                sub        r12, {operand}
-            "}
-            .to_string(),
-            Token::Add(operand) => formatdoc! {"
+                sub        r12, {offset}
+            {check}",
+                offset = distance.unsigned_abs() * bytes,
+                check = traps.pointer(span),
+            },
+            Instruction::Add { offset, value } if !options.wrapping => formatdoc! {"
             ;
-                add        byte [tape + r12], {operand}
-            "}
-            .to_string(),
-            Token::Sub(operand) => formatdoc! {"
+                {check}{add}
+            ",
+                check = traps.offset(span, offset),
+                add = traps.checked_add(span, &cell_ref(offset, bytes), value),
+            },
+            Instruction::Add { offset, value } => {
+                let op = if value >= 0 { "add" } else { "sub" };
+                let value = cell.truncate(value.unsigned_abs() as u64);
+                let target = cell_ref(offset, bytes);
+                let check = traps.offset(span, offset);
+
+                // There is no 64-bit immediate form of add/sub, so go through a register.
+                if cell == CellWidth::Bits64 && value > i32::MAX as u64 {
+                    formatdoc! {"
+                    ;
+                        {check}mov        r11, {value}
+                        {op}        qword [{target}], r11
+                    "}
+                } else {
+                    formatdoc! {"
+                    ;
+                        {check}{op}        {size} [{target}], {value}
+                    "}
+                }
+            }
+            Instruction::Clear { offset } => {
+                let target = cell_ref(offset, bytes);
+
+                formatdoc! {"
+                ;
+                    {check}{negative}mov        {size} [{target}], 0
+                ",
+                    check = traps.offset(span, offset),
+                    negative = traps.negative(span, &target),
+                }
+            }
+            Instruction::MulAdd { offset, factor } if !options.wrapping => formatdoc! {"
+            ;
+                {load}
+                test       r11, r11
+                jz         M{i}
+                {check}{negative}mov        rcx, r11
+            M{i}_loop:
+                {add}
+                dec        rcx
+                jnz        M{i}_loop
+            M{i}:
+            ",
+                load = load_counter(cell),
+                check = traps.offset(span, offset),
+                negative = traps.negative(span, "rbx + r12"),
+                add = traps.checked_add(span, &cell_ref(offset, bytes), factor),
+            },
+            Instruction::MulAdd { offset, factor } => {
+                // `imul` serves unsigned cells too.
+                let multiply = match factor {
+                    1 => String::new(),
+                    _ if i32::try_from(factor).is_ok() => {
+                        format!("imul       r11, r11, {factor}\n    ")
+                    }
+                    _ => format!("mov        rax, {factor}\n    imul       r11, rax\n    "),
+                };
+
+                formatdoc! {"
+                ;
+                    {load}
+                    test       r11, r11
+                    jz         M{i}
+                    {check}{multiply}add        {size} [{target}], {scratch}
+                M{i}:
+                ",
+                    check = traps.offset(span, offset),
+                    load = load_counter(cell),
+                    target = cell_ref(offset, bytes),
+                }
+            }
+            // `repne scas` stops just past the first zero cell, or at the
+            // edge of the tape if there is none.
+            Instruction::Scan(1) => formatdoc! {"
+            ;
+                lea        rdi, [rbx + r12]
+                mov        rcx, {tape_size}
+                sub        rcx, r12
+                shr        rcx, {shift}
+                xor        eax, eax
+                repne      {scas}
+                {not_found}lea        r12, [rdi - {bytes}]
+                sub        r12, rbx
+            ",
+                tape_size = tape_size,
+                shift = bytes.trailing_zeros(),
+                scas = scas(cell),
+                not_found = traps.scan(span),
+            },
+            Instruction::Scan(-1) => formatdoc! {"
             ;
-                sub        byte [tape + r12], {operand}
-            "}
-            .to_string(),
-            Token::Read => indoc! {"
+                lea        rdi, [rbx + r12]
+                lea        rcx, [r12 + {bytes}]
+                shr        rcx, {shift}
+                xor        eax, eax
+                std
+                repne      {scas}
+                cld
+                {not_found}lea        r12, [rdi + {bytes}]
+                sub        r12, rbx
+            ",
+                shift = bytes.trailing_zeros(),
+                scas = scas(cell),
+                not_found = traps.scan(span),
+            },
+            Instruction::Scan(stride) => formatdoc! {"
+            S{i}:
+                cmp        {size} [rbx + r12], 0
+                je         S{i}_end
+                {check}{op}        r12, {distance}
+                jmp        S{i}
+            S{i}_end:
+            ",
+                check = traps.offset(span, stride),
+                op = if stride > 0 { "add" } else { "sub" },
+                distance = stride.unsigned_abs() * bytes,
+            },
+            Instruction::Input { offset } => formatdoc! {"
             ;
+                {check}{select}
                 call       read
-            "}
-            .to_string(),
-            Token::Write => indoc! {"
+            ", check = traps.offset(span, offset), select = select(offset)},
+            Instruction::Output { offset } => formatdoc! {"
             ;
+                {check}{select}
                 call       write
-            "}
-            .to_string(),
-            Token::Loop(j) => formatdoc! {"
+            ", check = traps.offset(span, offset), select = select(offset)},
+            Instruction::LoopEnd(j) => formatdoc! {"
             ;
-                movzx      r11, byte [tape + r12]
-                cmp        r11, 0
+                cmp        {size} [rbx + r12], 0
                 jne        L{j}
             L{i}:
             "},
-            Token::Break(j) => formatdoc! {"
+            Instruction::Debug => {
+                let header = format!("# {} cell ", instruction.span);
+                headers.push_str(&format!("    D{i}          db \"{header}\"\n"));
+
+                formatdoc! {"
+                ;
+                    mov        rsi, D{i}
+                    mov        rcx, {length}
+                    call       dump
+                ", length = header.len()}
+            }
+            Instruction::LoopStart(j) => formatdoc! {"
             L{i}:
-                movzx      r11, byte [tape + r12]
-                cmp        r11, 0
+                cmp        {size} [rbx + r12], 0
                 je         L{j}
             "},
-            Token::Comment => "".to_string(),
         });
     }
 
@@ -88,5 +589,94 @@ pub fn codegen(program: &[Token], memory: usize) -> String {
             syscall
     "});
 
+    if options.tape != Tape::Fixed {
+        asm.push_str(&formatdoc! {"
+
+            no_tape:
+                mov        rax, 1
+                mov        rdi, 2
+                mov        rsi, no_tape_message
+                mov        rdx, {length}
+                syscall
+                mov        rax, 60
+                mov        rdi, 1
+                syscall
+
+            section .data
+                no_tape_message db \"{NO_TAPE}\", 10
+            section .text
+        ", length = NO_TAPE.len() + 1});
+    }
+
+    asm.push_str(&traps.emit());
+    if !headers.is_empty() {
+        asm.push_str(&dump(options, &headers));
+    }
+
     asm
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::{options, program};
+    use rstest::*;
+
+    #[rstest]
+    #[case(
+        1,
+        "repne      scasb\n    jne        T0\n    lea        r12, [rdi - 1]"
+    )]
+    #[case(-1, "cld\n    jne        T0\n    lea        r12, [rdi + 1]")]
+    fn test_scan_check(#[case] stride: isize, #[case] expected: &str) {
+        let options = Options {
+            wrapping: true,
+            bounds_check: true,
+            ..options(CellWidth::Bits8, false)
+        };
+        let asm = codegen(&program(&[Instruction::Scan(stride)]), &options);
+
+        // `repne scas` runs out at the edge of the tape without finding a zero.
+        assert!(asm.contains(expected), "{asm}");
+    }
+
+    #[rstest]
+    #[case(
+        CellWidth::Bits8,
+        false,
+        1,
+        "add        byte [rbx + r12], 1\n    jc         T0"
+    )]
+    #[case(CellWidth::Bits8, false, -1, "sub        byte [rbx + r12], 1\n    jc         T0")]
+    #[case(
+        CellWidth::Bits8,
+        true,
+        1,
+        "add        byte [rbx + r12], 1\n    jo         T0"
+    )]
+    #[case(
+        CellWidth::Bits64,
+        false,
+        1 << 40,
+        "mov        r11, 1099511627776\n    add        qword [rbx + r12], r11\n    jc         T0"
+    )]
+    #[case(
+        CellWidth::Bits32,
+        false,
+        0xffff_ffff,
+        "mov        r11d, 4294967295\n    add        dword [rbx + r12], r11d\n    jc         T0"
+    )]
+    fn test_checked_add(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] value: isize,
+        #[case] expected: &str,
+    ) {
+        let mut traps = Traps::new(&options(cell, signed));
+
+        assert_eq!(
+            traps.checked_add(Span::default(), "rbx + r12", value),
+            expected
+        );
+    }
+}
@@ -0,0 +1,536 @@
+//! The subset of GNU assembler syntax that
+//! [`crate::compiler::arch::aarch32_linux`] writes, in ARM state.
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::{split_operands, Expression, Isa, Section, Statement, Symbols};
+use crate::compiler::elf::Machine;
+
+pub struct Aarch32;
+
+/// An instruction, encoded as far as it can be without knowing symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Encoded(u32),
+    /// `b` or `bl`, with everything but the offset encoded.
+    Branch {
+        bits: u32,
+        target: Expression,
+    },
+    /// `movw` or `movt`, with everything but the immediate encoded.
+    MoveHalf {
+        bits: u32,
+        high: bool,
+        value: Expression,
+    },
+}
+
+const CONDITIONS: [&str; 15] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "al",
+];
+
+fn condition(suffix: &str) -> Option<u32> {
+    match suffix {
+        "" => Some(14),
+        "hs" => Some(2),
+        "lo" => Some(3),
+        _ => CONDITIONS
+            .iter()
+            .position(|&c| c == suffix)
+            .map(|c| c as u32),
+    }
+}
+
+fn register(text: &str) -> Result<u32> {
+    match text.trim() {
+        "sp" => Ok(13),
+        "lr" => Ok(14),
+        "pc" => Ok(15),
+        "fp" => Ok(11),
+        "ip" => Ok(12),
+        name => name
+            .strip_prefix('r')
+            .and_then(|number| number.parse().ok())
+            .filter(|&number| number < 16)
+            .ok_or(anyhow!("Invalid register {name}")),
+    }
+}
+
+fn immediate(text: &str) -> Result<Expression> {
+    let text = text.trim();
+    Expression::parse(text.strip_prefix('#').unwrap_or(text))
+}
+
+fn constant(text: &str) -> Result<i64> {
+    immediate(text)?
+        .constant()
+        .ok_or(anyhow!("{text} must be a constant"))
+}
+
+/// Encodes `value` as an 8-bit value rotated right by an even amount.
+fn rotated(value: u32) -> Result<u32> {
+    (0..16)
+        .find(|&rotation| value.rotate_left(2 * rotation) <= 0xff)
+        .map(|rotation| (rotation << 8) | value.rotate_left(2 * rotation))
+        .ok_or(anyhow!("{value} cannot be encoded as an immediate"))
+}
+
+/// A shift of a register by a constant, as in operand 2 and in addresses.
+fn shift(text: &str) -> Result<u32> {
+    let (kind, amount) = text
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or(anyhow!("Invalid shift {text}"))?;
+    let kind = match kind {
+        "lsl" => 0,
+        "lsr" => 1,
+        "asr" => 2,
+        "ror" => 3,
+        _ => bail!("Invalid shift {kind}"),
+    };
+    let amount = constant(amount)?;
+    if !(0..=32).contains(&amount) || amount == 32 && kind == 0 {
+        bail!("Invalid shift amount {amount}");
+    }
+
+    Ok((((amount as u32) & 31) << 7) | (kind << 5))
+}
+
+/// Operand 2 of a data-processing instruction, with the immediate bit.
+fn operand2(operands: &[&str]) -> Result<u32> {
+    match operands {
+        [value] if value.starts_with('#') => Ok(1 << 25 | rotated(constant(value)? as u32)?),
+        [rm] => register(rm),
+        [rm, amount] => Ok(shift(amount)? | register(rm)?),
+        _ => bail!("Invalid operands"),
+    }
+}
+
+/// Splits a mnemonic into one of `bases` and its condition, and whether it
+/// sets the flags.
+fn split_mnemonic<'a>(mnemonic: &str, bases: &[&'a str]) -> Option<(&'a str, bool, u32)> {
+    bases.iter().find_map(|&base| {
+        let suffix = mnemonic.strip_prefix(base)?;
+        match (condition(suffix), suffix.strip_prefix('s')) {
+            (Some(cond), _) => Some((base, false, cond)),
+            (None, Some(suffix)) => condition(suffix).map(|cond| (base, true, cond)),
+            (None, None) => None,
+        }
+    })
+}
+
+/// Data-processing operations and their opcodes.
+const DATA: [(&str, u32); 16] = [
+    ("and", 0),
+    ("eor", 1),
+    ("sub", 2),
+    ("rsb", 3),
+    ("add", 4),
+    ("adc", 5),
+    ("sbc", 6),
+    ("rsc", 7),
+    ("tst", 8),
+    ("teq", 9),
+    ("cmp", 10),
+    ("cmn", 11),
+    ("orr", 12),
+    ("mov", 13),
+    ("bic", 14),
+    ("mvn", 15),
+];
+
+/// Loads and stores, longest name first.
+const MEMORY: [&str; 10] = [
+    "ldrsb", "ldrsh", "ldrb", "ldrh", "ldrd", "strb", "strh", "strd", "ldr", "str",
+];
+
+/// The other instructions but branches, longest name first. They are looked
+/// up before [`DATA`], so that `movw` is not read as `mov`.
+const OTHERS: [&str; 13] = [
+    "umull", "movw", "movt", "push", "sxtb", "sxth", "pop", "mla", "mul", "svc", "lsl", "lsr",
+    "asr",
+];
+
+/// Encodes a load or store of `base` (one of [`MEMORY`]).
+fn memory(base: &str, cond: u32, operands: &[&str]) -> Result<u32> {
+    let dual = base.ends_with('d');
+    let (rd, address, post) = match (dual, operands) {
+        (false, [rd, address]) => (register(rd)?, *address, None),
+        (false, [rd, address, post]) => (register(rd)?, *address, Some(*post)),
+        (true, [rd, rd2, address, rest @ ..]) if rest.len() <= 1 => {
+            let rd = register(rd)?;
+            if rd % 2 != 0 || register(rd2)? != rd + 1 {
+                bail!("ldrd and strd need an even register and the one after it");
+            }
+            (rd, *address, rest.first().copied())
+        }
+        _ => bail!("Invalid operands"),
+    };
+    let inner = address
+        .trim()
+        .strip_prefix('[')
+        .and_then(|address| address.strip_suffix(']'))
+        .ok_or(anyhow!("Invalid address {address}"))?;
+    let parts = split_operands(inner);
+    let rn = register(parts.first().ok_or(anyhow!("Invalid address {address}"))?)?;
+
+    // Either an immediate offset, or a register one.
+    let (offset, immediate_offset, pre) = match (&parts[1..], post) {
+        ([], None) => (Some(0), None, true),
+        ([], Some(post)) => (Some(constant(post)?), None, false),
+        ([offset], None) if offset.starts_with('#') => (Some(constant(offset)?), None, true),
+        ([rm], None) => (None, Some((register(rm)?, 0)), true),
+        ([rm, amount], None) => (None, Some((register(rm)?, shift(amount)?)), true),
+        _ => bail!("Invalid address {address}"),
+    };
+
+    let pre = u32::from(pre) << 24;
+    let load = u32::from(base.starts_with("ldr"));
+    let fields = cond << 28 | rn << 16 | rd << 12;
+
+    match base {
+        "ldr" | "str" | "ldrb" | "strb" => {
+            let byte = u32::from(base.ends_with('b')) << 22;
+            let bits = 0x0400_0000 | pre | byte | load << 20 | fields;
+            Ok(match (offset, immediate_offset) {
+                (Some(offset), _) if offset.unsigned_abs() < 0x1000 => {
+                    bits | u32::from(offset >= 0) << 23 | offset.unsigned_abs() as u32
+                }
+                (None, Some((rm, shift))) => bits | 1 << 25 | 1 << 23 | shift | rm,
+                _ => bail!("Offset out of range"),
+            })
+        }
+        _ => {
+            if immediate_offset.is_some_and(|(_, shift)| shift != 0) {
+                bail!("{base} cannot shift its offset");
+            }
+            let (load, signed, half) = match base {
+                "ldrh" => (1, 0, 1),
+                "strh" => (0, 0, 1),
+                "ldrsb" => (1, 1, 0),
+                "ldrsh" => (1, 1, 1),
+                "ldrd" => (0, 1, 0),
+                _ => (0, 1, 1),
+            };
+            let bits = pre | load << 20 | fields | 1 << 7 | signed << 6 | half << 5 | 1 << 4;
+            Ok(match (offset, immediate_offset) {
+                (Some(offset), _) if offset.unsigned_abs() < 0x100 => {
+                    let magnitude = offset.unsigned_abs() as u32;
+                    bits | u32::from(offset >= 0) << 23
+                        | 1 << 22
+                        | (magnitude >> 4) << 8
+                        | (magnitude & 0xf)
+                }
+                (None, Some((rm, _))) => bits | 1 << 23 | rm,
+                _ => bail!("Offset out of range"),
+            })
+        }
+    }
+}
+
+/// Parses the register list of `push` and `pop`.
+fn registers(text: &str) -> Result<u32> {
+    let inner = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|list| list.strip_suffix('}'))
+        .ok_or(anyhow!("Invalid register list {text}"))?;
+    let mut mask = 0;
+    for item in inner.split(',') {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (register(first)?, register(last)?),
+            None => (register(item)?, register(item)?),
+        };
+        for number in first..=last {
+            mask |= 1 << number;
+        }
+    }
+
+    Ok(mask)
+}
+
+fn parse_instruction(mnemonic: &str, operands: &str) -> Result<Vec<Instruction>> {
+    let operands = split_operands(operands);
+    use Instruction::Encoded;
+
+    // `mov32` is the macro the backend defines to load any 32-bit value.
+    if mnemonic == "mov32" {
+        let [rd, value] = operands[..] else {
+            bail!("Invalid operands");
+        };
+        let bits = 14 << 28 | register(rd)? << 12;
+        let value = immediate(value)?;
+        return Ok(vec![
+            Instruction::MoveHalf {
+                bits: bits | 0x0300_0000,
+                high: false,
+                value: value.clone(),
+            },
+            Instruction::MoveHalf {
+                bits: bits | 0x0340_0000,
+                high: true,
+                value,
+            },
+        ]);
+    }
+
+    if let Some((base, false, cond)) = split_mnemonic(mnemonic, &MEMORY) {
+        return Ok(vec![Encoded(memory(base, cond, &operands)?)]);
+    }
+
+    let names: Vec<_> = DATA.iter().map(|(name, _)| *name).collect();
+    let others = split_mnemonic(mnemonic, &OTHERS);
+    if let (None, Some((base, flags, cond))) = (others, split_mnemonic(mnemonic, &names)) {
+        let opcode = DATA.iter().find(|(name, _)| *name == base).unwrap().1;
+        let bits = cond << 28 | opcode << 21;
+
+        return Ok(vec![Encoded(match (opcode, &operands[..]) {
+            // Comparisons always set the flags, and have no destination.
+            (8..=11, [rn, rest @ ..]) => bits | 1 << 20 | register(rn)? << 16 | operand2(rest)?,
+            (13 | 15, [rd, rest @ ..]) => {
+                bits | u32::from(flags) << 20 | register(rd)? << 12 | operand2(rest)?
+            }
+            (_, [rd, rn, rest @ ..]) => {
+                bits | u32::from(flags) << 20
+                    | register(rn)? << 16
+                    | register(rd)? << 12
+                    | operand2(rest)?
+            }
+            _ => bail!("Invalid operands"),
+        })]);
+    }
+
+    if let Some((base, flags, cond)) = others {
+        let cond = cond << 28;
+        let flags = u32::from(flags) << 20;
+
+        return Ok(vec![match (base, &operands[..]) {
+            ("lsl" | "lsr" | "asr", [rd, rm, amount]) => Encoded(
+                cond | 13 << 21
+                    | flags
+                    | register(rd)? << 12
+                    | shift(&format!("{base} {amount}"))?
+                    | register(rm)?,
+            ),
+            ("mul", [rd, rm, rs]) => Encoded(
+                cond | flags | 0x90 | register(rd)? << 16 | register(rs)? << 8 | register(rm)?,
+            ),
+            ("mla", [rd, rm, rs, ra]) => Encoded(
+                cond | 1 << 21
+                    | flags
+                    | 0x90
+                    | register(rd)? << 16
+                    | register(ra)? << 12
+                    | register(rs)? << 8
+                    | register(rm)?,
+            ),
+            ("umull", [low, high, rm, rs]) => Encoded(
+                cond | 1 << 23
+                    | flags
+                    | 0x90
+                    | register(high)? << 16
+                    | register(low)? << 12
+                    | register(rs)? << 8
+                    | register(rm)?,
+            ),
+            ("sxtb" | "sxth", [rd, rm]) if flags == 0 => Encoded(
+                cond | if base == "sxtb" {
+                    0x06af_0070
+                } else {
+                    0x06bf_0070
+                } | register(rd)? << 12
+                    | register(rm)?,
+            ),
+            ("movw" | "movt", [rd, value]) if flags == 0 => {
+                let value = constant(value)? as u32;
+                Encoded(
+                    cond | if base == "movw" {
+                        0x0300_0000
+                    } else {
+                        0x0340_0000
+                    } | (value >> 12 & 0xf) << 16
+                        | register(rd)? << 12
+                        | (value & 0xfff),
+                )
+            }
+            ("push", [list]) if flags == 0 => Encoded(cond | 0x092d_0000 | registers(list)?),
+            ("pop", [list]) if flags == 0 => Encoded(cond | 0x08bd_0000 | registers(list)?),
+            ("svc", [number]) if flags == 0 => {
+                Encoded(cond | 0x0f00_0000 | (constant(number)? as u32 & 0xff_ffff))
+            }
+            _ => bail!("Invalid operands"),
+        }]);
+    }
+
+    // `bl` is a branch with link, but `blt` and `ble` are conditional
+    // branches.
+    let branch = mnemonic
+        .strip_prefix('b')
+        .and_then(condition)
+        .map(|cond| cond << 28 | 0x0a00_0000)
+        .or_else(|| {
+            mnemonic
+                .strip_prefix("bl")
+                .and_then(condition)
+                .map(|cond| cond << 28 | 0x0b00_0000)
+        });
+    if let (Some(bits), [target]) = (branch, &operands[..]) {
+        return Ok(vec![Instruction::Branch {
+            bits,
+            target: Expression::parse(target)?,
+        }]);
+    }
+
+    bail!("Unsupported instruction {mnemonic}")
+}
+
+/// Parses the string of an `.ascii` directive.
+fn ascii(text: &str) -> Result<Vec<u8>> {
+    let inner = text
+        .trim()
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or(anyhow!("Invalid string {text}"))?;
+    let mut bytes = vec![];
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(ch @ ('\\' | '"')) => ch,
+                _ => bail!("Unsupported escape in {text}"),
+            },
+            ch => ch,
+        };
+        bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+
+    Ok(bytes)
+}
+
+fn parse_line(line: &str) -> Result<Vec<Statement<Instruction>>> {
+    let mut line = line.trim();
+    let mut statements = vec![];
+    if line.is_empty() || line.starts_with('@') {
+        return Ok(statements);
+    }
+
+    if let Some((label, rest)) = line.split_once(':') {
+        if !label.contains(char::is_whitespace) && !label.contains('#') {
+            statements.push(Statement::Label(label.to_string()));
+            line = rest.trim();
+        }
+    }
+    if line.is_empty() {
+        return Ok(statements);
+    }
+
+    let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operands = operands.trim();
+    let count = |text: &str| -> Result<usize> { Ok(usize::try_from(constant(text)?)?) };
+
+    match mnemonic {
+        ".syntax" | ".global" => (),
+        ".text" => statements.push(Statement::Section(Section::Text)),
+        ".data" => statements.push(Statement::Section(Section::Data)),
+        ".bss" => statements.push(Statement::Section(Section::Bss)),
+        ".balign" => statements.push(Statement::Align(count(operands)?)),
+        ".ascii" => statements.push(Statement::Bytes(ascii(operands)?)),
+        ".quad" => statements.push(Statement::Bytes(constant(operands)?.to_le_bytes().to_vec())),
+        ".space" => match split_operands(operands)[..] {
+            [length] => statements.push(Statement::Space(count(length)?)),
+            [length, fill] if constant(fill)? == 0 => {
+                statements.push(Statement::Space(count(length)?))
+            }
+            _ => bail!("Only zeroed space is supported"),
+        },
+        _ => statements.extend(
+            parse_instruction(mnemonic, operands)?
+                .into_iter()
+                .map(Statement::Instruction),
+        ),
+    }
+
+    Ok(statements)
+}
+
+impl Isa for Aarch32 {
+    type Instruction = Instruction;
+
+    const MACHINE: Machine = Machine::Arm;
+
+    fn parse(source: &str) -> Result<Vec<Statement<Instruction>>> {
+        let mut statements = vec![];
+        let mut in_macro = false;
+
+        for (number, line) in source.lines().enumerate() {
+            // The only macro is `mov32`, which is built in.
+            match line.split_whitespace().next() {
+                Some(".macro") => in_macro = true,
+                Some(".endm") => in_macro = false,
+                _ if in_macro => (),
+                _ => statements.extend(
+                    parse_line(line)
+                        .with_context(|| format!("line {}: {}", number + 1, line.trim()))?,
+                ),
+            }
+        }
+
+        Ok(statements)
+    }
+
+    fn encode(instruction: &Instruction, address: u64, symbols: &Symbols) -> Result<Vec<u8>> {
+        let bits = match instruction {
+            Instruction::Encoded(bits) => *bits,
+            Instruction::Branch { bits, target } => {
+                // The offset is from two instructions ahead, in words.
+                let offset = target.value(symbols)? - (address as i64 + 8);
+                bits | ((offset >> 2) as u32 & 0xff_ffff)
+            }
+            Instruction::MoveHalf { bits, high, value } => {
+                let value = value.value(symbols)? as u32;
+                let half = if *high { value >> 16 } else { value & 0xffff };
+                bits | (half >> 12) << 16 | (half & 0xfff)
+            }
+        };
+
+        Ok(bits.to_le_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    fn assemble(line: &str) -> u32 {
+        let Statement::Instruction(instruction) = parse_line(line).unwrap().remove(0) else {
+            panic!("{line} is not an instruction");
+        };
+        let bytes = Aarch32::encode(&instruction, 0x1000, &Symbols::default()).unwrap();
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    #[rstest]
+    #[case("mov    r4, #0", 0xe3a04000)]
+    #[case("mvn    r4, #0", 0xe3e04000)]
+    #[case("cmn    r0, #4096", 0xe3700a01)]
+    #[case("subs   r8, r8, #1", 0xe2588001)]
+    #[case("movlt  r9, r3", 0xb1a09003)]
+    #[case("strbeq r3, [r10], #1", 0x04ca3001)]
+    #[case("ldr    r0, [r5, r9, lsl #2]", 0xe7950109)]
+    #[case("ldrsh  r0, [r5, r3]", 0xe19500f3)]
+    #[case("ldrd   r2, r3, [r12], #8", 0xe0cc20d8)]
+    #[case("strd   r0, r1, [r5, r4]", 0xe18500f4)]
+    #[case("asr    r1, r0, #31", 0xe1a01fc0)]
+    #[case("umull  r8, r9, r0, r2", 0xe0898290)]
+    #[case("sxth   r3, r0", 0xe6bf3070)]
+    #[case("push   {r4-r11, lr}", 0xe92d4ff0)]
+    #[case("svc    #0", 0xef000000)]
+    #[case("blt    0x1000", 0xbafffffe)]
+    #[case("bl     0x1010", 0xeb000002)]
+    fn test_encode(#[case] line: &str, #[case] expected: u32) {
+        assert_eq!(assemble(line), expected);
+    }
+}
@@ -0,0 +1,327 @@
+//! Turns the assembly the backends generate into a static executable, so that
+//! compiling does not need an external assembler and linker.
+//!
+//! Each assembler only knows the instructions and directives its backend
+//! uses, and rejects anything else.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+
+use super::elf::{self, Layout, Machine};
+
+pub mod aarch32;
+pub mod x86_64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+/// A line of assembly, as far as laying out the program goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Statement<I> {
+    Section(Section),
+    Label(String),
+    /// Bytes to copy as they are.
+    Bytes(Vec<u8>),
+    /// Zeroed bytes.
+    Space(usize),
+    /// Pads with zeros to a multiple of this many bytes.
+    Align(usize),
+    Instruction(I),
+}
+
+/// An instruction set, with the syntax its backend writes.
+pub(crate) trait Isa {
+    type Instruction;
+
+    const MACHINE: Machine;
+
+    /// Parses a whole program. Errors name the line they are on.
+    fn parse(source: &str) -> Result<Vec<Statement<Self::Instruction>>>;
+
+    /// Encodes `instruction` at `address`. The length of the encoding must not
+    /// depend on the values of symbols, which are all 0 while the program is
+    /// laid out.
+    fn encode(instruction: &Self::Instruction, address: u64, symbols: &Symbols) -> Result<Vec<u8>>;
+}
+
+/// The addresses of labels, or none while the program is laid out.
+#[derive(Debug, Default)]
+pub(crate) struct Symbols(Option<HashMap<String, u64>>);
+
+impl Symbols {
+    pub(crate) fn get(&self, name: &str) -> Result<u64> {
+        match &self.0 {
+            Some(symbols) => symbols
+                .get(name)
+                .copied()
+                .ok_or(anyhow!("Undefined symbol {name}")),
+            None => Ok(0),
+        }
+    }
+}
+
+/// A constant, optionally added to the address of a symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Expression {
+    pub(crate) symbol: Option<String>,
+    pub(crate) constant: i64,
+}
+
+impl Expression {
+    /// Parses sums and differences of decimal or hexadecimal numbers, quoted
+    /// characters and at most one symbol, which must be added.
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let mut expression = Self {
+            symbol: None,
+            constant: 0,
+        };
+        let mut rest = text.trim();
+        let mut negative = false;
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('-') {
+                negative = !negative;
+                rest = after.trim_start();
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix('+') {
+                rest = after.trim_start();
+                continue;
+            }
+
+            let (term, after) = match rest.strip_prefix('\'') {
+                Some(quoted) => {
+                    let mut chars = quoted.chars();
+                    let ch = chars.next().ok_or(anyhow!("Unterminated character"))?;
+                    let after = chars
+                        .as_str()
+                        .strip_prefix('\'')
+                        .ok_or(anyhow!("Unterminated character"))?;
+                    (Term::Number(ch as i64), after)
+                }
+                None => {
+                    let end = rest.find(['+', '-', ' ']).unwrap_or(rest.len());
+                    (Term::parse(&rest[..end])?, &rest[end..])
+                }
+            };
+
+            match term {
+                Term::Number(value) if negative => {
+                    expression.constant = expression.constant.wrapping_sub(value)
+                }
+                Term::Number(value) => {
+                    expression.constant = expression.constant.wrapping_add(value)
+                }
+                Term::Symbol(_) if negative || expression.symbol.is_some() => {
+                    bail!("Unsupported expression {text}")
+                }
+                Term::Symbol(symbol) => expression.symbol = Some(symbol),
+            }
+            negative = false;
+            rest = after.trim_start();
+        }
+
+        Ok(expression)
+    }
+
+    pub(crate) fn value(&self, symbols: &Symbols) -> Result<i64> {
+        match &self.symbol {
+            Some(symbol) => Ok(symbols.get(symbol)? as i64 + self.constant),
+            None => Ok(self.constant),
+        }
+    }
+
+    /// The value, if it does not involve a symbol.
+    pub(crate) fn constant(&self) -> Option<i64> {
+        self.symbol.is_none().then_some(self.constant)
+    }
+}
+
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+impl Term {
+    fn parse(text: &str) -> Result<Self> {
+        // Numbers above `i64::MAX` are the bits of negative ones, as in
+        // unsigned cells of 64 bits.
+        let number = match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => text.parse(),
+        }
+        .map(|number| number as i64)
+        .or_else(|_| text.parse());
+
+        match number {
+            Ok(number) => Ok(Term::Number(number)),
+            Err(_)
+                if text
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
+                    && text.starts_with(|ch: char| !ch.is_ascii_digit()) =>
+            {
+                Ok(Term::Symbol(text.to_string()))
+            }
+            Err(_) => bail!("Invalid number or symbol {text}"),
+        }
+    }
+}
+
+/// Splits `text` at the commas that are not inside brackets, braces or
+/// quotes.
+pub(crate) fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = vec![];
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+
+    for (i, ch) in text.char_indices() {
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        operands.push(text[start..].trim());
+    }
+
+    operands
+}
+
+/// Assembles `source` into a static executable that starts at `_start`.
+pub fn assemble<A: Isa>(source: &str) -> Result<Vec<u8>> {
+    let statements = A::parse(source)?;
+
+    // Encodings have the same length whatever the symbols are, so laying the
+    // program out with every symbol at 0 gives every label its offset.
+    let mut offsets = HashMap::new();
+    let mut section = Section::Text;
+    let mut sizes = [0u64; 3];
+    for statement in &statements {
+        let size = &mut sizes[section as usize];
+        match statement {
+            Statement::Section(next) => section = *next,
+            Statement::Label(label) => {
+                if offsets.insert(label.clone(), (section, *size)).is_some() {
+                    bail!("Duplicate label {label}");
+                }
+            }
+            Statement::Bytes(bytes) => *size += bytes.len() as u64,
+            Statement::Space(length) => *size += *length as u64,
+            Statement::Align(alignment) => *size = size.next_multiple_of(*alignment as u64),
+            Statement::Instruction(instruction) => {
+                *size += A::encode(instruction, *size, &Symbols(None))?.len() as u64
+            }
+        }
+    }
+
+    let layout = Layout::new(
+        A::MACHINE,
+        sizes[Section::Text as usize] as usize,
+        sizes[Section::Data as usize] as usize,
+        sizes[Section::Bss as usize] as usize,
+    );
+    let base = |section| match section {
+        Section::Text => layout.text,
+        Section::Data => layout.data,
+        Section::Bss => layout.bss,
+    };
+    let symbols = Symbols(Some(
+        offsets
+            .into_iter()
+            .map(|(label, (section, offset))| (label, base(section) + offset))
+            .collect(),
+    ));
+
+    let (mut text, mut data) = (vec![], vec![]);
+    let mut section = Section::Text;
+    for statement in &statements {
+        let bytes = match section {
+            Section::Text => &mut text,
+            Section::Data => &mut data,
+            Section::Bss => match statement {
+                Statement::Section(next) => {
+                    section = *next;
+                    continue;
+                }
+                Statement::Label(_) | Statement::Space(_) | Statement::Align(_) => continue,
+                _ => bail!("Only space can be reserved in .bss"),
+            },
+        };
+
+        match statement {
+            Statement::Section(next) => section = *next,
+            Statement::Label(_) => (),
+            Statement::Bytes(more) => bytes.extend_from_slice(more),
+            Statement::Space(length) => bytes.resize(bytes.len() + length, 0),
+            Statement::Align(alignment) => {
+                bytes.resize(bytes.len().next_multiple_of(*alignment), 0)
+            }
+            Statement::Instruction(instruction) => {
+                let address = base(section) + bytes.len() as u64;
+                bytes.extend(A::encode(instruction, address, &symbols)?);
+            }
+        }
+    }
+
+    let entry = symbols
+        .get("_start")
+        .context("The program has no entry point")?;
+    Ok(elf::write(&layout, &text, &data, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("1 + 2 - 4", None, -1)]
+    #[case("tape + 0x10 - 'a'", Some("tape"), 16 - 97)]
+    #[case("- 0x8000000000000000", None, i64::MIN)]
+    fn test_expression(#[case] text: &str, #[case] symbol: Option<&str>, #[case] constant: i64) {
+        let expression = Expression::parse(text).unwrap();
+
+        assert_eq!(expression.symbol.as_deref(), symbol);
+        assert_eq!(expression.constant, constant);
+    }
+
+    #[rstest]
+    #[case("tape - other")]
+    #[case("tape + other")]
+    #[case("(1)")]
+    fn test_expression_unsupported(#[case] text: &str) {
+        assert!(Expression::parse(text).is_err());
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = indoc::indoc! {"
+            section .text
+            global _start
+            _start:
+                mov        rax, 60
+                movzx      edi, byte [status]
+                syscall
+            section .data
+            status     db 1
+        "};
+        let executable = assemble::<x86_64::X86_64>(source).unwrap();
+        let layout = Layout::new(Machine::X86_64, 0, 0, 0);
+
+        assert_eq!(&executable[..4], b"\x7fELF");
+        assert_eq!(executable[24..32], layout.text.to_le_bytes());
+        assert_eq!(executable[0x1000], 1);
+    }
+}
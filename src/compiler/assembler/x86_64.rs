@@ -0,0 +1,723 @@
+//! The subset of nasm syntax that [`crate::compiler::arch::x86_64_linux`]
+//! writes.
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::{split_operands, Expression, Isa, Section, Statement, Symbols};
+use crate::compiler::elf::Machine;
+
+pub struct X86_64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Register {
+    number: u8,
+    /// Size in bytes.
+    size: u8,
+}
+
+impl Register {
+    fn parse(name: &str) -> Option<Self> {
+        const WIDE: [&str; 8] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+        const DOUBLE: [&str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+        const WORD: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+        const BYTE: [&str; 8] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil"];
+
+        for (names, size) in [(WIDE, 8), (DOUBLE, 4), (WORD, 2), (BYTE, 1)] {
+            if let Some(number) = names.iter().position(|&n| n == name) {
+                return Some(Self {
+                    number: number as u8,
+                    size,
+                });
+            }
+        }
+
+        let extended = name.strip_prefix('r')?;
+        let end = extended
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(extended.len());
+        let number: u8 = extended[..end]
+            .parse()
+            .ok()
+            .filter(|n| (8..16).contains(n))?;
+        let size = match &extended[end..] {
+            "" => 8,
+            "d" => 4,
+            "w" => 2,
+            "b" => 1,
+            _ => return None,
+        };
+
+        Some(Self { number, size })
+    }
+
+    /// Whether the register can only be reached with a REX prefix.
+    fn needs_rex(self) -> bool {
+        self.size == 1 && (4..8).contains(&self.number)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Memory {
+    /// Size in bytes, if given.
+    size: Option<u8>,
+    base: Option<Register>,
+    index: Option<(Register, u8)>,
+    displacement: Expression,
+}
+
+impl Memory {
+    fn parse(text: &str) -> Result<Self> {
+        let (size, rest) = match text.split_once('[') {
+            Some((size, rest)) => (size.trim(), rest),
+            None => bail!("Invalid memory operand {text}"),
+        };
+        let size = match size {
+            "" => None,
+            "byte" => Some(1),
+            "word" => Some(2),
+            "dword" => Some(4),
+            "qword" => Some(8),
+            _ => bail!("Invalid operand size {size}"),
+        };
+        let inner = rest
+            .strip_suffix(']')
+            .ok_or(anyhow!("Invalid memory operand {text}"))?;
+
+        let mut memory = Self {
+            size,
+            base: None,
+            index: None,
+            displacement: Expression::parse("0")?,
+        };
+        let mut constants = String::new();
+        let mut start = 0;
+        // Registers are always added, so terms are split at the signs.
+        for (i, ch) in inner.char_indices().chain([(inner.len(), '+')]) {
+            if ch != '+' && ch != '-' || i == 0 {
+                continue;
+            }
+            let term = inner[start..i].trim();
+            let sign = if start == 0 {
+                "+"
+            } else {
+                &inner[start - 1..start]
+            };
+            start = i + 1;
+
+            let register = match term.split_once('*') {
+                Some((register, scale)) => Register::parse(register.trim())
+                    .map(|register| (register, scale.trim().parse::<u8>())),
+                None => Register::parse(term).map(|register| (register, Ok(1))),
+            };
+            match register {
+                Some((register, scale)) if sign == "+" => {
+                    let scale = scale?;
+                    if scale == 1 && memory.base.is_none() {
+                        memory.base = Some(register);
+                    } else if memory.index.is_none() && [1, 2, 4, 8].contains(&scale) {
+                        memory.index = Some((register, scale));
+                    } else {
+                        bail!("Invalid memory operand {text}");
+                    }
+                }
+                Some(_) => bail!("Invalid memory operand {text}"),
+                None => constants.push_str(&format!(" {sign} {term}")),
+            }
+        }
+        if !constants.is_empty() {
+            memory.displacement = Expression::parse(&constants)?;
+        }
+
+        Ok(memory)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    Register(Register),
+    Memory(Memory),
+    Immediate(Expression),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self> {
+        if text.contains('[') && !text.starts_with('\'') {
+            Ok(Operand::Memory(Memory::parse(text)?))
+        } else if let Some(register) = Register::parse(text) {
+            Ok(Operand::Register(register))
+        } else {
+            Ok(Operand::Immediate(Expression::parse(text)?))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// `rep` or `repne`, for string instructions.
+    prefix: Option<String>,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+/// The register or memory operand of a ModRM byte.
+enum Rm<'a> {
+    Register(Register),
+    Memory(&'a Memory),
+}
+
+/// Builds one instruction.
+struct Encoder<'a> {
+    bytes: Vec<u8>,
+    symbols: &'a Symbols,
+}
+
+impl Encoder<'_> {
+    /// Emits prefixes, `opcode` and the ModRM byte with `reg` in its middle
+    /// field, for an operation on `size` bytes.
+    fn modrm(&mut self, size: u8, opcode: &[u8], reg: u8, rm: Rm, force_rex: bool) -> Result<()> {
+        let (base, index) = match &rm {
+            Rm::Register(register) => (Some(register.number), None),
+            Rm::Memory(memory) => (
+                memory.base.map(|base| base.number),
+                memory.index.map(|(index, _)| index.number),
+            ),
+        };
+        let rex = (u8::from(size == 8) << 3)
+            | (u8::from(reg >= 8) << 2)
+            | (u8::from(index.is_some_and(|index| index >= 8)) << 1)
+            | u8::from(base.is_some_and(|base| base >= 8));
+
+        if size == 2 {
+            self.bytes.push(0x66);
+        }
+        if rex != 0 || force_rex {
+            self.bytes.push(0x40 | rex);
+        }
+        self.bytes.extend_from_slice(opcode);
+
+        let reg = (reg & 7) << 3;
+        let memory = match rm {
+            Rm::Register(register) => {
+                self.bytes.push(0xc0 | reg | (register.number & 7));
+                return Ok(());
+            }
+            Rm::Memory(memory) => memory,
+        };
+
+        let displacement = memory.displacement.value(self.symbols)?;
+        let constant = memory.displacement.constant();
+        let scale = memory
+            .index
+            .map_or(0, |(_, scale)| scale.trailing_zeros() as u8);
+        if memory.index.is_some_and(|(index, _)| index.number == 4) {
+            bail!("rsp cannot be an index");
+        }
+        let index = memory.index.map_or(4, |(index, _)| index.number & 7);
+
+        let Some(base) = memory.base else {
+            // An absolute address needs a SIB byte, as ModRM alone would make
+            // it relative to rip.
+            self.bytes
+                .extend([reg | 0b100, (scale << 6) | (index << 3) | 0b101]);
+            return self.immediate(displacement, 4);
+        };
+        let mode = match constant {
+            Some(0) if base.number & 7 != 5 => 0b00,
+            Some(constant) if i8::try_from(constant).is_ok() => 0b01,
+            _ => 0b10,
+        };
+        if memory.index.is_some() || base.number & 7 == 4 {
+            self.bytes.push((mode << 6) | reg | 0b100);
+            self.bytes
+                .push((scale << 6) | (index << 3) | (base.number & 7));
+        } else {
+            self.bytes.push((mode << 6) | reg | (base.number & 7));
+        }
+
+        match mode {
+            0b00 => Ok(()),
+            0b01 => self.immediate(displacement, 1),
+            _ => self.immediate(displacement, 4),
+        }
+    }
+
+    /// Emits `value` in `size` bytes. Values may be given signed or unsigned,
+    /// except that 8-byte operations sign-extend a 4-byte immediate.
+    fn immediate(&mut self, value: i64, size: u8) -> Result<()> {
+        let fits = match size {
+            1 => (-0x80..=0xff).contains(&value),
+            2 => (-0x8000..=0xffff).contains(&value),
+            4 => (i32::MIN as i64..=u32::MAX as i64).contains(&value),
+            _ => true,
+        };
+        if !fits {
+            bail!("Immediate {value} does not fit in {size} bytes");
+        }
+
+        self.bytes
+            .extend_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+}
+
+/// The condition code of a conditional jump.
+fn condition(mnemonic: &str) -> Option<u8> {
+    Some(match mnemonic {
+        "jo" => 0x0,
+        "jno" => 0x1,
+        "jb" | "jc" | "jnae" => 0x2,
+        "jae" | "jnb" | "jnc" => 0x3,
+        "je" | "jz" => 0x4,
+        "jne" | "jnz" => 0x5,
+        "jbe" | "jna" => 0x6,
+        "ja" | "jnbe" => 0x7,
+        "js" => 0x8,
+        "jns" => 0x9,
+        "jl" | "jnge" => 0xc,
+        "jge" | "jnl" => 0xd,
+        "jle" | "jng" => 0xe,
+        "jg" | "jnle" => 0xf,
+        _ => return None,
+    })
+}
+
+/// The operation number of the arithmetic instructions that share their
+/// encodings.
+fn arithmetic(mnemonic: &str) -> Option<u8> {
+    ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"]
+        .iter()
+        .position(|&op| op == mnemonic)
+        .map(|op| op as u8)
+}
+
+/// Parses a `db` list of quoted strings and numbers.
+fn bytes(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    for item in split_operands(text) {
+        match item
+            .strip_prefix('"')
+            .and_then(|item| item.strip_suffix('"'))
+        {
+            Some(string) => bytes.extend_from_slice(string.as_bytes()),
+            None => bytes.push(u8::try_from(
+                Expression::parse(item)?.constant.rem_euclid(256),
+            )?),
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn parse_line(line: &str) -> Result<Vec<Statement<Instruction>>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with(';') {
+        return Ok(vec![]);
+    }
+    if let Some(label) = line.strip_suffix(':') {
+        return Ok(vec![Statement::Label(label.to_string())]);
+    }
+
+    let (first, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let (second, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let after = after.trim();
+
+    match (first, second) {
+        ("section", ".text") => return Ok(vec![Statement::Section(Section::Text)]),
+        ("section", ".data") => return Ok(vec![Statement::Section(Section::Data)]),
+        ("section", ".bss") => return Ok(vec![Statement::Section(Section::Bss)]),
+        ("global", _) => return Ok(vec![]),
+        (label, "db") => {
+            return Ok(vec![
+                Statement::Label(label.to_string()),
+                Statement::Bytes(bytes(after)?),
+            ])
+        }
+        (label, "resb" | "resw" | "resd" | "resq") => {
+            let unit = match second {
+                "resb" => 1,
+                "resw" => 2,
+                "resd" => 4,
+                _ => 8,
+            };
+            let count = Expression::parse(after)?
+                .constant()
+                .ok_or(anyhow!("Reserved space must be a constant"))?;
+            return Ok(vec![
+                Statement::Label(label.to_string()),
+                Statement::Space(usize::try_from(count)? * unit),
+            ]);
+        }
+        _ => (),
+    }
+
+    let (prefix, mnemonic, operands) = match first {
+        "rep" | "repne" | "repe" => (Some(first.to_string()), second, after),
+        _ => (None, first, rest),
+    };
+
+    Ok(vec![Statement::Instruction(Instruction {
+        prefix,
+        mnemonic: mnemonic.to_string(),
+        operands: split_operands(operands)
+            .into_iter()
+            .map(Operand::parse)
+            .collect::<Result<_>>()?,
+    })])
+}
+
+impl Isa for X86_64 {
+    type Instruction = Instruction;
+
+    const MACHINE: Machine = Machine::X86_64;
+
+    fn parse(source: &str) -> Result<Vec<Statement<Instruction>>> {
+        let mut statements = vec![];
+        for (number, line) in source.lines().enumerate() {
+            statements.extend(
+                parse_line(line)
+                    .with_context(|| format!("line {}: {}", number + 1, line.trim()))?,
+            );
+        }
+
+        Ok(statements)
+    }
+
+    fn encode(instruction: &Instruction, address: u64, symbols: &Symbols) -> Result<Vec<u8>> {
+        encode(instruction, address, symbols).with_context(|| {
+            format!(
+                "Could not encode {} {:?}",
+                instruction.mnemonic, instruction.operands
+            )
+        })
+    }
+}
+
+fn encode(instruction: &Instruction, address: u64, symbols: &Symbols) -> Result<Vec<u8>> {
+    use Operand::{Immediate as I, Memory as M, Register as R};
+
+    let mut e = Encoder {
+        bytes: vec![],
+        symbols,
+    };
+    let mnemonic = instruction.mnemonic.as_str();
+    let operands = instruction.operands.as_slice();
+
+    // Operations on bytes use the opcode before the one for wider operands.
+    let narrow = |size: u8| u8::from(size != 1);
+
+    match (mnemonic, operands) {
+        (_, []) => {
+            let (prefix, string) = match instruction.prefix.as_deref() {
+                Some("rep") => (Some(0xf3), true),
+                Some("repne") => (Some(0xf2), true),
+                Some(prefix) => bail!("Unsupported prefix {prefix}"),
+                None => (None, false),
+            };
+            e.bytes.extend(prefix);
+
+            let bytes: &[u8] = match mnemonic {
+                "movsb" => &[0xa4],
+                "scasb" => &[0xae],
+                "scasw" => &[0x66, 0xaf],
+                "scasd" => &[0xaf],
+                "scasq" => &[0x48, 0xaf],
+                "ret" if !string => &[0xc3],
+                "syscall" if !string => &[0x0f, 0x05],
+                "std" if !string => &[0xfd],
+                "cld" if !string => &[0xfc],
+                _ => bail!("Unsupported instruction"),
+            };
+            // The operand size prefix goes before the repeat prefix.
+            if bytes[0] == 0x66 {
+                e.bytes.insert(0, 0x66);
+                e.bytes.extend_from_slice(&bytes[1..]);
+            } else {
+                e.bytes.extend_from_slice(bytes);
+            }
+        }
+        ("jmp" | "call", [I(target)]) => {
+            e.bytes.push(if mnemonic == "jmp" { 0xe9 } else { 0xe8 });
+            let next = address as i64 + 5;
+            e.immediate((target.value(symbols)? - next) as i32 as i64, 4)?;
+        }
+        (_, [I(target)]) if condition(mnemonic).is_some() => {
+            e.bytes.extend([0x0f, 0x80 | condition(mnemonic).unwrap()]);
+            let next = address as i64 + 6;
+            e.immediate((target.value(symbols)? - next) as i32 as i64, 4)?;
+        }
+        ("mov", [R(dst), R(src)]) if dst.size == src.size => e.modrm(
+            dst.size,
+            &[0x88 | narrow(dst.size)],
+            src.number,
+            Rm::Register(*dst),
+            dst.needs_rex() || src.needs_rex(),
+        )?,
+        ("mov", [M(dst), R(src)]) => e.modrm(
+            src.size,
+            &[0x88 | narrow(src.size)],
+            src.number,
+            Rm::Memory(dst),
+            src.needs_rex(),
+        )?,
+        ("mov", [R(dst), M(src)]) => e.modrm(
+            dst.size,
+            &[0x8a | narrow(dst.size)],
+            dst.number,
+            Rm::Memory(src),
+            dst.needs_rex(),
+        )?,
+        ("mov", [R(dst), I(value)]) => {
+            let constant = value.constant();
+            let value = value.value(symbols)?;
+            let rex = u8::from(dst.number >= 8) | u8::from(dst.needs_rex()) << 6;
+
+            match dst.size {
+                // Writing the low half of a register clears the high half, so
+                // addresses and other small values take the shorter encoding.
+                8 if constant.is_none_or(|value| u32::try_from(value).is_ok()) => {
+                    if rex != 0 {
+                        e.bytes.push(0x40 | rex);
+                    }
+                    e.bytes.push(0xb8 | (dst.number & 7));
+                    e.immediate(value, 4)?;
+                }
+                8 if i32::try_from(value).is_ok() => {
+                    e.modrm(8, &[0xc7], 0, Rm::Register(*dst), false)?;
+                    e.immediate(value, 4)?;
+                }
+                8 => {
+                    e.bytes.push(0x48 | rex);
+                    e.bytes.push(0xb8 | (dst.number & 7));
+                    e.immediate(value, 8)?;
+                }
+                size => {
+                    if size == 2 {
+                        e.bytes.push(0x66);
+                    }
+                    if rex != 0 {
+                        e.bytes.push(0x40 | rex);
+                    }
+                    e.bytes
+                        .push(if size == 1 { 0xb0 } else { 0xb8 } | (dst.number & 7));
+                    e.immediate(value, size)?;
+                }
+            }
+        }
+        ("mov", [M(dst), I(value)]) => {
+            let size = dst.size.ok_or(anyhow!("Operand size is missing"))?;
+            e.modrm(size, &[0xc6 | narrow(size)], 0, Rm::Memory(dst), false)?;
+            immediate_for(&mut e, value.value(symbols)?, size)?;
+        }
+        (_, [dst, src]) if arithmetic(mnemonic).is_some() => {
+            let op = arithmetic(mnemonic).unwrap();
+            match (dst, src) {
+                (R(dst), R(src)) => e.modrm(
+                    dst.size,
+                    &[op << 3 | narrow(dst.size)],
+                    src.number,
+                    Rm::Register(*dst),
+                    dst.needs_rex() || src.needs_rex(),
+                )?,
+                (M(dst), R(src)) => e.modrm(
+                    src.size,
+                    &[op << 3 | narrow(src.size)],
+                    src.number,
+                    Rm::Memory(dst),
+                    src.needs_rex(),
+                )?,
+                (R(dst), M(src)) => e.modrm(
+                    dst.size,
+                    &[op << 3 | 0x02 | narrow(dst.size)],
+                    dst.number,
+                    Rm::Memory(src),
+                    dst.needs_rex(),
+                )?,
+                (R(_) | M(_), I(value)) => {
+                    let (size, rm, rex) = match dst {
+                        R(dst) => (dst.size, Rm::Register(*dst), dst.needs_rex()),
+                        M(dst) => (
+                            dst.size.ok_or(anyhow!("Operand size is missing"))?,
+                            Rm::Memory(dst),
+                            false,
+                        ),
+                        I(_) => unreachable!(),
+                    };
+                    let short = value.constant().and_then(|value| i8::try_from(value).ok());
+
+                    match (size, short) {
+                        (1, _) => {
+                            e.modrm(1, &[0x80], op, rm, rex)?;
+                            e.immediate(value.value(symbols)?, 1)?;
+                        }
+                        (_, Some(short)) => {
+                            e.modrm(size, &[0x83], op, rm, rex)?;
+                            e.immediate(short as i64, 1)?;
+                        }
+                        _ => {
+                            e.modrm(size, &[0x81], op, rm, rex)?;
+                            immediate_for(&mut e, value.value(symbols)?, size)?;
+                        }
+                    }
+                }
+                _ => bail!("Unsupported operands"),
+            }
+        }
+        ("movzx" | "movsx", [R(dst), src]) => {
+            let (size, rm, rex) = match src {
+                R(src) => (src.size, Rm::Register(*src), src.needs_rex()),
+                M(src) => (
+                    src.size.ok_or(anyhow!("Operand size is missing"))?,
+                    Rm::Memory(src),
+                    false,
+                ),
+                I(_) => bail!("Unsupported operands"),
+            };
+            let opcode = match (mnemonic, size) {
+                ("movzx", 1) => 0xb6,
+                ("movzx", 2) => 0xb7,
+                ("movsx", 1) => 0xbe,
+                ("movsx", 2) => 0xbf,
+                _ => bail!("Unsupported operand size"),
+            };
+            e.modrm(dst.size, &[0x0f, opcode], dst.number, rm, rex)?;
+        }
+        ("movsxd", [R(dst), M(src)]) => {
+            e.modrm(8, &[0x63], dst.number, Rm::Memory(src), false)?;
+        }
+        ("lea", [R(dst), M(src)]) => {
+            e.modrm(dst.size, &[0x8d], dst.number, Rm::Memory(src), false)?;
+        }
+        ("test", [dst, R(src)]) => {
+            let rm = match dst {
+                R(dst) => Rm::Register(*dst),
+                M(dst) => Rm::Memory(dst),
+                I(_) => bail!("Unsupported operands"),
+            };
+            e.modrm(
+                src.size,
+                &[0x84 | narrow(src.size)],
+                src.number,
+                rm,
+                src.needs_rex(),
+            )?;
+        }
+        ("imul", [R(dst), R(src)]) => {
+            e.modrm(
+                dst.size,
+                &[0x0f, 0xaf],
+                dst.number,
+                Rm::Register(*src),
+                false,
+            )?;
+        }
+        ("imul", [R(dst), R(src), I(factor)]) => {
+            let factor = factor.value(symbols)?;
+            match i8::try_from(factor) {
+                Ok(factor) => {
+                    e.modrm(dst.size, &[0x6b], dst.number, Rm::Register(*src), false)?;
+                    e.immediate(factor as i64, 1)?;
+                }
+                Err(_) => {
+                    e.modrm(dst.size, &[0x69], dst.number, Rm::Register(*src), false)?;
+                    immediate_for(&mut e, factor, dst.size)?;
+                }
+            }
+        }
+        ("inc" | "dec" | "neg" | "not" | "div", [operand]) => {
+            let (size, rm, rex) = match operand {
+                R(register) => (register.size, Rm::Register(*register), register.needs_rex()),
+                M(memory) => (
+                    memory.size.ok_or(anyhow!("Operand size is missing"))?,
+                    Rm::Memory(memory),
+                    false,
+                ),
+                I(_) => bail!("Unsupported operands"),
+            };
+            let (opcode, reg) = match mnemonic {
+                "inc" => (0xfe, 0),
+                "dec" => (0xfe, 1),
+                "not" => (0xf6, 2),
+                "neg" => (0xf6, 3),
+                _ => (0xf6, 6),
+            };
+            e.modrm(size, &[opcode | narrow(size)], reg, rm, rex)?;
+        }
+        ("shl" | "shr" | "sar", [R(dst), I(count)]) => {
+            let reg = match mnemonic {
+                "shl" => 4,
+                "shr" => 5,
+                _ => 7,
+            };
+            e.modrm(
+                dst.size,
+                &[0xc0 | narrow(dst.size)],
+                reg,
+                Rm::Register(*dst),
+                dst.needs_rex(),
+            )?;
+            e.immediate(count.value(symbols)?, 1)?;
+        }
+        _ => bail!("Unsupported instruction"),
+    }
+
+    Ok(e.bytes)
+}
+
+/// Emits the immediate of an operation on `size` bytes, which is at most 4
+/// bytes long, sign-extended for 8-byte operations.
+fn immediate_for(e: &mut Encoder, value: i64, size: u8) -> Result<()> {
+    match size {
+        8 if i32::try_from(value).is_err() => {
+            bail!("Immediate {value} does not fit in a sign-extended 4 bytes")
+        }
+        8 => e.immediate(value, 4),
+        size => e.immediate(value, size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    fn assemble(line: &str) -> Vec<u8> {
+        let Statement::Instruction(instruction) = parse_line(line).unwrap().remove(0) else {
+            panic!("{line} is not an instruction");
+        };
+        X86_64::encode(&instruction, 0x1000, &Symbols::default()).unwrap()
+    }
+
+    #[rstest]
+    #[case("mov        r12, 0", &[0x41, 0xbc, 0, 0, 0, 0])]
+    #[case("mov        r8, -1", &[0x49, 0xc7, 0xc0, 0xff, 0xff, 0xff, 0xff])]
+    #[case("mov        rsi, 68719476736", &[0x48, 0xbe, 0, 0, 0, 0, 0x10, 0, 0, 0])]
+    #[case("add        byte [rbx + r12], 200", &[0x42, 0x80, 0x04, 0x23, 0xc8])]
+    #[case("mov        r11d, 4294967295", &[0x41, 0xbb, 0xff, 0xff, 0xff, 0xff])]
+    #[case("add        dword [rbx + r12], r11d", &[0x46, 0x01, 0x1c, 0x23])]
+    #[case("sub        qword [rbx + r12 - 16], 1", &[0x4a, 0x83, 0x6c, 0x23, 0xf0, 0x01])]
+    #[case("lea        r13, [r12 + 4]", &[0x4d, 0x8d, 0x6c, 0x24, 0x04])]
+    #[case("movzx      r11, word [rbx + r12]", &[0x4e, 0x0f, 0xb7, 0x1c, 0x23])]
+    #[case("movsx      rax, byte [r11 + r9]", &[0x4b, 0x0f, 0xbe, 0x04, 0x0b])]
+    #[case("mov        byte [rdi], '-'", &[0xc6, 0x07, 0x2d])]
+    #[case("mov        [rsi], dl", &[0x88, 0x16])]
+    #[case("imul       r11, r11, 300", &[0x4d, 0x69, 0xdb, 0x2c, 0x01, 0, 0])]
+    #[case("sar        r8, 3", &[0x49, 0xc1, 0xf8, 0x03])]
+    #[case("repne      scasw", &[0x66, 0xf2, 0xaf])]
+    #[case("jae        0x1000", &[0x0f, 0x83, 0xfa, 0xff, 0xff, 0xff])]
+    #[case("mov        byte [rdi], '['", &[0xc6, 0x07, 0x5b])]
+    fn test_encode(#[case] line: &str, #[case] expected: &[u8]) {
+        assert_eq!(assemble(line), expected);
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(
+            parse_line("E0          db \"Error: x\", 10").unwrap(),
+            [
+                Statement::Label("E0".to_string()),
+                Statement::Bytes(b"Error: x\n".to_vec())
+            ]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Writes static ELF executables for the assembler.
+
+/// The machines a static executable can be written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    X86_64,
+    Arm,
+}
+
+impl Machine {
+    /// Whether the executable is a 64-bit ELF file.
+    fn wide(self) -> bool {
+        match self {
+            Machine::X86_64 => true,
+            Machine::Arm => false,
+        }
+    }
+
+    /// The address the executable is loaded at, the same as the default of
+    /// `ld` for the machine.
+    fn base(self) -> u64 {
+        match self {
+            Machine::X86_64 => 0x40_0000,
+            Machine::Arm => 0x1_0000,
+        }
+    }
+
+    fn number(self) -> u16 {
+        match self {
+            Machine::X86_64 => 62,
+            Machine::Arm => 40,
+        }
+    }
+
+    fn flags(self) -> u32 {
+        match self {
+            Machine::X86_64 => 0,
+            // Version 5 of the EABI, with software floating point.
+            Machine::Arm => 0x0500_0200,
+        }
+    }
+
+    fn header_size(self) -> u64 {
+        if self.wide() {
+            64
+        } else {
+            52
+        }
+    }
+
+    fn program_header_size(self) -> u64 {
+        if self.wide() {
+            56
+        } else {
+            32
+        }
+    }
+
+    fn section_header_size(self) -> u64 {
+        if self.wide() {
+            64
+        } else {
+            40
+        }
+    }
+}
+
+const PAGE: u64 = 0x1000;
+
+/// Names of the sections, in the order of their headers after the null one.
+const NAMES: &[u8] = b"\0.text\0.data\0.bss\0.shstrtab\0";
+
+/// Where each section of an executable goes. Code is mapped read-only along
+/// with the headers, and data and bss writable on the pages after it.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    machine: Machine,
+    /// Address of the code.
+    pub text: u64,
+    /// Address of the initialized data.
+    pub data: u64,
+    /// Address of the zeroed data.
+    pub bss: u64,
+    text_size: u64,
+    data_size: u64,
+    bss_size: u64,
+}
+
+impl Layout {
+    pub fn new(machine: Machine, text: usize, data: usize, bss: usize) -> Self {
+        let headers = machine.header_size() + 2 * machine.program_header_size();
+        let text_offset = headers.next_multiple_of(16);
+        let data_offset = (text_offset + text as u64).next_multiple_of(PAGE);
+        let data_address = machine.base() + data_offset;
+
+        Self {
+            machine,
+            text: machine.base() + text_offset,
+            data: data_address,
+            bss: (data_address + data as u64).next_multiple_of(16),
+            text_size: text as u64,
+            data_size: data as u64,
+            bss_size: bss as u64,
+        }
+    }
+
+    fn text_offset(&self) -> u64 {
+        self.text - self.machine.base()
+    }
+
+    fn data_offset(&self) -> u64 {
+        self.data - self.machine.base()
+    }
+}
+
+/// Appends little-endian fields, of the width of an address where the
+/// format depends on the class of the file.
+struct Writer {
+    bytes: Vec<u8>,
+    wide: bool,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn word(&mut self, value: u64) {
+        match self.wide {
+            true => self.bytes.extend_from_slice(&value.to_le_bytes()),
+            false => self.u32(value as u32),
+        }
+    }
+
+    fn pad(&mut self, length: u64) {
+        self.bytes.resize(length as usize, 0);
+    }
+
+    /// A loadable segment of `file` bytes from `offset`, mapped at `address`
+    /// and taking `memory` bytes there.
+    fn segment(&mut self, offset: u64, address: u64, file: u64, memory: u64, flags: u32) {
+        self.u32(1);
+        if self.wide {
+            self.u32(flags);
+        }
+        self.word(offset);
+        self.word(address);
+        self.word(address);
+        self.word(file);
+        self.word(memory);
+        if !self.wide {
+            self.u32(flags);
+        }
+        self.word(PAGE);
+    }
+
+    fn section(&mut self, name: u32, kind: u32, flags: u64, address: u64, offset: u64, size: u64) {
+        self.u32(name);
+        self.u32(kind);
+        self.word(flags);
+        self.word(address);
+        self.word(offset);
+        self.word(size);
+        self.u32(0);
+        self.u32(0);
+        self.word(match kind {
+            0 => 0,
+            3 => 1,
+            _ => 16,
+        });
+        self.word(0);
+    }
+}
+
+/// A static executable of `text` and `data` placed as in `layout`, starting
+/// at `entry`.
+pub fn write(layout: &Layout, text: &[u8], data: &[u8], entry: u64) -> Vec<u8> {
+    let machine = layout.machine;
+    let mut file = Writer {
+        bytes: vec![],
+        wide: machine.wide(),
+    };
+
+    let names_offset = layout.data_offset() + layout.data_size;
+    let sections_offset = (names_offset + NAMES.len() as u64).next_multiple_of(8);
+
+    file.bytes.extend_from_slice(b"\x7fELF");
+    file.bytes
+        .extend_from_slice(&[if machine.wide() { 2 } else { 1 }, 1, 1]);
+    file.pad(16);
+    file.u16(2);
+    file.u16(machine.number());
+    file.u32(1);
+    file.word(entry);
+    file.word(machine.header_size());
+    file.word(sections_offset);
+    file.u32(machine.flags());
+    file.u16(machine.header_size() as u16);
+    file.u16(machine.program_header_size() as u16);
+    file.u16(2);
+    file.u16(machine.section_header_size() as u16);
+    file.u16(5);
+    file.u16(4);
+
+    let end = layout.bss + layout.bss_size;
+    file.segment(
+        0,
+        machine.base(),
+        layout.text_offset() + layout.text_size,
+        layout.text_offset() + layout.text_size,
+        5,
+    );
+    file.segment(
+        layout.data_offset(),
+        layout.data,
+        layout.data_size,
+        end - layout.data,
+        6,
+    );
+
+    file.pad(layout.text_offset());
+    file.bytes.extend_from_slice(text);
+    file.pad(layout.data_offset());
+    file.bytes.extend_from_slice(data);
+    file.bytes.extend_from_slice(NAMES);
+    file.pad(sections_offset);
+
+    let (text_name, data_name, bss_name, names_name) = (1, 7, 13, 18);
+    file.section(0, 0, 0, 0, 0, 0);
+    file.section(
+        text_name,
+        1,
+        6,
+        layout.text,
+        layout.text_offset(),
+        layout.text_size,
+    );
+    file.section(
+        data_name,
+        1,
+        3,
+        layout.data,
+        layout.data_offset(),
+        layout.data_size,
+    );
+    file.section(
+        bss_name,
+        8,
+        3,
+        layout.bss,
+        layout.data_offset() + layout.data_size,
+        layout.bss_size,
+    );
+    file.section(names_name, 3, 0, 0, names_offset, NAMES.len() as u64);
+
+    file.bytes
+}
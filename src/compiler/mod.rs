@@ -1,10 +1,139 @@
 use anyhow::{anyhow, bail, Result};
-use std::{fs::File, io::Write, path::Path, process::Command};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    process::Command,
+};
 
-use self::arch::Target;
+use self::{
+    arch::Target,
+    assembler::{aarch32::Aarch32, x86_64::X86_64},
+};
+use crate::{
+    cell::{CellWidth, Eof, Tape},
+    ir::Instruction,
+    span::Spanned,
+};
 
 pub mod arch;
+pub(crate) mod assembler;
+pub(crate) mod elf;
 
+/// Bytes left inaccessible on either side of a tape that grows, so that
+/// running off it faults instead of reaching other memory.
+pub(crate) const GUARD: usize = 1 << 20;
+
+/// What a program prints when it cannot reserve its tape, before exiting
+/// with status 1.
+pub(crate) const NO_TAPE: &str = "Error: Could not reserve memory for the tape";
+
+/// Settings that shape the generated code, shared by every target.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Number of cells in memory. Unused unless the tape is fixed.
+    pub memory: usize,
+    /// Whether the tape grows. The compiled program reserves a region of
+    /// address space instead, which the kernel fills with zeroed pages as
+    /// they are touched.
+    pub tape: Tape,
+    pub cell: CellWidth,
+    /// Whether cells are treated as signed. Wrapping arithmetic is identical
+    /// either way, so this only matters without `wrapping`.
+    pub signed: bool,
+    /// Whether cell values wrap around. Otherwise, a program that takes a
+    /// cell out of its range prints where to stderr and exits with the
+    /// status of [`crate::interpreter::InterpreterError::IntegerOverflow`] or
+    /// [`crate::interpreter::InterpreterError::IntegerUnderflow`].
+    pub wrapping: bool,
+    /// What `,` stores once input is exhausted.
+    pub eof: Eof,
+    /// Whether the data pointer is checked against the edges of the tape.
+    /// A program that leaves the tape prints where to stderr and exits with
+    /// the status of [`crate::interpreter::InterpreterError::OutOfBounds`].
+    pub bounds_check: bool,
+}
+
+impl Options {
+    /// A one-line description of the memory layout, for the header comment.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} of {}-bit {} integers",
+            match self.tape {
+                Tape::Fixed => format!("{} cells", self.memory),
+                Tape::Growable => "a growable tape".to_string(),
+                Tape::Bidirectional => "a bidirectional tape".to_string(),
+            },
+            self.cell.bits(),
+            if self.signed { "signed" } else { "unsigned" }
+        ) + if self.wrapping { "" } else { ", not wrapping" }
+            + if self.bounds_check {
+                ", bounds checked"
+            } else {
+                ""
+            }
+    }
+
+    /// The size in bytes of the tape and the offset of the cell the data
+    /// pointer starts on, on a target that reserves `reserved` bytes for a
+    /// tape that grows. A bidirectional tape starts in the middle.
+    pub(crate) fn layout(&self, reserved: usize) -> (usize, usize) {
+        match self.tape {
+            Tape::Fixed => (self.memory * self.cell.bytes(), 0),
+            Tape::Growable => (reserved, 0),
+            Tape::Bidirectional => (reserved, reserved / 2),
+        }
+    }
+}
+
+/// Splits adding or subtracting `magnitude` to a cell into steps that each
+/// set the carry flag, or the overflow flag for signed cells, exactly when
+/// the cell leaves its range. `None` if every cell value would leave it.
+pub(crate) fn checked_steps(magnitude: u64, options: &Options) -> Option<Vec<u64>> {
+    let bits = options.cell.bits();
+    if bits < 64 && magnitude >> bits != 0 {
+        return None;
+    }
+
+    // A signed step above the maximum would read as negative.
+    let max = match options.signed {
+        true => u64::MAX >> (65 - bits),
+        false => u64::MAX >> (64 - bits),
+    };
+    let mut steps = vec![];
+    let mut left = magnitude;
+    while left > 0 {
+        steps.push(left.min(max));
+        left -= left.min(max);
+    }
+
+    Some(steps)
+}
+
+/// Generates assembly, or C, for `target`.
+pub fn codegen(program: &[Spanned<Instruction>], target: Target, options: &Options) -> String {
+    let codegen = match target {
+        Target::Aarch32Linux => arch::aarch32_linux::codegen,
+        Target::Aarch64Linux => arch::aarch64_linux::codegen,
+        Target::C => arch::c::codegen,
+        Target::I386Linux => arch::i386_linux::codegen,
+        Target::Riscv64Linux => arch::riscv64_linux::codegen,
+        Target::X86_64Linux => arch::x86_64_linux::codegen,
+    };
+
+    codegen(program, options)
+}
+
+/// Assembles `asm_source` into an executable named after `outfile`, in the
+/// current directory. The assembly is kept next to it as `outfile` with the
+/// target's extension if `keep_artifacts` is set.
+///
+/// The executable is written directly, unless it needs `debug` symbols or
+/// the target has no built-in assembler: then the target's external
+/// assembler and linker build it. C is built with `cc`, unless `outfile` has
+/// a `c` extension: then the C file is all that is written, at `outfile`
+/// itself rather than in the current directory.
 pub fn compile(
     asm_source: &String,
     outfile: &Path,
@@ -12,7 +141,54 @@ pub fn compile(
     debug: bool,
     target: Target,
 ) -> Result<()> {
-    let asm = outfile.with_extension("s");
+    let asm = outfile.with_extension(target.extension());
+    let bin = outfile
+        .file_stem()
+        .ok_or(anyhow!("Output file is a directory"))?;
+
+    if let (Target::C, Some("c")) = (target, outfile.extension().and_then(|e| e.to_str())) {
+        File::create(outfile)?.write_all(asm_source.as_bytes())?;
+        return Ok(());
+    }
+
+    // Debug symbols need the external toolchain, which is given the assembly.
+    let assembled = match target {
+        _ if debug => None,
+        Target::Aarch32Linux => Some(assembler::assemble::<Aarch32>(asm_source)),
+        Target::Aarch64Linux | Target::C | Target::I386Linux | Target::Riscv64Linux => None,
+        Target::X86_64Linux => Some(assembler::assemble::<X86_64>(asm_source)),
+    };
+    let Some(assembled) = assembled else {
+        return assemble_externally(asm_source, outfile, keep_artifacts, debug, target);
+    };
+
+    if keep_artifacts {
+        File::create(&asm)?.write_all(asm_source.as_bytes())?;
+    }
+
+    let executable = assembled.map_err(|err| anyhow!("Compilation error:\n{:#}", err))?;
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(bin)?
+        .write_all(&executable)?;
+
+    Ok(())
+}
+
+/// Assembles and links with the toolchain of `target`, with `debug`
+/// information if asked for.
+fn assemble_externally(
+    asm_source: &String,
+    outfile: &Path,
+    keep_artifacts: bool,
+    debug: bool,
+    target: Target,
+) -> Result<()> {
+    let asm = outfile.with_extension(target.extension());
     let obj = outfile.with_extension("o");
     let bin = outfile
         .file_stem()
@@ -21,7 +197,6 @@ pub fn compile(
     File::create(&asm)?.write_all(asm_source.as_bytes())?;
 
     // TODO: add local platform detection and set default accordingly
-    // TODO: find a way to use the platform's native assembler
 
     let assembler = match target {
         Target::Aarch32Linux => Command::new("arm-linux-gnueabi-as")
@@ -30,6 +205,37 @@ pub fn compile(
             .arg(&obj)
             .arg(&asm)
             .output(),
+        Target::Aarch64Linux => Command::new("aarch64-linux-gnu-as")
+            .args(if debug { vec!["-g"] } else { vec![] })
+            .arg("-o")
+            .arg(&obj)
+            .arg(&asm)
+            .output(),
+        Target::C => Command::new("cc")
+            .args(["-O2", "-c"])
+            .args(if debug { vec!["-g"] } else { vec![] })
+            .arg("-o")
+            .arg(&obj)
+            .arg(&asm)
+            .output(),
+        Target::I386Linux => Command::new("nasm")
+            .args(["-f", "elf32"])
+            .args(if debug {
+                vec!["-g", "-F", "dwarf"]
+            } else {
+                vec![]
+            })
+            .arg("-o")
+            .arg(&obj)
+            .arg(&asm)
+            .output(),
+        Target::Riscv64Linux => Command::new("riscv64-linux-gnu-as")
+            .arg("-march=rv64gc")
+            .args(if debug { vec!["-g"] } else { vec![] })
+            .arg("-o")
+            .arg(&obj)
+            .arg(&asm)
+            .output(),
         Target::X86_64Linux => Command::new("nasm")
             .args(["-f", "elf64"])
             .args(if debug {
@@ -47,6 +253,10 @@ pub fn compile(
             "Could not find compiler: {}: {}",
             match target {
                 Target::Aarch32Linux => "arm-linux-gnueabi-as",
+                Target::Aarch64Linux => "aarch64-linux-gnu-as",
+                Target::C => "cc",
+                Target::I386Linux => "nasm",
+                Target::Riscv64Linux => "riscv64-linux-gnu-as",
                 Target::X86_64Linux => "nasm",
             },
             err
@@ -75,6 +285,23 @@ pub fn compile(
             .arg(bin)
             .arg(&obj)
             .output(),
+        Target::Aarch64Linux => Command::new("aarch64-linux-gnu-ld")
+            .arg("-o")
+            .arg(bin)
+            .arg(&obj)
+            .output(),
+        Target::C => Command::new("cc").arg("-o").arg(bin).arg(&obj).output(),
+        Target::I386Linux => Command::new("ld")
+            .args(["-m", "elf_i386"])
+            .arg("-o")
+            .arg(bin)
+            .arg(&obj)
+            .output(),
+        Target::Riscv64Linux => Command::new("riscv64-linux-gnu-ld")
+            .arg("-o")
+            .arg(bin)
+            .arg(&obj)
+            .output(),
         Target::X86_64Linux => Command::new("ld").arg("-o").arg(bin).arg(&obj).output(),
     }
     .map_err(|err| {
@@ -82,6 +309,10 @@ pub fn compile(
             "Could not find linker: {}: {}",
             match target {
                 Target::Aarch32Linux => "arm-linux-gnueabi-ld",
+                Target::Aarch64Linux => "aarch64-linux-gnu-ld",
+                Target::C => "cc",
+                Target::I386Linux => "ld",
+                Target::Riscv64Linux => "riscv64-linux-gnu-ld",
                 Target::X86_64Linux => "ld",
             },
             err
@@ -118,3 +349,209 @@ pub fn compile(
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{interpreter::InterpreterError, span::Span};
+    use rstest::*;
+
+    /// Where backend tests put their instructions.
+    pub(crate) const SPAN: Span = Span {
+        start: 4,
+        end: 5,
+        line: 1,
+        column: 5,
+    };
+
+    /// `nodes`, each spanning [`SPAN`].
+    pub(crate) fn program(nodes: &[Instruction]) -> Vec<Spanned<Instruction>> {
+        nodes
+            .iter()
+            .map(|&node| Spanned { node, span: SPAN })
+            .collect()
+    }
+
+    /// The code a target runs when a check fails, up to the status.
+    fn trap(target: Target, status: i32) -> String {
+        match target {
+            Target::Aarch32Linux => format!("mov    r3, #{status}\n"),
+            Target::Aarch64Linux => format!("mov    x3, #{status}\n"),
+            Target::C => format!("\\n\", {status});"),
+            Target::I386Linux => format!("mov        edi, {status}\n"),
+            Target::Riscv64Linux => format!("li     a3, {status}\n"),
+            Target::X86_64Linux => format!("mov        r14, {status}\n"),
+        }
+    }
+
+    /// The first jump to a failed check, and the start of the generated
+    /// program, before which the routines it calls are defined.
+    fn start(target: Target) -> (&'static str, &'static str) {
+        match target {
+            Target::C => ("fail(", "int main(void) {"),
+            Target::Riscv64Linux => ("fail0", "_start:"),
+            _ => ("T0", "_start:"),
+        }
+    }
+
+    #[rstest]
+    #[case(Instruction::Output { offset: 2 }, InterpreterError::OutOfBounds(SPAN))]
+    #[case(Instruction::Move(-1), InterpreterError::OutOfBounds(SPAN))]
+    #[case(Instruction::Add { offset: 0, value: 1 }, InterpreterError::IntegerOverflow(SPAN))]
+    #[case(Instruction::Add { offset: 0, value: 256 }, InterpreterError::IntegerOverflow(SPAN))]
+    #[case(Instruction::Add { offset: 0, value: -1 }, InterpreterError::IntegerUnderflow(SPAN))]
+    #[case(Instruction::Clear { offset: 0 }, InterpreterError::IntegerUnderflow(SPAN))]
+    #[case(Instruction::MulAdd { offset: 1, factor: 2 }, InterpreterError::IntegerOverflow(SPAN))]
+    fn test_trap(
+        #[case] instruction: Instruction,
+        #[case] error: InterpreterError,
+        #[values(
+            Target::Aarch32Linux,
+            Target::Aarch64Linux,
+            Target::C,
+            Target::I386Linux,
+            Target::Riscv64Linux,
+            Target::X86_64Linux
+        )]
+        target: Target,
+    ) {
+        let options = Options {
+            bounds_check: true,
+            ..options(CellWidth::Bits8, true)
+        };
+        let code = codegen(&program(&[instruction]), target, &options);
+
+        assert!(code.contains(&format!("Error: {error}")), "{code}");
+        assert!(code.contains(&trap(target, error.status())), "{code}");
+    }
+
+    #[rstest]
+    #[case(Instruction::Input { offset: 2 }, "read\n", "input(&")]
+    #[case(Instruction::Output { offset: -2 }, "write\n", "putchar(")]
+    fn test_check_before_access(
+        #[case] instruction: Instruction,
+        #[case] call: &str,
+        #[case] c_call: &str,
+        #[values(
+            Target::Aarch32Linux,
+            Target::Aarch64Linux,
+            Target::C,
+            Target::I386Linux,
+            Target::Riscv64Linux,
+            Target::X86_64Linux
+        )]
+        target: Target,
+    ) {
+        let options = Options {
+            bounds_check: true,
+            ..options(CellWidth::Bits8, false)
+        };
+        let code = codegen(&program(&[instruction]), target, &options);
+        let (fail, start) = start(target);
+        let body = &code[code.find(start).unwrap()..];
+        let access = if let Target::C = target { c_call } else { call };
+
+        assert!(
+            body.find(fail).unwrap() < body.find(access).unwrap(),
+            "{code}"
+        );
+    }
+
+    /// Options for a fixed tape of unwrapped `cell`s, which backend tests
+    /// adjust as they need.
+    pub(crate) fn options(cell: CellWidth, signed: bool) -> Options {
+        Options {
+            memory: 30_000,
+            tape: Tape::Fixed,
+            cell,
+            signed,
+            wrapping: false,
+            eof: Eof::Unchanged,
+            bounds_check: false,
+        }
+    }
+
+    #[rstest]
+    #[case(Tape::Fixed, CellWidth::Bits8, (30_000, 0))]
+    #[case(Tape::Fixed, CellWidth::Bits64, (240_000, 0))]
+    #[case(Tape::Growable, CellWidth::Bits16, (1 << 30, 0))]
+    #[case(Tape::Bidirectional, CellWidth::Bits32, (1 << 30, 1 << 29))]
+    fn test_layout(#[case] tape: Tape, #[case] cell: CellWidth, #[case] expected: (usize, usize)) {
+        let options = Options {
+            tape,
+            ..options(cell, false)
+        };
+
+        assert_eq!(options.layout(1 << 30), expected);
+    }
+
+    #[rstest]
+    #[case(CellWidth::Bits8, false, 255, Some(vec![255]))]
+    #[case(CellWidth::Bits8, true, 255, Some(vec![127, 127, 1]))]
+    #[case(CellWidth::Bits8, true, 127, Some(vec![127]))]
+    #[case(CellWidth::Bits8, false, 256, None)]
+    #[case(CellWidth::Bits8, true, 256, None)]
+    #[case(CellWidth::Bits16, false, 0xffff, Some(vec![0xffff]))]
+    #[case(CellWidth::Bits16, true, 0x8000, Some(vec![0x7fff, 1]))]
+    #[case(CellWidth::Bits16, false, 0x1_0000, None)]
+    #[case(CellWidth::Bits32, false, 0xffff_ffff, Some(vec![0xffff_ffff]))]
+    #[case(CellWidth::Bits32, true, 0xffff_ffff, Some(vec![0x7fff_ffff, 0x7fff_ffff, 1]))]
+    #[case(CellWidth::Bits32, true, 1 << 32, None)]
+    #[case(CellWidth::Bits64, false, u64::MAX, Some(vec![u64::MAX]))]
+    #[case(CellWidth::Bits64, true, u64::MAX, Some(vec![i64::MAX as u64, i64::MAX as u64, 1]))]
+    #[case(CellWidth::Bits64, true, 1 << 63, Some(vec![i64::MAX as u64, 1]))]
+    fn test_checked_steps(
+        #[case] cell: CellWidth,
+        #[case] signed: bool,
+        #[case] magnitude: u64,
+        #[case] expected: Option<Vec<u64>>,
+    ) {
+        assert_eq!(checked_steps(magnitude, &options(cell, signed)), expected);
+    }
+
+    #[rstest]
+    fn test_checked_steps_signed_max(
+        #[values(
+            CellWidth::Bits8,
+            CellWidth::Bits16,
+            CellWidth::Bits32,
+            CellWidth::Bits64
+        )]
+        cell: CellWidth,
+    ) {
+        let max = u64::MAX >> (65 - cell.bits());
+
+        assert_eq!(checked_steps(max, &options(cell, true)), Some(vec![max]));
+        assert_eq!(
+            checked_steps(max + 1, &options(cell, true)),
+            Some(vec![max, 1])
+        );
+    }
+
+    #[rstest]
+    fn test_checked_steps_zero(
+        #[values(
+            CellWidth::Bits8,
+            CellWidth::Bits16,
+            CellWidth::Bits32,
+            CellWidth::Bits64
+        )]
+        cell: CellWidth,
+        #[values(false, true)] signed: bool,
+    ) {
+        assert_eq!(checked_steps(0, &options(cell, signed)), Some(vec![]));
+    }
+
+    #[test]
+    fn test_compile_c_source() {
+        let outfile = std::env::temp_dir().join(format!("nauc-{}-test.c", std::process::id()));
+        let source = String::from("int main(void) { return 0; }\n");
+
+        compile(&source, &outfile, false, false, Target::C).unwrap();
+        let written = std::fs::read_to_string(&outfile);
+        std::fs::remove_file(&outfile).unwrap();
+
+        assert_eq!(written.unwrap(), source);
+        assert!(!Path::new(outfile.file_stem().unwrap()).exists());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use crate::{ir::Instruction, span::Spanned};
+
+/// Which instructions of a program ran, and which way its brackets went.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Times each instruction ran.
+    hits: Vec<u64>,
+    /// Times each bracket jumped, and times it fell through. A `[` falls
+    /// through into the loop body, a `]` jumps back to run it again.
+    branches: Vec<[u64; 2]>,
+}
+
+impl Coverage {
+    /// Empty coverage of a program of `length` instructions.
+    pub fn new(length: usize) -> Self {
+        Self {
+            hits: vec![0; length],
+            branches: vec![[0; 2]; length],
+        }
+    }
+
+    /// Records that the instruction at `pc` ran.
+    pub fn hit(&mut self, pc: usize) {
+        self.hits[pc] += 1;
+    }
+
+    /// Records that the bracket at `pc` jumped, or fell through.
+    pub fn branch(&mut self, pc: usize, jumped: bool) {
+        self.branches[pc][usize::from(!jumped)] += 1;
+    }
+
+    /// Times each instruction ran.
+    pub fn hits(&self) -> &[u64] {
+        &self.hits
+    }
+
+    /// Times each bracket jumped, and times it fell through.
+    pub fn branches(&self) -> &[[u64; 2]] {
+        &self.branches
+    }
+
+    /// The coverage of `program`, parsed from the file called `name` whose
+    /// contents are `source`, as an lcov tracefile.
+    ///
+    /// Every line an instruction spans counts as often as the most frequent
+    /// instruction on it. Each bracket is a block of two branches: jumping,
+    /// then falling through.
+    pub fn lcov(&self, program: &[Spanned<Instruction>], name: &str, source: &str) -> String {
+        let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+        let mut branches = vec![];
+
+        for (pc, instruction) in program.iter().enumerate() {
+            let span = instruction.span;
+            let last = span.line + source[span.start..span.end].matches('\n').count();
+            for line in span.line..=last {
+                let count = lines.entry(line).or_default();
+                *count = (*count).max(self.hits[pc]);
+            }
+
+            if matches!(
+                instruction.node,
+                Instruction::LoopStart(_) | Instruction::LoopEnd(_)
+            ) {
+                // Branches of code that never ran have no count at all.
+                for (branch, &taken) in self.branches[pc].iter().enumerate() {
+                    branches.push((span.line, pc, branch, (self.hits[pc] > 0).then_some(taken)));
+                }
+            }
+        }
+
+        let mut lcov = format!("TN:\nSF:{name}\n");
+        for &(line, block, branch, taken) in &branches {
+            let taken = taken.map_or("-".to_string(), |taken| taken.to_string());
+            lcov.push_str(&format!("BRDA:{line},{block},{branch},{taken}\n"));
+        }
+        lcov.push_str(&format!(
+            "BRF:{}\nBRH:{}\n",
+            branches.len(),
+            branches
+                .iter()
+                .filter(|&&(.., taken)| taken.is_some_and(|taken| taken > 0))
+                .count()
+        ));
+        for (line, count) in &lines {
+            lcov.push_str(&format!("DA:{line},{count}\n"));
+        }
+        lcov.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            lines.len(),
+            lines.values().filter(|&&count| count > 0).count()
+        ));
+
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter::InterpreterBuilder, ir::lower, parser::ast};
+    use indoc::indoc;
+
+    #[test]
+    fn test_lcov() {
+        let source = "+[-]\n[\n+\n]";
+        let program = lower(&ast(source).unwrap());
+        let mut interpreter = InterpreterBuilder::new()
+            .program(program)
+            .input(&b""[..])
+            .output(vec![])
+            .coverage(true)
+            .build::<u8>();
+        interpreter.finish().unwrap();
+
+        let coverage = interpreter.coverage().unwrap();
+        assert_eq!(coverage.hits(), [1, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(coverage.branches()[1], [0, 1]);
+        assert_eq!(
+            coverage.lcov(interpreter.program(), "a.bf", source),
+            indoc! {"
+                TN:
+                SF:a.bf
+                BRDA:1,1,0,0
+                BRDA:1,1,1,1
+                BRDA:1,3,0,0
+                BRDA:1,3,1,1
+                BRDA:2,4,0,1
+                BRDA:2,4,1,0
+                BRDA:4,6,0,-
+                BRDA:4,6,1,-
+                BRF:8
+                BRH:3
+                DA:1,1
+                DA:2,1
+                DA:3,0
+                DA:4,0
+                LF:4
+                LH:2
+                end_of_record
+            "}
+        );
+    }
+}
@@ -0,0 +1,746 @@
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{BufRead, Read, Write},
+};
+
+use crate::{
+    cell::Cell,
+    interpreter::{Interpreter, InterpreterError, Undo},
+    ir::Instruction,
+    span,
+};
+
+/// Where execution pauses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at a line and column of the source.
+    Position { line: usize, column: usize },
+    /// Before the instruction made from the token at this index.
+    Token(usize),
+    /// Before the instruction following each `#` in the source.
+    Hash,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Position { line, column } => write!(f, "{line}:{column}"),
+            Breakpoint::Token(index) => write!(f, "token {index}"),
+            Breakpoint::Hash => write!(f, "#"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Breakpoint {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || anyhow!("{} is not a valid breakpoint", &value);
+
+        match value.split_once(':') {
+            _ if value == "#" => Ok(Breakpoint::Hash),
+            Some((line, column)) => Ok(Breakpoint::Position {
+                line: line.parse().map_err(|_| invalid())?,
+                column: column.parse().map_err(|_| invalid())?,
+            }),
+            None => value.parse().map(Breakpoint::Token).map_err(|_| invalid()),
+        }
+    }
+}
+
+/// A command typed at the debugger prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Runs this many instructions.
+    Step(usize),
+    /// Runs one instruction, or a whole loop when on its start.
+    Next,
+    Continue,
+    /// Runs until the innermost loop around the current instruction exits.
+    FinishLoop,
+    /// Takes back this many instructions.
+    ReverseStep(usize),
+    /// Runs backwards to the last write of a cell, or to a breakpoint.
+    ReverseContinue(Option<usize>),
+    Break(Breakpoint),
+    /// Deletes one breakpoint by number, or all of them.
+    Delete(Option<usize>),
+    Watch(usize),
+    Unwatch(usize),
+    /// Shows the cells within this many of the data pointer.
+    Tape(Option<usize>),
+    Info,
+    Help,
+    Quit,
+}
+
+impl TryFrom<&str> for Command {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut words = value.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+
+        if words.next().is_some() {
+            bail!("Too many arguments to {}", command);
+        }
+
+        let number = |argument: Option<&str>| -> Result<usize> {
+            let argument = argument.ok_or(anyhow!("{} needs a number", command))?;
+            argument
+                .parse()
+                .map_err(|_| anyhow!("{} is not a number", argument))
+        };
+
+        match command {
+            "step" | "s" => Ok(Command::Step(argument.map_or(Ok(1), |_| number(argument))?)),
+            "next" | "n" => Ok(Command::Next),
+            "continue" | "c" => Ok(Command::Continue),
+            "finish-loop" | "finish" | "f" => Ok(Command::FinishLoop),
+            "reverse-step" | "rs" => Ok(Command::ReverseStep(
+                argument.map_or(Ok(1), |_| number(argument))?,
+            )),
+            "reverse-continue" | "rc" => Ok(Command::ReverseContinue(
+                argument.map(|_| number(argument)).transpose()?,
+            )),
+            "break" | "b" => Ok(Command::Break(Breakpoint::try_from(
+                argument.ok_or(anyhow!("break needs a position, a token index or #"))?,
+            )?)),
+            "delete" | "d" => Ok(Command::Delete(
+                argument.map(|_| number(argument)).transpose()?,
+            )),
+            "watch" | "w" => Ok(Command::Watch(number(argument)?)),
+            "unwatch" => Ok(Command::Unwatch(number(argument)?)),
+            "tape" | "t" => Ok(Command::Tape(
+                argument.map(|_| number(argument)).transpose()?,
+            )),
+            "info" | "i" => Ok(Command::Info),
+            "help" | "h" => Ok(Command::Help),
+            "quit" | "q" => Ok(Command::Quit),
+            _ => Err(anyhow!("Unknown command {}, try help", command)),
+        }
+    }
+}
+
+const HELP: &str = "\
+step [n]                (s)  run n instructions, 1 by default
+next                    (n)  run one instruction, or a whole loop when on its start
+continue                (c)  run until a breakpoint, a watchpoint or the end
+finish-loop             (f)  run until the innermost loop exits
+reverse-step [n]        (rs) take back n instructions, 1 by default, of the last 65536
+reverse-continue [cell] (rc) run backwards to the last write of a cell, or to a breakpoint
+break <where>           (b)  pause at a line:column, a token index or every #
+delete [n]              (d)  delete breakpoint n, or all of them
+watch <cell>            (w)  pause when a cell changes
+unwatch <cell>               stop watching a cell
+tape [radius]           (t)  show the cells around the data pointer
+info                    (i)  list breakpoints and watchpoints
+quit                    (q)  stop debugging
+An empty line repeats the last command.";
+
+/// How many steps can be taken back. Older steps are forgotten, so that a
+/// long `continue` keeps a few MB of history at most.
+const HISTORY: usize = 1 << 16;
+
+/// Why execution paused.
+#[derive(Debug)]
+pub enum Stop {
+    /// The requested steps are done.
+    Step,
+    Breakpoint(Breakpoint),
+    Watchpoint {
+        cell: usize,
+        old: i128,
+        new: i128,
+    },
+    /// Running backwards reached the instruction that last changed a cell.
+    LastWrite {
+        cell: usize,
+        old: i128,
+        new: i128,
+    },
+    /// Running backwards used up the recorded history.
+    HistoryStart,
+    Finished,
+    Error(InterpreterError),
+}
+
+/// Runs an [`Interpreter`] under the control of breakpoints, watchpoints and
+/// stepping commands.
+///
+/// The program should not be optimized, so that each instruction is exactly
+/// one token of `source`.
+pub struct Debugger<'a, C: Cell, R: Read, W: Write> {
+    interpreter: Interpreter<C, R, W>,
+    name: &'a str,
+    source: &'a str,
+    /// Breakpoints, with the instructions they pause before.
+    breakpoints: Vec<(Breakpoint, Vec<usize>)>,
+    /// Watched cells, with their last seen values.
+    watchpoints: Vec<(usize, C)>,
+    /// Undo records of the latest steps, oldest first.
+    history: VecDeque<Undo<C>>,
+    radius: usize,
+}
+
+impl<'a, C: Cell, R: Read, W: Write> Debugger<'a, C, R, W> {
+    /// Debugs `interpreter`, running a program parsed from the file called
+    /// `name` whose contents are `source`.
+    pub fn new(interpreter: Interpreter<C, R, W>, name: &'a str, source: &'a str) -> Self {
+        Self {
+            interpreter,
+            name,
+            source,
+            breakpoints: vec![],
+            watchpoints: vec![],
+            history: VecDeque::new(),
+            radius: 4,
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<C, R, W> {
+        &self.interpreter
+    }
+
+    /// Adds a breakpoint, failing if it does not land on any instruction.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<()> {
+        let program = self.interpreter.program();
+        // The first instruction that ends after a byte offset.
+        let after = |offset: usize| program.iter().position(|i| i.span.end > offset);
+
+        let pcs: Vec<usize> = match breakpoint {
+            Breakpoint::Position { line, column } => span::chars(self.source)
+                .find(|(_, span)| span.line == line && span.column == column)
+                .and_then(|(_, span)| after(span.start))
+                .into_iter()
+                .collect(),
+            Breakpoint::Token(index) => (index < program.len())
+                .then_some(index)
+                .into_iter()
+                .collect(),
+            Breakpoint::Hash => {
+                let mut pcs: Vec<usize> = span::chars(self.source)
+                    .filter(|&(ch, _)| ch == '#')
+                    .filter_map(|(_, span)| after(span.start))
+                    .collect();
+                pcs.dedup();
+                pcs
+            }
+        };
+
+        if pcs.is_empty() {
+            bail!("Breakpoint at {} is not on any instruction", breakpoint);
+        }
+
+        self.breakpoints.push((breakpoint, pcs));
+        Ok(())
+    }
+
+    /// Deletes breakpoint `number`, counting from 1, or every breakpoint.
+    pub fn delete_breakpoint(&mut self, number: Option<usize>) -> Result<()> {
+        match number {
+            Some(number) if (1..=self.breakpoints.len()).contains(&number) => {
+                self.breakpoints.remove(number - 1);
+            }
+            Some(number) => bail!("There is no breakpoint {}", number),
+            None => self.breakpoints.clear(),
+        }
+
+        Ok(())
+    }
+
+    pub fn watch(&mut self, cell: usize) -> Result<()> {
+        let value = *self
+            .interpreter
+            .tape()
+            .get(cell)
+            .ok_or(anyhow!("Cell {} is out of bounds", cell))?;
+
+        if !self.watchpoints.iter().any(|&(c, _)| c == cell) {
+            self.watchpoints.push((cell, value));
+        }
+
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, cell: usize) {
+        self.watchpoints.retain(|&(c, _)| c != cell);
+    }
+
+    /// Runs `count` instructions.
+    pub fn step(&mut self, count: usize) -> Stop {
+        let mut left = count;
+        self.run_until(|_| {
+            left = left.saturating_sub(1);
+            left == 0
+        })
+    }
+
+    /// Runs one instruction, or the whole loop if the current instruction
+    /// starts one.
+    pub fn step_over(&mut self) -> Stop {
+        match self.current() {
+            Some(Instruction::LoopStart(end)) => self.run_until(|i| i.pc() == end + 1),
+            _ => self.step(1),
+        }
+    }
+
+    /// Runs until a breakpoint, a watchpoint or the end of the program.
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+
+    /// Runs until the innermost loop around the current instruction exits.
+    pub fn finish_loop(&mut self) -> Result<Stop> {
+        let pc = self.interpreter.pc();
+        let end = self.interpreter.program()[..pc.min(self.interpreter.program().len())]
+            .iter()
+            .rev()
+            .find_map(|i| match i.node {
+                Instruction::LoopStart(end) if end >= pc => Some(end),
+                _ => None,
+            })
+            .ok_or(anyhow!("Not inside a loop"))?;
+
+        Ok(self.run_until(|i| i.pc() == end + 1))
+    }
+
+    /// Takes back `count` instructions.
+    pub fn reverse_step(&mut self, count: usize) -> Stop {
+        for _ in 0..count {
+            match self.history.pop_back() {
+                Some(undo) => self.undo(undo),
+                None => return Stop::HistoryStart,
+            }
+        }
+
+        Stop::Step
+    }
+
+    /// Runs backwards until just before the last instruction that changed
+    /// `cell`, or without a cell, until a breakpoint.
+    pub fn reverse_continue(&mut self, cell: Option<usize>) -> Stop {
+        while let Some(undo) = self.history.pop_back() {
+            let write = match (cell, undo.cell()) {
+                (Some(cell), Some((written, old))) if written == cell => {
+                    Some((old, self.interpreter.tape()[cell]))
+                }
+                _ => None,
+            };
+            self.undo(undo);
+
+            match (cell, write) {
+                (Some(cell), Some((old, new))) if old != new => {
+                    let signed = self.interpreter.signed();
+                    return Stop::LastWrite {
+                        cell,
+                        old: old.value(signed),
+                        new: new.value(signed),
+                    };
+                }
+                (None, _) => {
+                    if let Some(breakpoint) = self.breakpoint() {
+                        return Stop::Breakpoint(breakpoint);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Stop::HistoryStart
+    }
+
+    /// Reads commands from `commands` until it ends or the user quits, and
+    /// reports to `out`.
+    pub fn repl(&mut self, mut commands: impl BufRead, mut out: impl Write) -> Result<()> {
+        writeln!(out, "{}", self.location())?;
+        let mut last = None;
+
+        loop {
+            write!(out, "(nauc) ")?;
+            out.flush()?;
+
+            let mut line = String::new();
+            if commands.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            let command = match (line.trim(), last) {
+                ("", Some(last)) => last,
+                ("", None) => continue,
+                (line, _) => match Command::try_from(line) {
+                    Ok(command) => command,
+                    Err(err) => {
+                        writeln!(out, "{err}")?;
+                        continue;
+                    }
+                },
+            };
+            last = Some(command);
+
+            let stop = match command {
+                Command::Step(count) => Ok(self.step(count)),
+                Command::Next => Ok(self.step_over()),
+                Command::Continue => Ok(self.resume()),
+                Command::FinishLoop => self.finish_loop(),
+                Command::ReverseStep(count) => Ok(self.reverse_step(count)),
+                Command::ReverseContinue(cell) => Ok(self.reverse_continue(cell)),
+                Command::Break(breakpoint) => {
+                    let added = self.add_breakpoint(breakpoint).map(|_| {
+                        format!("Breakpoint {} at {}", self.breakpoints.len(), breakpoint)
+                    });
+                    writeln!(out, "{}", added.unwrap_or_else(|err| err.to_string()))?;
+                    continue;
+                }
+                Command::Delete(number) => {
+                    if let Err(err) = self.delete_breakpoint(number) {
+                        writeln!(out, "{err}")?;
+                    }
+                    continue;
+                }
+                Command::Watch(cell) => {
+                    if let Err(err) = self.watch(cell) {
+                        writeln!(out, "{err}")?;
+                    }
+                    continue;
+                }
+                Command::Unwatch(cell) => {
+                    self.unwatch(cell);
+                    continue;
+                }
+                Command::Tape(radius) => {
+                    self.radius = radius.unwrap_or(self.radius);
+                    writeln!(out, "{}", self.interpreter.tape_window(self.radius))?;
+                    continue;
+                }
+                Command::Info => {
+                    writeln!(out, "{}", self.info())?;
+                    continue;
+                }
+                Command::Help => {
+                    writeln!(out, "{HELP}")?;
+                    continue;
+                }
+                Command::Quit => break,
+            };
+
+            match stop {
+                Ok(stop) => writeln!(out, "{}", self.report(&stop))?,
+                Err(err) => writeln!(out, "{err}")?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The instruction about to run.
+    fn current(&self) -> Option<Instruction> {
+        self.interpreter
+            .program()
+            .get(self.interpreter.pc())
+            .map(|i| i.node)
+    }
+
+    /// Takes back a step, and takes the watched cells' values from the tape
+    /// as it was, so the next step reports only its own changes.
+    fn undo(&mut self, undo: Undo<C>) {
+        self.interpreter.undo(undo);
+
+        let tape = self.interpreter.tape();
+        for (cell, value) in &mut self.watchpoints {
+            *value = tape[*cell];
+        }
+    }
+
+    /// Steps until `done` holds, stopping early at breakpoints, watchpoints,
+    /// errors and the end of the program.
+    fn run_until(&mut self, mut done: impl FnMut(&Interpreter<C, R, W>) -> bool) -> Stop {
+        loop {
+            if self.interpreter.finished() {
+                return Stop::Finished;
+            }
+
+            match self.interpreter.step_undoable() {
+                Ok(undo) => {
+                    if self.history.len() == HISTORY {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back(undo);
+                }
+                Err(err) => return Stop::Error(err),
+            }
+
+            let tape = self.interpreter.tape();
+            if let Some((cell, old)) = self
+                .watchpoints
+                .iter_mut()
+                .find(|(cell, old)| tape[*cell] != *old)
+            {
+                let signed = self.interpreter.signed();
+                let (old_value, new) = (old.value(signed), tape[*cell].value(signed));
+                *old = tape[*cell];
+
+                return Stop::Watchpoint {
+                    cell: *cell,
+                    old: old_value,
+                    new,
+                };
+            }
+
+            if self.interpreter.finished() {
+                return Stop::Finished;
+            }
+
+            if done(&self.interpreter) {
+                return Stop::Step;
+            }
+
+            if let Some(breakpoint) = self.breakpoint() {
+                return Stop::Breakpoint(breakpoint);
+            }
+        }
+    }
+
+    /// The breakpoint on the instruction about to run.
+    fn breakpoint(&self) -> Option<Breakpoint> {
+        let pc = self.interpreter.pc();
+        self.breakpoints
+            .iter()
+            .find(|(_, pcs)| pcs.contains(&pc))
+            .map(|&(breakpoint, _)| breakpoint)
+    }
+
+    /// Describes why execution stopped, and where.
+    fn report(&self, stop: &Stop) -> String {
+        match stop {
+            Stop::Step => self.location(),
+            Stop::Breakpoint(breakpoint) => {
+                format!("Breakpoint at {breakpoint}\n{}", self.location())
+            }
+            Stop::Watchpoint { cell, old, new } => {
+                format!(
+                    "Cell {cell} changed from {old} to {new}\n{}",
+                    self.location()
+                )
+            }
+            Stop::LastWrite { cell, old, new } => format!(
+                "Cell {cell} was last changed from {old} to {new} by\n{}",
+                self.location()
+            ),
+            Stop::HistoryStart => format!(
+                "Reached the start of the recorded history\n{}",
+                self.location()
+            ),
+            Stop::Finished => "The program has finished".to_string(),
+            Stop::Error(err) => match err.span() {
+                Some(span) => format!("{err}\n{}", span.snippet(self.name, self.source)),
+                None => err.to_string(),
+            },
+        }
+    }
+
+    /// The next instruction in the source, and the cells around the pointer.
+    fn location(&self) -> String {
+        match self.interpreter.program().get(self.interpreter.pc()) {
+            Some(instruction) => format!(
+                "Token {} at {}\n{}\n{}",
+                self.interpreter.pc(),
+                instruction.span,
+                instruction.span.snippet(self.name, self.source),
+                self.interpreter.tape_window(self.radius)
+            ),
+            None => "The program has finished".to_string(),
+        }
+    }
+
+    /// The breakpoints and watchpoints that are set.
+    fn info(&self) -> String {
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .enumerate()
+            .map(|(i, (breakpoint, _))| format!("Breakpoint {} at {}", i + 1, breakpoint));
+        let watchpoints = self.watchpoints.iter().map(|&(cell, value)| {
+            format!(
+                "Watching cell {cell}, currently {}",
+                value.value(self.interpreter.signed())
+            )
+        });
+        let lines: Vec<String> = breakpoints.chain(watchpoints).collect();
+
+        if lines.is_empty() {
+            "No breakpoints or watchpoints".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::InterpreterBuilder, ir::lower, parser::ast};
+    use rstest::*;
+
+    fn debugger(source: &str) -> Debugger<'_, u8, &'static [u8], Vec<u8>> {
+        let interpreter = InterpreterBuilder::new()
+            .program(lower(&ast(source).unwrap()))
+            .input(&b""[..])
+            .output(vec![])
+            .build::<u8>();
+
+        Debugger::new(interpreter, "test.bf", source)
+    }
+
+    #[rstest]
+    #[case("s", Command::Step(1))]
+    #[case("step 3", Command::Step(3))]
+    #[case("b 2:5", Command::Break(Breakpoint::Position { line: 2, column: 5 }))]
+    #[case("break 7", Command::Break(Breakpoint::Token(7)))]
+    #[case("b #", Command::Break(Breakpoint::Hash))]
+    #[case("d", Command::Delete(None))]
+    #[case("watch 2", Command::Watch(2))]
+    #[case("finish-loop", Command::FinishLoop)]
+    fn test_command(#[case] line: &str, #[case] expected: Command) {
+        assert_eq!(Command::try_from(line).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("step x")]
+    #[case("break")]
+    #[case("jump 3")]
+    #[case("b 1:2:3")]
+    fn test_invalid_command(#[case] line: &str) {
+        assert!(Command::try_from(line).is_err());
+    }
+
+    #[test]
+    fn test_next_skips_loops() {
+        let mut debugger = debugger("++[->+<]>.");
+
+        debugger.step(1);
+        assert_eq!(debugger.interpreter().pc(), 1);
+        assert!(matches!(debugger.step_over(), Stop::Step));
+        assert_eq!(debugger.interpreter().pc(), 7);
+        assert_eq!(debugger.interpreter().tape()[1], 2);
+    }
+
+    #[test]
+    fn test_finish_loop() {
+        let mut debugger = debugger("++[->+<]>.");
+
+        debugger.step(3);
+        assert!(matches!(debugger.finish_loop(), Ok(Stop::Step)));
+        assert_eq!(debugger.interpreter().pc(), 7);
+        assert!(debugger.finish_loop().is_err());
+    }
+
+    #[rstest]
+    #[case("+++>+#>-", Breakpoint::Hash, 3)]
+    #[case("+++\n>+ >", Breakpoint::Position { line: 2, column: 3 }, 3)]
+    #[case("+++>+++", Breakpoint::Token(2), 2)]
+    fn test_breakpoint(#[case] source: &str, #[case] breakpoint: Breakpoint, #[case] pc: usize) {
+        let mut debugger = debugger(source);
+
+        debugger.add_breakpoint(breakpoint).unwrap();
+        assert!(matches!(debugger.resume(), Stop::Breakpoint(b) if b == breakpoint));
+        assert_eq!(debugger.interpreter().pc(), pc);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut debugger = debugger("++>+<[->>+<<]");
+
+        debugger.watch(2).unwrap();
+        assert!(matches!(
+            debugger.resume(),
+            Stop::Watchpoint {
+                cell: 2,
+                old: 0,
+                new: 1
+            }
+        ));
+        assert_eq!(debugger.interpreter().pc(), 8);
+    }
+
+    #[test]
+    fn test_watchpoint_after_reverse() {
+        let mut debugger = debugger("+>+<+");
+
+        debugger.watch(1).unwrap();
+        assert!(matches!(
+            debugger.resume(),
+            Stop::Watchpoint { cell: 1, .. }
+        ));
+        assert!(matches!(debugger.reverse_step(2), Stop::Step));
+        assert!(matches!(debugger.step(1), Stop::Step));
+        assert!(matches!(
+            debugger.step(1),
+            Stop::Watchpoint {
+                cell: 1,
+                old: 0,
+                new: 1
+            }
+        ));
+        assert_eq!(debugger.interpreter().pc(), 3);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let source = "+-".repeat(HISTORY / 2 + 1);
+        let mut debugger = debugger(&source);
+
+        assert!(matches!(debugger.resume(), Stop::Finished));
+        assert!(matches!(debugger.reverse_step(HISTORY), Stop::Step));
+        assert_eq!(debugger.interpreter().pc(), 2);
+        assert!(matches!(debugger.reverse_step(1), Stop::HistoryStart));
+        assert!(HELP.contains(&format!("of the last {HISTORY}\n")));
+    }
+
+    #[test]
+    fn test_empty_tape() {
+        let interpreter = InterpreterBuilder::new()
+            .program(lower(&ast("+").unwrap()))
+            .input(&b""[..])
+            .output(vec![])
+            .memory(0)
+            .build::<u8>();
+        let mut out = vec![];
+
+        Debugger::new(interpreter, "test.bf", "+")
+            .repl(&b"step\ntape\n"[..], &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("cell \nvalue\n"));
+        assert!(out.contains("out of bounds memory at 1:1"));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut debugger = debugger("+++>++<[->+<]>");
+
+        debugger.resume();
+        assert_eq!(debugger.interpreter().tape()[1], 5);
+
+        assert!(matches!(debugger.reverse_step(2), Stop::Step));
+        assert_eq!(debugger.interpreter().pc(), 9);
+        assert!(matches!(
+            debugger.reverse_continue(Some(1)),
+            Stop::LastWrite {
+                cell: 1,
+                old: 4,
+                new: 5
+            }
+        ));
+        assert_eq!(debugger.interpreter().pc(), 7);
+        assert!(matches!(
+            debugger.reverse_continue(Some(7)),
+            Stop::HistoryStart
+        ));
+        assert_eq!(debugger.interpreter().pc(), 0);
+        assert_eq!(debugger.interpreter().tape()[..2], [0, 0]);
+    }
+}
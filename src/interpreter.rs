@@ -1,29 +1,140 @@
-use crate::parser::Token;
-use anyhow::Result;
+use crate::{
+    cell::{Cell, CellWidth, Eof, Tape},
+    coverage::Coverage,
+    ir::{Instruction, DUMP_RADIUS},
+    span::{Span, Spanned},
+};
+use anyhow::{anyhow, Result};
+#[cfg(feature = "cli")]
+use clap::{builder::PossibleValue, ValueEnum};
+use std::io::{self, BufReader, BufWriter, Read, Stdin, Stdout, Write};
 use thiserror::Error;
 
-#[derive(Debug, Error, PartialEq, Eq)]
+#[derive(Debug, Error)]
 pub enum InterpreterError {
-    #[error("Integer overflow at cell {0}")]
-    IntegerOverflow(usize),
+    #[error("Integer overflow at {0}")]
+    IntegerOverflow(Span),
 
-    #[error("Integer underflow at cell {0}")]
-    IntegerUnderflow(usize),
+    #[error("Integer underflow at {0}")]
+    IntegerUnderflow(Span),
 
-    #[error("Pointer points to out of bounds memory.")]
-    OutOfBounds,
+    #[error("Pointer points to out of bounds memory at {0}")]
+    OutOfBounds(Span),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl InterpreterError {
+    /// The span of the instruction that failed, if the error came from one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            InterpreterError::IntegerOverflow(span)
+            | InterpreterError::IntegerUnderflow(span)
+            | InterpreterError::OutOfBounds(span) => Some(*span),
+            InterpreterError::Io(_) => None,
+        }
+    }
+
+    /// The status a run that fails this way exits with. Compiled programs
+    /// that check for the same error exit with the same status.
+    pub fn status(&self) -> i32 {
+        match self {
+            InterpreterError::Io(_) => 1,
+            InterpreterError::OutOfBounds(_) => 3,
+            InterpreterError::IntegerOverflow(_) => 4,
+            InterpreterError::IntegerUnderflow(_) => 5,
+        }
+    }
+}
+
+/// When buffered output is handed to the underlying writer.
+///
+/// Output is always flushed before reading input and when the program stops,
+/// so that prompts are visible and nothing is lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flush {
+    /// After every `.`.
+    Always,
+    /// After every newline.
+    #[default]
+    Line,
+    /// Only before input and on exit.
+    Exit,
+}
+
+#[cfg(feature = "cli")]
+impl ValueEnum for Flush {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Always, Self::Line, Self::Exit]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Flush> for &str {
+    fn from(value: Flush) -> Self {
+        match value {
+            Flush::Always => "always",
+            Flush::Line => "line",
+            Flush::Exit => "exit",
+        }
+    }
+}
+
+impl TryFrom<&str> for Flush {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "always" => Ok(Flush::Always),
+            "line" => Ok(Flush::Line),
+            "exit" => Ok(Flush::Exit),
+            _ => Err(anyhow!("{} is not a valid flush policy", &value)),
+        }
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct NoProgram;
 #[derive(Clone, Default)]
-pub struct Program(Vec<Token>);
+pub struct Program(Vec<Spanned<Instruction>>);
 
-#[derive(Clone, Default)]
-pub struct InterpreterBuilder<P> {
+/// Configures an [`Interpreter`].
+///
+/// Options that are not set fall back to a fixed tape of 30000 wrapping,
+/// unsigned cells,
+/// with I/O on stdin and stdout.
+pub struct InterpreterBuilder<P, R = Stdin, W = Stdout> {
     program: P,
+    input: R,
+    output: W,
     wrapping: Option<bool>,
+    signed: Option<bool>,
+    eof: Option<Eof>,
+    flush: Option<Flush>,
     memory: Option<usize>,
+    tape: Option<Tape>,
+    coverage: Option<bool>,
+}
+
+impl Default for InterpreterBuilder<NoProgram> {
+    fn default() -> Self {
+        Self {
+            program: NoProgram,
+            input: io::stdin(),
+            output: io::stdout(),
+            wrapping: None,
+            signed: None,
+            eof: None,
+            flush: None,
+            memory: None,
+            tape: None,
+            coverage: None,
+        }
+    }
 }
 
 impl InterpreterBuilder<NoProgram> {
@@ -32,12 +143,51 @@ impl InterpreterBuilder<NoProgram> {
     }
 }
 
-impl<P> InterpreterBuilder<P> {
-    pub fn program(self, program: Vec<Token>) -> InterpreterBuilder<Program> {
+impl<P, R, W> InterpreterBuilder<P, R, W> {
+    pub fn program(self, program: Vec<Spanned<Instruction>>) -> InterpreterBuilder<Program, R, W> {
         InterpreterBuilder {
             program: Program(program),
+            input: self.input,
+            output: self.output,
+            wrapping: self.wrapping,
+            signed: self.signed,
+            eof: self.eof,
+            flush: self.flush,
+            memory: self.memory,
+            tape: self.tape,
+            coverage: self.coverage,
+        }
+    }
+
+    /// Reads `,` input from `input` instead of stdin.
+    pub fn input<R2: Read>(self, input: R2) -> InterpreterBuilder<P, R2, W> {
+        InterpreterBuilder {
+            program: self.program,
+            input,
+            output: self.output,
+            wrapping: self.wrapping,
+            signed: self.signed,
+            eof: self.eof,
+            flush: self.flush,
+            memory: self.memory,
+            tape: self.tape,
+            coverage: self.coverage,
+        }
+    }
+
+    /// Writes `.` output to `output` instead of stdout.
+    pub fn output<W2: Write>(self, output: W2) -> InterpreterBuilder<P, R, W2> {
+        InterpreterBuilder {
+            program: self.program,
+            input: self.input,
+            output,
             wrapping: self.wrapping,
+            signed: self.signed,
+            eof: self.eof,
+            flush: self.flush,
             memory: self.memory,
+            tape: self.tape,
+            coverage: self.coverage,
         }
     }
 
@@ -46,99 +196,709 @@ impl<P> InterpreterBuilder<P> {
         self
     }
 
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.signed = Some(signed);
+        self
+    }
+
+    pub fn eof(mut self, eof: Eof) -> Self {
+        self.eof = Some(eof);
+        self
+    }
+
+    pub fn flush(mut self, flush: Flush) -> Self {
+        self.flush = Some(flush);
+        self
+    }
+
     pub fn memory(mut self, memory: usize) -> Self {
         self.memory = Some(memory);
         self
     }
+
+    /// Lets the tape grow past `memory` cells, see [`Tape`].
+    pub fn tape(mut self, tape: Tape) -> Self {
+        self.tape = Some(tape);
+        self
+    }
+
+    /// Records which instructions run and which way brackets go, see
+    /// [`Interpreter::coverage`].
+    pub fn coverage(mut self, coverage: bool) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
 }
 
-impl InterpreterBuilder<Program> {
-    pub fn build(self) -> Interpreter {
+impl<R: Read, W: Write> InterpreterBuilder<Program, R, W> {
+    /// Builds an interpreter whose cells are of type `C`.
+    pub fn build<C: Cell>(self) -> Interpreter<C, R, W> {
         let memory = self.memory.unwrap_or(30_000);
         let wrapping = self.wrapping.unwrap_or(true);
+        let signed = self.signed.unwrap_or(false);
+        let eof = self.eof.unwrap_or_default();
+        let flush = self.flush.unwrap_or_default();
+        let tape_mode = self.tape.unwrap_or_default();
+        let coverage = self
+            .coverage
+            .unwrap_or(false)
+            .then(|| Coverage::new(self.program.0.len()));
+
+        // A tape that grows starts with at least the cell the pointer is on.
+        let memory = match tape_mode {
+            Tape::Fixed => memory,
+            Tape::Growable | Tape::Bidirectional => memory.max(1),
+        };
 
         Interpreter {
             program: self.program.0,
-            tape: vec![0u8; memory],
-            input_buffer: vec![],
+            tape: vec![C::default(); memory],
+            input: BufReader::new(self.input),
+            output: BufWriter::new(self.output),
             pc: 0,
             index: 0,
+            origin: 0,
+            tape_mode,
             wrapping,
+            signed,
+            eof,
+            flush,
+            unread: vec![],
+            last_read: None,
+            coverage,
+        }
+    }
+
+    /// Builds and runs an interpreter, picking the cell type from `width`.
+    pub fn run(self, width: CellWidth) -> Result<(), InterpreterError> {
+        match width {
+            CellWidth::Bits8 => self.build::<u8>().run(),
+            CellWidth::Bits16 => self.build::<u16>().run(),
+            CellWidth::Bits32 => self.build::<u32>().run(),
+            CellWidth::Bits64 => self.build::<u64>().run(),
         }
     }
 }
 
+/// Runs a program one instruction at a time.
 #[derive(Debug)]
-pub struct Interpreter {
-    program: Vec<Token>,
-    tape: Vec<u8>,
+pub struct Interpreter<C: Cell = u8, R: Read = Stdin, W: Write = Stdout> {
+    program: Vec<Spanned<Instruction>>,
+    tape: Vec<C>,
     index: usize,
+    /// Index of the cell the data pointer started on, which moves when the
+    /// tape grows to the left.
+    origin: usize,
+    tape_mode: Tape,
     pc: usize,
-    input_buffer: Vec<u8>,
+    input: BufReader<R>,
+    output: BufWriter<W>,
     wrapping: bool,
+    signed: bool,
+    eof: Eof,
+    flush: Flush,
+    /// Input bytes handed back by [`Interpreter::undo`], read before `input`.
+    unread: Vec<u8>,
+    /// The byte the last `,` read, if it read one.
+    last_read: Option<u8>,
+    coverage: Option<Coverage>,
 }
 
-impl Interpreter {
+/// What one step changed, so that [`Interpreter::undo`] can take it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo<C> {
+    pc: usize,
+    index: usize,
+    /// The cell the instruction could write, with its old value.
+    cell: Option<(usize, C)>,
+    /// The input byte the instruction read.
+    read: Option<u8>,
+}
+
+impl<C: Cell> Undo<C> {
+    /// Index of the instruction that ran.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The cell the instruction could write, with its value from before.
+    pub fn cell(&self) -> Option<(usize, C)> {
+        self.cell
+    }
+}
+
+impl<C: Cell, R: Read, W: Write> Interpreter<C, R, W> {
     pub fn run(mut self) -> Result<(), InterpreterError> {
-        while self.pc < self.program.len() {
-            match self.program[self.pc] {
-                Token::Right(count) => {
-                    self.index = (self.index + count < self.tape.capacity())
-                        .then(|| self.index + count)
-                        .ok_or(InterpreterError::OutOfBounds)?
+        self.finish()
+    }
+
+    /// Runs the rest of the program, keeping the interpreter around so that
+    /// its state can be looked at afterwards.
+    pub fn finish(&mut self) -> Result<(), InterpreterError> {
+        let result = self.execute();
+        self.output.flush()?;
+        result
+    }
+
+    fn execute(&mut self) -> Result<(), InterpreterError> {
+        while !self.finished() {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes the instruction at the program counter. Does nothing once the
+    /// program has finished.
+    pub fn step(&mut self) -> Result<(), InterpreterError> {
+        if self.finished() {
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        let instruction = self.program[pc].node;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit(pc);
+        }
+
+        match instruction {
+            Instruction::Move(distance) => self.index = self.cell(distance)?,
+            Instruction::Add { offset, value } => {
+                let cell = self.cell(offset)?;
+
+                if self.wrapping {
+                    self.tape[cell] = self.tape[cell].wrapping_offset(value as i128);
+                } else {
+                    self.tape[cell] = self.tape[cell]
+                        .checked_offset(value as i128, self.signed)
+                        .ok_or(if value > 0 {
+                            InterpreterError::IntegerOverflow(self.span())
+                        } else {
+                            InterpreterError::IntegerUnderflow(self.span())
+                        })?
                 }
-                Token::Left(count) => {
-                    self.index = self
-                        .index
-                        .checked_sub(count)
-                        .ok_or(InterpreterError::OutOfBounds)?
+            }
+            Instruction::Clear { offset } => {
+                let cell = self.cell(offset)?;
+
+                // Counting a negative signed cell down to zero runs past its minimum.
+                if !self.wrapping && self.tape[cell].value(self.signed) < 0 {
+                    return Err(InterpreterError::IntegerUnderflow(self.span()));
                 }
-                Token::Add(count) => {
-                    if self.wrapping {
-                        self.tape[self.index] = self.tape[self.index].wrapping_add(count);
-                    } else {
-                        self.tape[self.index] = self.tape[self.index]
-                            .checked_add(count)
-                            .ok_or(InterpreterError::IntegerOverflow(self.pc))?
-                    }
+
+                self.tape[cell] = C::default();
+            }
+            Instruction::MulAdd { offset, factor } => {
+                let counter = self.cell(0)?;
+                let counter = self.tape[counter];
+
+                // The loop this came from would not have run at all.
+                if counter == C::default() {
+                    self.pc += 1;
+                    return Ok(());
                 }
-                Token::Sub(count) => {
-                    if self.wrapping {
-                        self.tape[self.index] = self.tape[self.index].wrapping_sub(count);
-                    } else {
-                        self.tape[self.index] = self.tape[self.index]
-                            .checked_sub(count)
-                            .ok_or(InterpreterError::IntegerUnderflow(self.pc))?
+
+                let cell = self.cell(offset)?;
+
+                if self.wrapping {
+                    let product = counter.value(false).wrapping_mul(factor as i128);
+                    self.tape[cell] = self.tape[cell].wrapping_offset(product);
+                } else {
+                    let counter = counter.value(self.signed);
+                    if counter < 0 {
+                        return Err(InterpreterError::IntegerUnderflow(self.span()));
                     }
+
+                    self.tape[cell] = counter
+                        .checked_mul(factor as i128)
+                        .and_then(|delta| self.tape[cell].checked_offset(delta, self.signed))
+                        .ok_or(if factor > 0 {
+                            InterpreterError::IntegerOverflow(self.span())
+                        } else {
+                            InterpreterError::IntegerUnderflow(self.span())
+                        })?
                 }
-                Token::Read => {
-                    if let Some(value) = self.input_buffer.pop() {
-                        self.tape[self.index] = value;
-                    } else {
-                        let mut buf = String::new();
-                        std::io::stdin().read_line(&mut buf).unwrap();
-                        self.input_buffer = buf.into_bytes().into_iter().rev().collect();
-                        self.tape[self.index] = self.input_buffer.pop().unwrap();
+            }
+            Instruction::Scan(stride) => {
+                // Like the loop it came from, the scan first reads the current cell.
+                self.cell(0)?;
+                let step = stride.unsigned_abs();
+                let found = match (stride > 0, step) {
+                    (true, 1) => C::find_zero(&self.tape[self.index..]),
+                    (false, 1) => {
+                        C::rfind_zero(&self.tape[..=self.index]).map(|cell| self.index - cell)
                     }
+                    (true, _) => self.tape[self.index..]
+                        .iter()
+                        .step_by(step)
+                        .position(|&cell| cell == C::default())
+                        .map(|steps| steps * step),
+                    (false, _) => self.tape[..=self.index]
+                        .iter()
+                        .rev()
+                        .step_by(step)
+                        .position(|&cell| cell == C::default())
+                        .map(|steps| steps * step),
+                };
+
+                // Past the edge of the tape every cell is zero, so the scan
+                // stops at the first position there. Running off a tape that
+                // cannot grow is what the original loop would have done.
+                let distance = found.unwrap_or(if stride > 0 {
+                    (self.tape.len() - self.index).div_ceil(step) * step
+                } else {
+                    (self.index / step + 1) * step
+                });
+                self.index = self.cell(if stride > 0 {
+                    distance as isize
+                } else {
+                    -(distance as isize)
+                })?;
+            }
+            Instruction::Input { offset } => {
+                let cell = self.cell(offset)?;
+                self.output.flush()?;
+
+                // `bytes` retries interrupted reads, and ends at EOF.
+                let byte = match self.unread.pop() {
+                    Some(byte) => Some(byte),
+                    None => self.input.by_ref().bytes().next().transpose()?,
+                };
+                self.last_read = byte;
+
+                match (byte, self.eof) {
+                    (Some(value), _) => self.tape[cell] = C::from_byte(value),
+                    (None, Eof::Unchanged) => (),
+                    (None, Eof::Zero) => self.tape[cell] = C::default(),
+                    (None, Eof::MinusOne) => self.tape[cell] = C::default().wrapping_offset(-1),
                 }
-                Token::Write => {
-                    print!("{}", self.tape[self.index] as char)
+            }
+            Instruction::Output { offset } => {
+                let cell = self.cell(offset)?;
+                let byte = self.tape[cell].low_byte();
+                self.output.write_all(&[byte])?;
+
+                if self.flush == Flush::Always || (self.flush == Flush::Line && byte == b'\n') {
+                    self.output.flush()?;
                 }
-                Token::Loop(jump) => {
-                    if self.tape[self.index] != 0 {
-                        self.pc = jump
-                    }
+            }
+            Instruction::LoopEnd(jump) => {
+                let cell = self.cell(0)?;
+                if self.tape[cell] != C::default() {
+                    self.pc = jump
                 }
-                Token::Break(jump) => {
-                    if self.tape[self.index] == 0 {
-                        self.pc = jump;
-                    }
+            }
+            Instruction::Debug => {
+                self.output.flush()?;
+                writeln!(io::stderr(), "{}", self.dump())?;
+            }
+            Instruction::LoopStart(jump) => {
+                let cell = self.cell(0)?;
+                if self.tape[cell] == C::default() {
+                    self.pc = jump;
                 }
-                Token::Comment => (),
             }
-            self.pc += 1;
+        }
+        self.pc += 1;
+
+        if let (Some(coverage), Instruction::LoopStart(_) | Instruction::LoopEnd(_)) =
+            (&mut self.coverage, instruction)
+        {
+            coverage.branch(pc, self.pc != pc + 1);
         }
 
         Ok(())
     }
+
+    /// Like [`Interpreter::step`], but also returns what is needed to undo the
+    /// step. Steps that grow a bidirectional tape to the left cannot be
+    /// undone.
+    pub fn step_undoable(&mut self) -> Result<Undo<C>, InterpreterError> {
+        let offset = match self.program.get(self.pc).map(|i| i.node) {
+            Some(
+                Instruction::Add { offset, .. }
+                | Instruction::Clear { offset }
+                | Instruction::MulAdd { offset, .. }
+                | Instruction::Input { offset },
+            ) => Some(offset),
+            _ => None,
+        };
+        let (pc, index) = (self.pc, self.index);
+        let cell = offset
+            .and_then(|offset| self.cell(offset).ok())
+            .map(|cell| (cell, self.tape[cell]));
+
+        self.last_read = None;
+        self.step()?;
+
+        Ok(Undo {
+            pc,
+            index,
+            cell,
+            read: self.last_read,
+        })
+    }
+
+    /// Takes back a step. Steps must be undone from the latest to the
+    /// earliest. Input that was read is read again, but output stays written.
+    pub fn undo(&mut self, undo: Undo<C>) {
+        self.pc = undo.pc;
+        self.index = undo.index;
+
+        if let Some((cell, value)) = undo.cell {
+            self.tape[cell] = value;
+        }
+
+        if let Some(byte) = undo.read {
+            self.unread.push(byte);
+        }
+    }
+
+    /// Hands buffered output to the underlying writer.
+    pub fn flush(&mut self) -> Result<(), InterpreterError> {
+        Ok(self.output.flush()?)
+    }
+
+    /// What ran so far, if the interpreter was built to record it.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Whether every instruction has run.
+    pub fn finished(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    pub fn program(&self) -> &[Spanned<Instruction>] {
+        &self.program
+    }
+
+    /// Index of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Index of the cell the data pointer is on.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Index of the cell the data pointer started on. It is 0 unless a
+    /// bidirectional tape grew to the left.
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    pub fn tape(&self) -> &[C] {
+        &self.tape
+    }
+
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Formats the cells within `radius` of the data pointer, the current one
+    /// in brackets, with their values below them.
+    pub fn tape_window(&self, radius: usize) -> String {
+        // An empty tape has no cells to show.
+        let end = (self.index + radius + 1).min(self.tape.len());
+        let start = self.index.saturating_sub(radius).min(end);
+        let (mut cells, mut values) = (String::from("cell "), String::from("value"));
+
+        for (i, cell) in self.tape[start..end].iter().enumerate() {
+            let i = start + i;
+            let label = if i == self.index {
+                format!("[{i}]")
+            } else {
+                i.to_string()
+            };
+            let value = cell.value(self.signed).to_string();
+            let width = label.len().max(value.len());
+
+            cells.push_str(&format!(" {label:>width$}"));
+            values.push_str(&format!(" {value:>width$}"));
+        }
+
+        format!("{cells}\n{values}")
+    }
+
+    /// The line a `Debug` instruction writes, without its newline.
+    ///
+    /// Cells are numbered from the one the data pointer started on, and the
+    /// cells a growable tape has not reached yet show as 0.
+    fn dump(&self) -> String {
+        let index = self.index as isize - self.origin as isize;
+        let (first, last) = match self.tape_mode {
+            Tape::Fixed => (0, self.tape.len() as isize - 1),
+            Tape::Growable => (0, isize::MAX),
+            Tape::Bidirectional => (isize::MIN, isize::MAX),
+        };
+        let start = index.saturating_sub(DUMP_RADIUS as isize).max(first);
+        let end = index.saturating_add(DUMP_RADIUS as isize).min(last);
+        let cells: String = (start..=end)
+            .map(|i| {
+                let cell = usize::try_from(i + self.origin as isize)
+                    .ok()
+                    .and_then(|i| self.tape.get(i))
+                    .copied()
+                    .unwrap_or_default();
+                match cell.value(self.signed) {
+                    value if i == index => format!(" [{value}]"),
+                    value => format!(" {value}"),
+                }
+            })
+            .collect();
+
+        format!("# {} cell {index}:{cells}", self.span())
+    }
+
+    /// The span of the instruction being executed.
+    fn span(&self) -> Span {
+        self.program[self.pc].span
+    }
+
+    /// Index of the cell at `offset` from the data pointer, growing the tape
+    /// to reach it if it may.
+    fn cell(&mut self, offset: isize) -> Result<usize, InterpreterError> {
+        match (self.index.checked_add_signed(offset), self.tape_mode) {
+            (Some(cell), _) if cell < self.tape.len() => Ok(cell),
+            (Some(cell), Tape::Growable | Tape::Bidirectional) => {
+                // Doubling keeps growing cell by cell linear overall.
+                let length = (cell + 1).max(2 * self.tape.len());
+                self.tape.resize(length, C::default());
+                Ok(cell)
+            }
+            (None, Tape::Bidirectional) => {
+                let missing = offset.unsigned_abs() - self.index;
+                let added = missing.max(self.tape.len());
+                self.tape
+                    .splice(0..0, std::iter::repeat_n(C::default(), added));
+                self.index += added;
+                self.origin += added;
+                Ok(self.index - offset.unsigned_abs())
+            }
+            _ => Err(InterpreterError::OutOfBounds(self.span())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ir::{lower, optimize},
+        parser::{ast, parse},
+    };
+    use rstest::*;
+
+    fn run(source: &str, input: &[u8], eof: Eof) -> Vec<u8> {
+        let mut output = vec![];
+
+        InterpreterBuilder::new()
+            .program(optimize(lower(&ast(source).unwrap())))
+            .input(input)
+            .output(&mut output)
+            .eof(eof)
+            .build::<u8>()
+            .run()
+            .unwrap();
+
+        output
+    }
+
+    #[rstest]
+    #[case("++++++++[>++++++++<-]>+.", b"", b"A")]
+    #[case(",[.,]", b"\x00", b"")]
+    #[case(",[.,]", b"\xff\x80\n", b"\xff\x80\n")]
+    #[case(",.,.,.", b"a", b"a\0\0")]
+    fn test_io(#[case] source: &str, #[case] input: &[u8], #[case] expected: &[u8]) {
+        assert_eq!(run(source, input, Eof::Zero), expected);
+    }
+
+    #[rstest]
+    #[case(Eof::Unchanged, b"\x05")]
+    #[case(Eof::Zero, b"\x00")]
+    #[case(Eof::MinusOne, b"\xff")]
+    fn test_eof(#[case] eof: Eof, #[case] expected: &[u8]) {
+        assert_eq!(run("+++++,.", b"", eof), expected);
+    }
+
+    /// The value, read as signed, of the cell `+++++,` leaves at EOF.
+    fn read_at_eof<C: Cell>(eof: Eof) -> i128 {
+        let mut interpreter = InterpreterBuilder::new()
+            .program(lower(&ast("+++++,").unwrap()))
+            .input(&b""[..])
+            .output(vec![])
+            .eof(eof)
+            .build::<C>();
+
+        interpreter.finish().unwrap();
+        interpreter.tape()[0].value(true)
+    }
+
+    #[rstest]
+    #[case(Eof::Unchanged, 5)]
+    #[case(Eof::Zero, 0)]
+    #[case(Eof::MinusOne, -1)]
+    fn test_eof_widths(
+        #[case] eof: Eof,
+        #[case] expected: i128,
+        #[values(
+            CellWidth::Bits8,
+            CellWidth::Bits16,
+            CellWidth::Bits32,
+            CellWidth::Bits64
+        )]
+        width: CellWidth,
+    ) {
+        let value = match width {
+            CellWidth::Bits8 => read_at_eof::<u8>(eof),
+            CellWidth::Bits16 => read_at_eof::<u16>(eof),
+            CellWidth::Bits32 => read_at_eof::<u32>(eof),
+            CellWidth::Bits64 => read_at_eof::<u64>(eof),
+        };
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut interpreter = InterpreterBuilder::new()
+            .program(lower(&ast(",>,[-<+>]<").unwrap()))
+            .input(&b"ab"[..])
+            .output(vec![])
+            .build::<u8>();
+
+        let mut history = vec![];
+        while !interpreter.finished() {
+            history.push(interpreter.step_undoable().unwrap());
+        }
+        assert_eq!(interpreter.tape()[..2], [195, 0]);
+
+        for undo in history.into_iter().rev() {
+            interpreter.undo(undo);
+        }
+        assert_eq!((interpreter.pc(), interpreter.index()), (0, 0));
+        assert_eq!(interpreter.tape()[..2], [0, 0]);
+
+        // The input is read again, in the same order.
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.tape()[..2], [b'a', b'b']);
+    }
+
+    #[rstest]
+    #[case("+++[->++<]", false, true)]
+    #[case("-[-]", true, false)]
+    #[case("+[-]", true, true)]
+    #[case("+++++++++++++++[->++++++++++++++++<]", false, true)]
+    #[case("+++++++++++++++[->++++++++++++++++<]", true, false)]
+    #[case("++++++++++++++++[->++++++++++++++++<]", false, false)]
+    #[case("++++++++[->--------<]", false, false)]
+    #[case("++++++++[->--------<]", true, true)]
+    fn test_no_wrap(#[case] source: &str, #[case] signed: bool, #[case] ok: bool) {
+        let result = InterpreterBuilder::new()
+            .program(optimize(lower(&ast(source).unwrap())))
+            .input(&b""[..])
+            .output(vec![])
+            .wrapping(false)
+            .signed(signed)
+            .build::<u8>()
+            .run();
+
+        assert_eq!(result.is_ok(), ok);
+    }
+
+    #[rstest]
+    #[case("<", Tape::Fixed, false)]
+    #[case(">>>>+", Tape::Fixed, false)]
+    #[case(">>>>+<<<<<", Tape::Growable, false)]
+    #[case(">>>>+[>]>+", Tape::Growable, true)]
+    #[case("<<<<+>>>>>+[<]<-", Tape::Bidirectional, true)]
+    #[case("+<<<<<<[-<<<]", Tape::Bidirectional, true)]
+    #[case("[-]+.", Tape::Growable, true)]
+    #[case("[-]+.", Tape::Bidirectional, true)]
+    fn test_tape(
+        #[case] source: &str,
+        #[case] tape: Tape,
+        #[case] ok: bool,
+        #[values(0, 2)] memory: usize,
+    ) {
+        for program in [
+            lower(&ast(source).unwrap()),
+            optimize(lower(&ast(source).unwrap())),
+        ] {
+            let result = InterpreterBuilder::new()
+                .program(program)
+                .input(&b""[..])
+                .output(vec![])
+                .memory(memory)
+                .tape(tape)
+                .build::<u8>()
+                .run();
+
+            assert_eq!(result.is_ok(), ok);
+        }
+    }
+
+    #[rstest]
+    #[case("[]")]
+    #[case("[-]")]
+    #[case("[->+<]")]
+    #[case("[<]")]
+    #[case("[>>]")]
+    fn test_empty_fixed_tape(#[case] source: &str) {
+        for program in [
+            lower(&ast(source).unwrap()),
+            optimize(lower(&ast(source).unwrap())),
+        ] {
+            let result = InterpreterBuilder::new()
+                .program(program)
+                .input(&b""[..])
+                .output(vec![])
+                .memory(0)
+                .build::<u8>()
+                .run();
+
+            assert!(matches!(result, Err(InterpreterError::OutOfBounds(_))));
+        }
+    }
+
+    #[test]
+    fn test_grow_left() {
+        let tokens = parse("+<<<--<+>#", true).tokens.unwrap();
+        let mut interpreter = InterpreterBuilder::new()
+            .program(optimize(lower(&tokens)))
+            .input(&b""[..])
+            .output(vec![])
+            .memory(2)
+            .tape(Tape::Bidirectional)
+            .build::<u8>();
+
+        while interpreter.program()[interpreter.pc()].node != Instruction::Debug {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.origin() - interpreter.index(), 3);
+        assert_eq!(interpreter.dump(), "# 1:10 cell -3: 0 0 0 1 [254] 0 0 1 0");
+    }
+
+    #[rstest]
+    #[case("#", 0, "# 1:1 cell 0: [0] 0 0 0 0")]
+    #[case("+>--\n>>>>>>#", 3, "# 2:7 cell 7: 0 0 0 0 [0] 0 0")]
+    #[case(">>>>>>>>-#", 2, "# 1:10 cell 8: 0 0 0 0 [-1] 0")]
+    fn test_dump(#[case] source: &str, #[case] steps: usize, #[case] expected: &str) {
+        let tokens = parse(source, true).tokens.unwrap();
+        let mut interpreter = InterpreterBuilder::new()
+            .program(optimize(lower(&tokens)))
+            .input(&b""[..])
+            .output(vec![])
+            .signed(true)
+            .memory(10)
+            .build::<u8>();
+
+        for _ in 0..steps {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.dump(), expected);
+    }
 }
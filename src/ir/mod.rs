@@ -0,0 +1,96 @@
+use crate::{parser::Token, span::Spanned};
+
+pub mod passes;
+
+/// An instruction of the intermediate representation that sits between
+/// parsing and execution.
+///
+/// Cell accesses carry an offset relative to the data pointer, so that a
+/// straight run of code only has to move the pointer once at its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Moves the data pointer.
+    Move(isize),
+    /// Adds `value` to the cell at `offset`. Negative values subtract.
+    Add { offset: isize, value: isize },
+    /// Sets the cell at `offset` to zero.
+    Clear { offset: isize },
+    /// Adds the current cell times `factor` to the cell at `offset`.
+    MulAdd { offset: isize, factor: isize },
+    /// Moves the data pointer by `stride` until it lands on a zero cell.
+    Scan(isize),
+    /// Reads a byte into the cell at `offset`.
+    Input { offset: isize },
+    /// Writes the cell at `offset`.
+    Output { offset: isize },
+    /// Jumps to the matching `LoopEnd` if the current cell is zero.
+    LoopStart(usize),
+    /// Jumps back to the matching `LoopStart` if the current cell is not zero.
+    LoopEnd(usize),
+    /// Writes a line like `# 3:5 cell 12: 0 0 [72] 101 0` to stderr: where
+    /// in the source the dump is, the index of the current cell, and the
+    /// values of the cells within [`DUMP_RADIUS`] of it.
+    Debug,
+}
+
+/// How many cells on each side of the current one `Debug` shows.
+pub const DUMP_RADIUS: usize = 4;
+
+/// Translates tokens into instructions one to one, without optimizing. Each
+/// instruction keeps the span of its token.
+pub fn lower(tokens: &[Spanned<Token>]) -> Vec<Spanned<Instruction>> {
+    let program = tokens
+        .iter()
+        .filter_map(|&Spanned { node, span }| {
+            let instruction = match node {
+                Token::Right(count) => Instruction::Move(count as isize),
+                Token::Left(count) => Instruction::Move(-(count as isize)),
+                Token::Add(count) => Instruction::Add {
+                    offset: 0,
+                    value: count as isize,
+                },
+                Token::Sub(count) => Instruction::Add {
+                    offset: 0,
+                    value: -(count as isize),
+                },
+                Token::Read => Instruction::Input { offset: 0 },
+                Token::Write => Instruction::Output { offset: 0 },
+                Token::Break(_) => Instruction::LoopStart(0),
+                Token::Loop(_) => Instruction::LoopEnd(0),
+                Token::Debug => Instruction::Debug,
+                Token::Comment => return None,
+            };
+
+            Some(Spanned::new(instruction, span))
+        })
+        .collect();
+
+    link(program)
+}
+
+/// Recomputes the jump targets of every loop. Passes that add or remove
+/// instructions call this once they are done.
+///
+/// The brackets must already be balanced, which the parser guarantees.
+pub fn link(mut program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+    let mut stack = vec![];
+
+    for i in 0..program.len() {
+        match program[i].node {
+            Instruction::LoopStart(_) => stack.push(i),
+            Instruction::LoopEnd(_) => {
+                let start = stack.pop().expect("unbalanced loop in IR");
+                program[start].node = Instruction::LoopStart(i);
+                program[i].node = Instruction::LoopEnd(start);
+            }
+            _ => (),
+        }
+    }
+
+    program
+}
+
+/// Runs every optimization pass. Use a [`passes::PassManager`] to pick them.
+pub fn optimize(program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+    passes::PassManager::default().run(program)
+}
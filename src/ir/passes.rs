@@ -0,0 +1,398 @@
+use super::{link, Instruction};
+use crate::span::{Span, Spanned};
+use anyhow::anyhow;
+#[cfg(feature = "cli")]
+use clap::{builder::PossibleValue, ValueEnum};
+
+/// The highest optimization level.
+pub const MAX_LEVEL: u8 = 3;
+
+/// A named optimization pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    FoldOffsets,
+    FoldLoops,
+    FoldScans,
+}
+
+impl Pass {
+    /// Every pass, in the order the pipeline runs them.
+    pub const ALL: [Pass; 3] = [Pass::FoldOffsets, Pass::FoldLoops, Pass::FoldScans];
+
+    /// The lowest optimization level that enables this pass.
+    pub fn level(self) -> u8 {
+        match self {
+            Pass::FoldOffsets => 1,
+            Pass::FoldLoops => 2,
+            Pass::FoldScans => 3,
+        }
+    }
+
+    pub fn run(self, program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        match self {
+            Pass::FoldOffsets => fold_offsets(program),
+            Pass::FoldLoops => fold_loops(program),
+            Pass::FoldScans => fold_scans(program),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ValueEnum for Pass {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Self::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Pass> for &str {
+    fn from(value: Pass) -> Self {
+        match value {
+            Pass::FoldOffsets => "fold-offsets",
+            Pass::FoldLoops => "fold-loops",
+            Pass::FoldScans => "fold-scans",
+        }
+    }
+}
+
+impl TryFrom<&str> for Pass {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Pass::ALL
+            .into_iter()
+            .find(|&pass| Into::<&str>::into(pass) == value)
+            .ok_or(anyhow!("{} is not a known pass", &value))
+    }
+}
+
+/// Decides which passes run, and runs them.
+///
+/// Passes always run in the order of [`Pass::ALL`], regardless of the order
+/// they were enabled in, so that toggling one pass never reorders the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassManager {
+    enabled: Vec<Pass>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new(MAX_LEVEL)
+    }
+}
+
+impl PassManager {
+    /// Enables every pass up to optimization `level`.
+    pub fn new(level: u8) -> Self {
+        Self {
+            enabled: Pass::ALL
+                .into_iter()
+                .filter(|pass| pass.level() <= level)
+                .collect(),
+        }
+    }
+
+    pub fn enable(&mut self, pass: Pass) -> &mut Self {
+        if !self.enabled.contains(&pass) {
+            self.enabled.push(pass);
+        }
+        self
+    }
+
+    pub fn disable(&mut self, pass: Pass) -> &mut Self {
+        self.enabled.retain(|&p| p != pass);
+        self
+    }
+
+    /// The passes that will run, in order.
+    pub fn passes(&self) -> impl Iterator<Item = Pass> + '_ {
+        Pass::ALL
+            .into_iter()
+            .filter(|pass| self.enabled.contains(pass))
+    }
+
+    pub fn run(&self, program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        self.passes()
+            .fold(program, |program, pass| pass.run(program))
+    }
+}
+
+/// Replaces pointer moves inside straight-line code with offsets on the
+/// instructions that access cells.
+///
+/// The accumulated move is only materialized right before a loop bracket (the
+/// loop condition reads the current cell), a debug dump and at the end of the
+/// program. It spans all of the moves it replaces.
+pub fn fold_offsets(program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+    let mut folded = Vec::with_capacity(program.len());
+    let mut offset = 0;
+    let mut moves: Option<Span> = None;
+
+    for Spanned { node, span } in program {
+        let instruction = match node {
+            Instruction::Move(distance) => {
+                offset += distance;
+                moves = Some(moves.map_or(span, |moves| moves.to(span)));
+                continue;
+            }
+            Instruction::Add { offset: o, value } => Instruction::Add {
+                offset: offset + o,
+                value,
+            },
+            Instruction::Clear { offset: o } => Instruction::Clear { offset: offset + o },
+            Instruction::MulAdd { offset: o, factor } => Instruction::MulAdd {
+                offset: offset + o,
+                factor,
+            },
+            Instruction::Input { offset: o } => Instruction::Input { offset: offset + o },
+            Instruction::Output { offset: o } => Instruction::Output { offset: offset + o },
+            Instruction::Scan(_)
+            | Instruction::LoopStart(_)
+            | Instruction::LoopEnd(_)
+            | Instruction::Debug => {
+                if let Some(moves) = moves.take().filter(|_| offset != 0) {
+                    folded.push(Spanned::new(Instruction::Move(offset), moves));
+                }
+                offset = 0;
+                node
+            }
+        };
+
+        folded.push(Spanned::new(instruction, span));
+    }
+
+    if let Some(moves) = moves.filter(|_| offset != 0) {
+        folded.push(Spanned::new(Instruction::Move(offset), moves));
+    }
+
+    link(folded)
+}
+
+/// Replaces loops that count the current cell down to zero, adding multiples
+/// of it to other cells along the way, with `MulAdd`s and a `Clear`.
+///
+/// This matches `[-]`, `[->+<]`, `[->++>+++<<]` and the like: loop bodies made
+/// only of additions, with no net pointer movement, that subtract exactly one
+/// from the current cell per iteration. Run `fold_offsets` first so that the
+/// pointer moves inside such bodies have been turned into offsets. The
+/// replacement instructions span the whole loop.
+pub fn fold_loops(program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+    let mut folded: Vec<Spanned<Instruction>> = Vec::with_capacity(program.len());
+    let mut starts = vec![];
+
+    for instruction in program {
+        match instruction.node {
+            Instruction::LoopStart(_) => starts.push(folded.len()),
+            Instruction::LoopEnd(_) => {
+                let start = starts.pop().expect("unbalanced loop in IR");
+
+                if let Some(replacement) = multiplication(&folded[start + 1..]) {
+                    let span = folded[start].span.to(instruction.span);
+                    folded.truncate(start);
+                    folded.extend(replacement.into_iter().map(|node| Spanned::new(node, span)));
+                    continue;
+                }
+            }
+            _ => (),
+        }
+
+        folded.push(instruction);
+    }
+
+    link(folded)
+}
+
+/// The `MulAdd`s and `Clear` equivalent to a loop `body`, if there are any.
+fn multiplication(body: &[Spanned<Instruction>]) -> Option<Vec<Instruction>> {
+    let mut counter = 0;
+    let mut factors: Vec<(isize, isize)> = vec![];
+
+    for instruction in body {
+        match instruction.node {
+            Instruction::Add { offset: 0, value } => counter += value,
+            Instruction::Add { offset, value } => {
+                match factors.iter_mut().find(|(o, _)| *o == offset) {
+                    Some((_, factor)) => *factor += value,
+                    None => factors.push((offset, value)),
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    (counter == -1).then(|| {
+        factors
+            .into_iter()
+            .filter(|&(_, factor)| factor != 0)
+            .map(|(offset, factor)| Instruction::MulAdd { offset, factor })
+            .chain([Instruction::Clear { offset: 0 }])
+            .collect()
+    })
+}
+
+/// Replaces loops that only move the pointer, such as `[>]` or `[<<<<]`,
+/// with a `Scan` spanning the whole loop.
+pub fn fold_scans(program: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+    let mut folded: Vec<Spanned<Instruction>> = Vec::with_capacity(program.len());
+
+    for instruction in program {
+        if let (
+            Instruction::LoopEnd(_),
+            [.., Spanned {
+                node: Instruction::LoopStart(_),
+                span: start,
+            }, Spanned {
+                node: Instruction::Move(stride),
+                ..
+            }],
+        ) = (instruction.node, folded.as_slice())
+        {
+            let scan = Spanned::new(Instruction::Scan(*stride), start.to(instruction.span));
+            folded.truncate(folded.len() - 2);
+            folded.push(scan);
+            continue;
+        }
+
+        folded.push(instruction);
+    }
+
+    link(folded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ir::{lower, optimize},
+        parser::{ast, parse},
+    };
+    use rstest::*;
+
+    #[allow(unused)]
+    type I = Instruction;
+
+    fn nodes(program: Vec<Spanned<I>>) -> Vec<I> {
+        program.into_iter().map(|i| i.node).collect()
+    }
+
+    #[rstest]
+    #[case("", vec![])]
+    #[case(">+>+<<", vec![I::Add { offset: 1, value: 1 }, I::Add { offset: 2, value: 1 }])]
+    #[case(">>-.", vec![I::Add { offset: 2, value: -1 }, I::Output { offset: 2 }, I::Move(2)])]
+    #[case("<,>", vec![I::Input { offset: -1 }])]
+    #[case(">[>+<-]<", vec![
+        I::Move(1),
+        I::LoopStart(4),
+        I::Add { offset: 1, value: 1 },
+        I::Add { offset: 0, value: -1 },
+        I::LoopEnd(1),
+        I::Move(-1),
+    ])]
+    #[case("[>]", vec![I::LoopStart(2), I::Move(1), I::LoopEnd(0)])]
+    #[case(">#>+", vec![I::Move(1), I::Debug, I::Add { offset: 1, value: 1 }, I::Move(1)])]
+    fn test_fold_offsets(#[case] source: &str, #[case] expected: Vec<I>) {
+        let tokens = parse(source, true).tokens.unwrap();
+
+        assert_eq!(nodes(fold_offsets(lower(&tokens))), expected);
+    }
+
+    #[rstest]
+    #[case("[-]", vec![I::Clear { offset: 0 }])]
+    #[case("[+]", vec![I::LoopStart(2), I::Add { offset: 0, value: 1 }, I::LoopEnd(0)])]
+    #[case("[->+<]", vec![I::MulAdd { offset: 1, factor: 1 }, I::Clear { offset: 0 }])]
+    #[case("[>+<-]", vec![I::MulAdd { offset: 1, factor: 1 }, I::Clear { offset: 0 }])]
+    #[case("[->++>+++<<]", vec![
+        I::MulAdd { offset: 1, factor: 2 },
+        I::MulAdd { offset: 2, factor: 3 },
+        I::Clear { offset: 0 },
+    ])]
+    #[case("[-<->>+<]", vec![
+        I::MulAdd { offset: -1, factor: -1 },
+        I::MulAdd { offset: 1, factor: 1 },
+        I::Clear { offset: 0 },
+    ])]
+    #[case("[->+>]", vec![
+        I::LoopStart(4),
+        I::Add { offset: 0, value: -1 },
+        I::Add { offset: 1, value: 1 },
+        I::Move(2),
+        I::LoopEnd(0),
+    ])]
+    #[case("[->.<]", vec![
+        I::LoopStart(3),
+        I::Add { offset: 0, value: -1 },
+        I::Output { offset: 1 },
+        I::LoopEnd(0),
+    ])]
+    #[case("+[[-]>]", vec![
+        I::Add { offset: 0, value: 1 },
+        I::LoopStart(4),
+        I::Clear { offset: 0 },
+        I::Move(1),
+        I::LoopEnd(1),
+    ])]
+    fn test_fold_loops(#[case] source: &str, #[case] expected: Vec<I>) {
+        assert_eq!(
+            nodes(fold_loops(fold_offsets(lower(&ast(source).unwrap())))),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("[>]", vec![I::Scan(1)])]
+    #[case("[<<<<]", vec![I::Scan(-4)])]
+    #[case("[>>-<]", vec![I::LoopStart(3), I::Add { offset: 2, value: -1 }, I::Move(1), I::LoopEnd(0)])]
+    #[case("+[[>]<]", vec![
+        I::Add { offset: 0, value: 1 },
+        I::LoopStart(4),
+        I::Scan(1),
+        I::Move(-1),
+        I::LoopEnd(1),
+    ])]
+    #[case("[[>]]", vec![I::LoopStart(2), I::Scan(1), I::LoopEnd(0)])]
+    fn test_fold_scans(#[case] source: &str, #[case] expected: Vec<I>) {
+        assert_eq!(
+            nodes(fold_scans(fold_offsets(lower(&ast(source).unwrap())))),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(">+>", vec![(1, 2), (0, 3)])]
+    #[case("+[->+<]>", vec![(0, 1), (1, 7), (1, 7), (7, 8)])]
+    #[case("[>>]", vec![(0, 4)])]
+    fn test_spans(#[case] source: &str, #[case] expected: Vec<(usize, usize)>) {
+        let spans: Vec<_> = optimize(lower(&ast(source).unwrap()))
+            .into_iter()
+            .map(|i| (i.span.start, i.span.end))
+            .collect();
+
+        assert_eq!(spans, expected);
+    }
+
+    #[rstest]
+    #[case(0, &[], &[], &[])]
+    #[case(1, &[], &[], &[Pass::FoldOffsets])]
+    #[case(3, &[], &[], &Pass::ALL)]
+    #[case(3, &[], &[Pass::FoldLoops], &[Pass::FoldOffsets, Pass::FoldScans])]
+    #[case(0, &[Pass::FoldScans, Pass::FoldOffsets], &[], &[Pass::FoldOffsets, Pass::FoldScans])]
+    fn test_pass_manager(
+        #[case] level: u8,
+        #[case] enable: &[Pass],
+        #[case] disable: &[Pass],
+        #[case] expected: &[Pass],
+    ) {
+        let mut manager = PassManager::new(level);
+        enable.iter().for_each(|&pass| {
+            manager.enable(pass);
+        });
+        disable.iter().for_each(|&pass| {
+            manager.disable(pass);
+        });
+
+        assert_eq!(manager.passes().collect::<Vec<_>>(), expected);
+    }
+}
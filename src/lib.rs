@@ -0,0 +1,47 @@
+//! Năuc is a brainfuck interpreter and compiler.
+//!
+//! A program goes through the same stages whether it is interpreted or compiled:
+//!
+//! 1. [`parser::ast`] turns source code into a list of [`parser::Token`]s,
+//!    folding runs of the same instruction together and resolving the jump
+//!    targets of loops. Every token, and every instruction made from it,
+//!    carries the [`span::Span`] of source it came from.
+//! 2. [`ir::lower`] translates the tokens into the intermediate representation,
+//!    which [`ir::optimize`] then rewrites into something cheaper to run.
+//! 3. [`interpreter::InterpreterBuilder`] runs the instructions directly, or
+//! 4. [`compiler::codegen`] turns them into assembly for a [`compiler::arch::Target`],
+//!    which [`compiler::compile`] then assembles and links into an executable.
+//!
+//! ```
+//! use nauc::{
+//!     cell::CellWidth,
+//!     interpreter::InterpreterBuilder,
+//!     ir::{lower, optimize},
+//!     parser::ast,
+//! };
+//!
+//! let program = optimize(lower(&ast("++++++++[>++++++++<-]>+.").unwrap()));
+//! let mut output = vec![];
+//!
+//! InterpreterBuilder::new()
+//!     .program(program)
+//!     .input(&b""[..])
+//!     .output(&mut output)
+//!     .run(CellWidth::Bits8)
+//!     .unwrap();
+//!
+//! assert_eq!(output, b"A");
+//! ```
+//!
+//! The command line interface lives behind the `cli` feature, which is on by
+//! default. Disable default features to use the library without `clap`.
+
+pub mod cell;
+pub mod compiler;
+pub mod coverage;
+pub mod debugger;
+pub mod interpreter;
+pub mod ir;
+pub mod parser;
+pub mod profiler;
+pub mod span;
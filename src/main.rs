@@ -1,18 +1,24 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::PathBuf,
+};
 
 use cli::{Cli, Command};
-use compiler::{
-    arch::{self, Target},
-    compile,
+use nauc::{
+    cell::{Cell, CellWidth},
+    compiler::{codegen, compile, Options},
+    debugger::Debugger,
+    interpreter::{Flush, Interpreter, InterpreterBuilder, InterpreterError},
+    ir::lower,
+    parser::{parse, Severity, Token},
+    profiler::Profiler,
+    span::Spanned,
 };
-use interpreter::InterpreterBuilder;
-use parser::ast;
 
 mod cli;
-mod compiler;
-mod interpreter;
-mod parser;
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -20,30 +26,91 @@ fn main() -> Result<()> {
     match cli.command {
         Some(Command::Interpret {
             file,
+            optimization,
+            debug_hash,
             no_wrap,
             memory,
+            tape,
+            cell_bits,
+            flush,
+            signed,
+            eof,
+            profile,
+            flamegraph,
+            coverage,
         }) => {
+            let name = file.display().to_string();
             let code = std::fs::read_to_string(file.as_path())?;
-            let ast = ast(code)?;
+            let tokens = parse_file(&name, &code, debug_hash)?;
+
+            // Profiles and coverage are of tokens, which optimized
+            // instructions no longer are.
+            let instrumented = profile || coverage.is_some();
+            let program = match instrumented {
+                true => lower(&tokens),
+                false => optimization.pass_manager().run(lower(&tokens)),
+            };
 
-            let interpreter = InterpreterBuilder::new()
-                .program(ast)
+            let builder = InterpreterBuilder::new()
+                .program(program)
+                .input(std::io::stdin().lock())
+                .output(std::io::stdout().lock())
                 .wrapping(!no_wrap)
+                .signed(signed)
+                .eof(eof)
+                .flush(flush)
                 .memory(memory)
-                .build();
+                .tape(tape)
+                .coverage(coverage.is_some());
+            let outputs = Outputs {
+                profile,
+                flamegraph,
+                coverage,
+            };
 
-            interpreter.run()?;
+            match (instrumented, cell_bits) {
+                (false, _) => builder.run(cell_bits),
+                (true, CellWidth::Bits8) => {
+                    run_instrumented(builder.build::<u8>(), &name, &code, outputs)
+                }
+                (true, CellWidth::Bits16) => {
+                    run_instrumented(builder.build::<u16>(), &name, &code, outputs)
+                }
+                (true, CellWidth::Bits32) => {
+                    run_instrumented(builder.build::<u32>(), &name, &code, outputs)
+                }
+                (true, CellWidth::Bits64) => {
+                    run_instrumented(builder.build::<u64>(), &name, &code, outputs)
+                }
+            }
+            .unwrap_or_else(|err| {
+                match err.span() {
+                    Some(span) => eprintln!("Error: {err}\n{}", span.snippet(&name, &code)),
+                    None => eprintln!("Error: {err}"),
+                }
+                std::process::exit(err.status());
+            });
         }
         Some(Command::Compile {
             file,
+            optimization,
+            debug_hash,
             memory,
+            tape,
+            cell_bits,
+            signed,
+            eof,
+            no_wrap,
+            bounds_check,
             output,
             keep_artifacts,
             debug,
             target,
         }) => {
+            let name = file.display().to_string();
             let code = std::fs::read_to_string(file.as_path())?;
-            let ast = ast(code)?;
+            let tokens = parse_file(&name, &code, debug_hash)?;
+            let program = optimization.pass_manager().run(lower(&tokens));
 
             let output = output.unwrap_or(
                 file.file_name()
@@ -51,15 +118,131 @@ fn main() -> Result<()> {
                     .into(),
             );
 
-            let asm = match target {
-                Target::Aarch32Linux => arch::aarch32_linux::codegen,
-                Target::X86_64Linux => arch::x86_64_linux::codegen,
-            }(&ast, memory);
+            let options = Options {
+                memory,
+                tape,
+                cell: cell_bits,
+                signed,
+                eof,
+                wrapping: !no_wrap,
+                bounds_check,
+            };
+
+            let asm = codegen(&program, target, &options);
 
             compile(&asm, &output, debug || keep_artifacts, debug, target)?;
         }
+        Some(Command::Debug {
+            file,
+            input,
+            debug_hash,
+            no_wrap,
+            memory,
+            cell_bits,
+            signed,
+            eof,
+        }) => {
+            let name = file.display().to_string();
+            let code = std::fs::read_to_string(file.as_path())?;
+            let tokens = parse_file(&name, &code, debug_hash)?;
+
+            // Stdin holds debugger commands, so the program reads from a file.
+            let input: Box<dyn Read> = match input {
+                Some(input) => Box::new(File::open(input)?),
+                None => Box::new(io::empty()),
+            };
+
+            let builder = InterpreterBuilder::new()
+                .program(lower(&tokens))
+                .input(input)
+                .output(io::stdout())
+                .wrapping(!no_wrap)
+                .signed(signed)
+                .eof(eof)
+                .flush(Flush::Always)
+                .memory(memory);
+
+            match cell_bits {
+                CellWidth::Bits8 => debug(builder.build::<u8>(), &name, &code)?,
+                CellWidth::Bits16 => debug(builder.build::<u16>(), &name, &code)?,
+                CellWidth::Bits32 => debug(builder.build::<u32>(), &name, &code)?,
+                CellWidth::Bits64 => debug(builder.build::<u64>(), &name, &code)?,
+            }
+        }
         None => {}
     }
 
     Ok(())
 }
+
+/// Runs the debugger's prompt on stdin and stdout.
+fn debug<C: Cell, R: Read, W: Write>(
+    interpreter: Interpreter<C, R, W>,
+    name: &str,
+    code: &str,
+) -> Result<()> {
+    Debugger::new(interpreter, name, code).repl(io::stdin().lock(), io::stdout())
+}
+
+/// What an instrumented run of the interpreter reports.
+struct Outputs {
+    profile: bool,
+    flamegraph: Option<PathBuf>,
+    coverage: Option<PathBuf>,
+}
+
+/// Runs the interpreter to the end, under the profiler if asked to, and
+/// writes the reports in `outputs` even if the program fails.
+fn run_instrumented<C: Cell, R: Read, W: Write>(
+    mut interpreter: Interpreter<C, R, W>,
+    name: &str,
+    code: &str,
+    outputs: Outputs,
+) -> Result<(), InterpreterError> {
+    let result = if outputs.profile {
+        let mut profiler = Profiler::new(interpreter, name, code);
+        let result = profiler.run();
+
+        eprint!("\n{}", profiler.report());
+        if let Some(path) = outputs.flamegraph {
+            fs::write(path, profiler.collapsed())?;
+        }
+
+        interpreter = profiler.into_interpreter();
+        result
+    } else {
+        interpreter.finish()
+    };
+
+    if let (Some(path), Some(coverage)) = (outputs.coverage, interpreter.coverage()) {
+        fs::write(path, coverage.lcov(interpreter.program(), name, code))?;
+    }
+
+    result
+}
+
+/// Parses `code`, printing every diagnostic to stderr. Fails if any of them
+/// is an error. `#` is a debug dump if `debug_hash` is set.
+fn parse_file(name: &str, code: &str, debug_hash: bool) -> Result<Vec<Spanned<Token>>> {
+    let parsed = parse(code, debug_hash);
+
+    for diagnostic in &parsed.diagnostics {
+        eprintln!("{}\n", diagnostic.render(name, code));
+    }
+
+    match parsed.tokens {
+        Some(tokens) => Ok(tokens),
+        None => {
+            let errors = parsed
+                .diagnostics
+                .iter()
+                .filter(|d| d.severity() == Severity::Error)
+                .count();
+
+            bail!(
+                "Could not parse {name} due to {errors} {}",
+                if errors == 1 { "error" } else { "errors" }
+            )
+        }
+    }
+}
@@ -1,126 +1,312 @@
 use itertools::Itertools;
+use std::{collections::HashMap, fmt};
 use thiserror::Error;
 
+use crate::span::{self, Span, Spanned};
+
+/// A brainfuck instruction. Counts are for runs of the same character, and
+/// loop tokens hold the index of their matching bracket.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Token {
     Right(usize),
     Left(usize),
-    Add(u8),
-    Sub(u8),
+    Add(usize),
+    Sub(usize),
     Read,
     Write,
     Loop(usize),
     Break(usize),
+    /// `#`, which dumps the cells around the pointer. Only recognized when
+    /// asked for, since plenty of programs use `#` in their comments.
+    Debug,
     Comment,
 }
 
+/// How serious a diagnostic is. Only errors stop a program from running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
-pub enum ParserError {
-    #[error("Unmatched '{0}'")]
-    UnmatchedBracket(char),
+pub enum Diagnostic {
+    /// `scan_end` is where the search for a `[`'s partner gave up: the last
+    /// token of the program.
+    #[error("Unmatched '{bracket}' at {span}")]
+    UnmatchedBracket {
+        bracket: char,
+        span: Span,
+        scan_end: Option<Span>,
+    },
+
+    /// A loop whose cell is always zero when it is reached.
+    #[error("Loop at {span} can never execute")]
+    DeadLoop { span: Span },
+
+    /// Code after a loop that is always entered and never left.
+    #[error("Unreachable code after infinite loop at {infinite}")]
+    UnreachableCode { span: Span, infinite: Span },
 }
 
-pub fn ast<T: AsRef<str>>(source: T) -> Result<Vec<Token>, ParserError> {
-    let chars: Vec<char> = source
-        .as_ref()
-        .chars()
-        .filter(|&ch| "<>+-.,[]".contains(|c| c == ch))
-        .collect();
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::UnmatchedBracket { .. } => Severity::Error,
+            Diagnostic::DeadLoop { .. } | Diagnostic::UnreachableCode { .. } => Severity::Warning,
+        }
+    }
 
-    let uncompressed_tokens: Vec<Token> = chars
-        .iter()
-        .map(|ch| match ch {
-            '>' => Token::Right(1),
-            '<' => Token::Left(1),
-            '+' => Token::Add(1),
-            '-' => Token::Sub(1),
-            '.' => Token::Write,
-            ',' => Token::Read,
-            '[' => Token::Break(0),
-            ']' => Token::Loop(0),
-            _ => Token::Comment,
+    /// The part of the source the diagnostic is about.
+    pub fn span(&self) -> Span {
+        match self {
+            Diagnostic::UnmatchedBracket { span, .. }
+            | Diagnostic::DeadLoop { span }
+            | Diagnostic::UnreachableCode { span, .. } => *span,
+        }
+    }
+
+    /// Renders the diagnostic with the offending source lines, for a file
+    /// called `name` whose contents are `source`.
+    pub fn render(&self, name: &str, source: &str) -> String {
+        let mut rendered = format!(
+            "{}: {self}\n{}",
+            self.severity(),
+            self.span().snippet(name, source)
+        );
+
+        let note = match self {
+            Diagnostic::UnmatchedBracket {
+                scan_end: Some(scan_end),
+                ..
+            } => Some((
+                "reached the end of the program looking for the matching ']'",
+                scan_end,
+            )),
+            Diagnostic::UnreachableCode { infinite, .. } => {
+                Some(("this loop never ends once entered", infinite))
+            }
+            _ => None,
+        };
+
+        if let Some((note, span)) = note {
+            rendered.push_str(&format!("\nnote: {note}\n{}", span.snippet(name, source)));
+        }
+
+        rendered
+    }
+}
+
+/// The outcome of parsing.
+#[derive(Debug)]
+pub struct Parsed {
+    /// The tokens, unless the source had errors.
+    pub tokens: Option<Vec<Spanned<Token>>>,
+    /// Every error and warning found, in source order.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses brainfuck source, keeping only the tokens. Warnings are dropped,
+/// and so the error list holds every error.
+pub fn ast<T: AsRef<str>>(source: T) -> Result<Vec<Spanned<Token>>, Vec<Diagnostic>> {
+    let parsed = parse(source, false);
+    parsed.tokens.ok_or(parsed.diagnostics)
+}
+
+/// Parses brainfuck source. Every character other than the eight
+/// instructions is a comment, as is `#` unless `debug_hash` is set.
+///
+/// A token made from a run of characters spans the whole run, including any
+/// comments inside it. Parsing carries on past unmatched brackets so that
+/// all of them are reported. Warnings are only looked for once the brackets
+/// are balanced.
+pub fn parse<T: AsRef<str>>(source: T, debug_hash: bool) -> Parsed {
+    let uncompressed_tokens: Vec<Spanned<Token>> = span::chars(source.as_ref())
+        .filter_map(|(ch, span)| {
+            let token = match ch {
+                '>' => Token::Right(1),
+                '<' => Token::Left(1),
+                '+' => Token::Add(1),
+                '-' => Token::Sub(1),
+                '.' => Token::Write,
+                ',' => Token::Read,
+                '[' => Token::Break(0),
+                ']' => Token::Loop(0),
+                '#' if debug_hash => Token::Debug,
+                _ => return None,
+            };
+
+            Some(Spanned::new(token, span))
         })
         .collect();
 
-    let compressed_tokens: Vec<Token> = uncompressed_tokens
+    let compressed_tokens: Vec<Spanned<Token>> = uncompressed_tokens
         .iter()
-        .chunk_by(|&&t| t)
+        .chunk_by(|t| t.node)
         .into_iter()
         .flat_map(|(key, group)| {
             let original_tokens = group.cloned().collect_vec();
             let count = original_tokens.len();
+            let span = original_tokens[0].span.to(original_tokens[count - 1].span);
 
             match key {
-                Token::Right(_) => vec![Token::Right(count)],
-                Token::Left(_) => vec![Token::Left(count)],
-                Token::Add(_) => vec![Token::Add((count % 256) as u8)],
-                Token::Sub(_) => vec![Token::Sub((count % 256) as u8)],
+                Token::Right(_) => vec![Spanned::new(Token::Right(count), span)],
+                Token::Left(_) => vec![Spanned::new(Token::Left(count), span)],
+                Token::Add(_) => vec![Spanned::new(Token::Add(count), span)],
+                Token::Sub(_) => vec![Spanned::new(Token::Sub(count), span)],
                 _ => original_tokens,
             }
         })
         .collect();
 
-    compressed_tokens
-        .iter()
-        .enumerate()
-        .map(|(i, &token)| {
-            match token {
-                /* Explanation for the following two operations:
-                - To find the matching ] for a [, we keep track of a number that
-                     - is incremented when we hit [
-                     - is decremented when we hit ]
-                     - is unmodified otherwise
-                - Once this value reaches 0, we have hit the matching bracket.
-                - Its index is the index of the first 0 in the map. */
-                Token::Break(_) => {
-                    let mut count = 1;
-                    let jump = compressed_tokens[i + 1..]
-                        .iter()
-                        .map(move |t| {
-                            count += match t {
-                                Token::Break(_) => 1,
-                                Token::Loop(_) => -1,
-                                _ => 0,
-                            };
-
-                            count
-                        })
-                        .position(|count| count == 0);
-
-                    if let Some(jump) = jump {
-                        Ok(Token::Break(i + 1 + jump))
-                    } else {
-                        Err(ParserError::UnmatchedBracket('['))
-                    }
+    // Match brackets in a single pass: every `]` closes the innermost `[`
+    // that is still open.
+    let mut tokens = compressed_tokens;
+    let mut open = vec![];
+    let mut errors = vec![];
+
+    for i in 0..tokens.len() {
+        match tokens[i].node {
+            Token::Break(_) => open.push(i),
+            Token::Loop(_) => match open.pop() {
+                Some(start) => {
+                    tokens[start].node = Token::Break(i);
+                    tokens[i].node = Token::Loop(start);
                 }
-                Token::Loop(_) => {
-                    let mut count = -1;
-                    let jump = compressed_tokens[..i]
-                        .iter()
-                        .rev()
-                        .map(move |t| {
-                            count += match t {
-                                Token::Break(_) => 1,
-                                Token::Loop(_) => -1,
-                                _ => 0,
-                            };
-
-                            count
-                        })
-                        .position(|count| count == 0);
-
-                    if let Some(jump) = jump {
-                        Ok(Token::Loop(i - 1 - jump))
-                    } else {
-                        Err(ParserError::UnmatchedBracket(']'))
-                    }
+                None => errors.push(Diagnostic::UnmatchedBracket {
+                    bracket: ']',
+                    span: tokens[i].span,
+                    scan_end: None,
+                }),
+            },
+            _ => (),
+        }
+    }
+
+    let scan_end = tokens.last().map(|t| t.span);
+    errors.extend(open.into_iter().map(|i| Diagnostic::UnmatchedBracket {
+        bracket: '[',
+        span: tokens[i].span,
+        scan_end,
+    }));
+
+    if errors.is_empty() {
+        Parsed {
+            diagnostics: lint(&tokens),
+            tokens: Some(tokens),
+        }
+    } else {
+        errors.sort_by_key(|error| error.span().start);
+        Parsed {
+            tokens: None,
+            diagnostics: errors,
+        }
+    }
+}
+
+/// Looks for loops that can never run and for code that can never be reached.
+///
+/// This follows the program from its start, knowing every cell is zero, and
+/// forgets what it knew about the tape whenever a loop might run. Cell widths
+/// are not known here, so a cell is only taken to be non-zero if it would be
+/// at every width. A loop at the very start of the program is the usual way
+/// to write a header comment, so it is never reported.
+fn lint(tokens: &[Spanned<Token>]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    // Known cell values by offset from the start. `None` is unknown, and
+    // missing cells are zero while `exact` holds.
+    let mut cells: HashMap<isize, Option<i128>> = HashMap::new();
+    let mut exact = true;
+    let mut pointer: isize = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let value = cells
+            .get(&pointer)
+            .copied()
+            .unwrap_or(if exact { Some(0) } else { None });
+
+        match tokens[i].node {
+            Token::Right(count) => pointer += count as isize,
+            Token::Left(count) => pointer -= count as isize,
+            Token::Add(count) => {
+                cells.insert(pointer, value.map(|v| v + count as i128));
+            }
+            Token::Sub(count) => {
+                cells.insert(pointer, value.map(|v| v - count as i128));
+            }
+            Token::Read => {
+                cells.insert(pointer, None);
+            }
+            Token::Break(end) if value == Some(0) => {
+                if i != 0 {
+                    diagnostics.push(Diagnostic::DeadLoop {
+                        span: tokens[i].span.to(tokens[end].span),
+                    });
                 }
-                _ => Ok(token),
+
+                i = end + 1;
+                continue;
             }
-        })
-        .collect::<Result<Vec<Token>, ParserError>>()
+            Token::Break(end)
+                if value.is_some_and(|v| v % 256 != 0) && stuck(&tokens[i + 1..end]) =>
+            {
+                if let Some(last) = tokens.get(end + 1..).and_then(|rest| rest.last()) {
+                    diagnostics.push(Diagnostic::UnreachableCode {
+                        span: tokens[end + 1].span.to(last.span),
+                        infinite: tokens[i].span.to(tokens[end].span),
+                    });
+                }
+
+                break;
+            }
+            Token::Break(_) => {
+                cells.clear();
+                exact = false;
+            }
+            Token::Loop(_) => {
+                cells = HashMap::from([(pointer, Some(0))]);
+                exact = false;
+            }
+            Token::Write | Token::Debug | Token::Comment => (),
+        }
+
+        i += 1;
+    }
+
+    diagnostics
 }
 
+/// Whether a loop `body` leaves the loop's cell, and the pointer, untouched.
+/// Such a loop never ends once it is entered.
+fn stuck(body: &[Spanned<Token>]) -> bool {
+    let mut offset: isize = 0;
+
+    body.iter().all(|token| match token.node {
+        Token::Right(count) => {
+            offset += count as isize;
+            true
+        }
+        Token::Left(count) => {
+            offset -= count as isize;
+            true
+        }
+        Token::Add(_) | Token::Sub(_) | Token::Read => offset != 0,
+        Token::Write | Token::Debug | Token::Comment => true,
+        Token::Break(_) | Token::Loop(_) => false,
+    }) && offset == 0
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use rstest::*;
@@ -138,9 +324,86 @@ mod tests {
     #[case("[[.-]]", Ok(vec![T::Break(5), T::Break(4), T::Write, T::Sub(1), T::Loop(1), T::Loop(0)]))]
     #[case("><+-.,[]", Ok(vec![T::Right(1), T::Left(1), T::Add(1), T::Sub(1), T::Write, T::Read, T::Break(7), T::Loop(6)]))]
     #[case("++++[>+.<-]", Ok(vec![T::Add(4), T::Break(7), T::Right(1), T::Add(1), T::Write, T::Left(1), T::Sub(1), T::Loop(1)]))]
-    #[case("[[+++>++]", Err(ParserError::UnmatchedBracket('[')))]
-    #[case("[+>++]]]", Err(ParserError::UnmatchedBracket(']')))]
-    fn test_parser(#[case] source: &str, #[case] expected: Result<Vec<T>, ParserError>) {
-        assert_eq!(ast(source), expected);
+    #[case("[[+++>++]", Err(vec![Diagnostic::UnmatchedBracket {
+        bracket: '[',
+        span: Span { start: 0, end: 1, line: 1, column: 1 },
+        scan_end: Some(Span { start: 8, end: 9, line: 1, column: 9 }),
+    }]))]
+    #[case("[+>++]\n]]", Err(vec![
+        Diagnostic::UnmatchedBracket {
+            bracket: ']',
+            span: Span { start: 7, end: 8, line: 2, column: 1 },
+            scan_end: None,
+        },
+        Diagnostic::UnmatchedBracket {
+            bracket: ']',
+            span: Span { start: 8, end: 9, line: 2, column: 2 },
+            scan_end: None,
+        },
+    ]))]
+    #[case("]+[", Err(vec![
+        Diagnostic::UnmatchedBracket {
+            bracket: ']',
+            span: Span { start: 0, end: 1, line: 1, column: 1 },
+            scan_end: None,
+        },
+        Diagnostic::UnmatchedBracket {
+            bracket: '[',
+            span: Span { start: 2, end: 3, line: 1, column: 3 },
+            scan_end: Some(Span { start: 2, end: 3, line: 1, column: 3 }),
+        },
+    ]))]
+    fn test_parser(#[case] source: &str, #[case] expected: Result<Vec<T>, Vec<Diagnostic>>) {
+        let tokens = ast(source).map(|tokens| tokens.into_iter().map(|t| t.node).collect());
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[rstest]
+    #[case("+#+##", false, vec![T::Add(2)])]
+    #[case("+#+##", true, vec![T::Add(1), T::Debug, T::Add(1), T::Debug, T::Debug])]
+    fn test_debug_hash(#[case] source: &str, #[case] debug_hash: bool, #[case] expected: Vec<T>) {
+        let tokens = parse(source, debug_hash).tokens.unwrap();
+
+        assert_eq!(
+            tokens.into_iter().map(|t| t.node).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("+", vec![(0, 1, 1, 1)])]
+    #[case("é\n+ +[\n]", vec![(3, 6, 2, 1), (6, 7, 2, 4), (8, 9, 3, 1)])]
+    fn test_spans(#[case] source: &str, #[case] expected: Vec<(usize, usize, usize, usize)>) {
+        let spans: Vec<_> = ast(source)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.span.start, t.span.end, t.span.line, t.span.column))
+            .collect();
+
+        assert_eq!(spans, expected);
+    }
+
+    #[rstest]
+    #[case("[comment]+[-][.]", vec!["Loop at 1:14 can never execute"])]
+    #[case(">[-]<[-]", vec!["Loop at 1:2 can never execute", "Loop at 1:6 can never execute"])]
+    #[case(",[-][.]", vec!["Loop at 1:5 can never execute"])]
+    #[case("+[]>.", vec!["Unreachable code after infinite loop at 1:2"])]
+    #[case("+[>+<.]\n>.", vec!["Unreachable code after infinite loop at 1:2"])]
+    #[case("+[]", vec![])]
+    #[case(",[].", vec![])]
+    #[case("+[-]", vec![])]
+    #[case("++++++++[>++++++++<-]>+.", vec![])]
+    fn test_warnings(#[case] source: &str, #[case] expected: Vec<&str>) {
+        let parsed = parse(source, false);
+        let warnings: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .inspect(|d| assert_eq!(d.severity(), Severity::Warning))
+            .map(|d| d.to_string())
+            .collect();
+
+        assert!(parsed.tokens.is_some());
+        assert_eq!(warnings, expected);
     }
 }
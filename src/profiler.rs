@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::{
+    cell::Cell,
+    interpreter::{Interpreter, InterpreterError},
+    ir::Instruction,
+    span::Span,
+};
+
+/// How many entries each table of the report lists.
+pub const TOP: usize = 10;
+
+/// How many instructions in a row make up a sequence.
+pub const SEQUENCE: usize = 3;
+
+/// Loops in the report are cut to this many characters.
+const WIDTH: usize = 40;
+
+/// A loop, or the whole program at the root, with the instructions run in it
+/// and not in a nested loop.
+#[derive(Debug)]
+struct Frame {
+    parent: usize,
+    /// Index of the `LoopStart`, or `None` at the root.
+    start: Option<usize>,
+    children: HashMap<usize, usize>,
+    samples: u64,
+}
+
+/// Runs an [`Interpreter`] to the end, counting how often each instruction
+/// runs, how often each loop is entered and which instructions run one after
+/// the other.
+///
+/// The program should not be optimized, so that each instruction is exactly
+/// one token of `source`.
+pub struct Profiler<'a, C: Cell, R: Read, W: Write> {
+    interpreter: Interpreter<C, R, W>,
+    name: &'a str,
+    source: &'a str,
+    /// Times each instruction ran.
+    counts: Vec<u64>,
+    /// Times each loop was entered, by the index of its `LoopStart`.
+    entries: Vec<u64>,
+    sequences: HashMap<[usize; SEQUENCE], u64>,
+    /// The latest instructions to run, oldest first.
+    window: [usize; SEQUENCE],
+    /// The stack of loops as a tree, the root first.
+    frames: Vec<Frame>,
+    frame: usize,
+}
+
+impl<'a, C: Cell, R: Read, W: Write> Profiler<'a, C, R, W> {
+    /// Profiles `interpreter`, running a program parsed from the file called
+    /// `name` whose contents are `source`.
+    pub fn new(interpreter: Interpreter<C, R, W>, name: &'a str, source: &'a str) -> Self {
+        let length = interpreter.program().len();
+
+        Self {
+            interpreter,
+            name,
+            source,
+            counts: vec![0; length],
+            entries: vec![0; length],
+            sequences: HashMap::new(),
+            window: [usize::MAX; SEQUENCE],
+            frames: vec![Frame {
+                parent: 0,
+                start: None,
+                children: HashMap::new(),
+                samples: 0,
+            }],
+            frame: 0,
+        }
+    }
+
+    /// Runs the program until it finishes or fails. The counts cover every
+    /// instruction that ran, including the one that failed.
+    pub fn run(&mut self) -> Result<(), InterpreterError> {
+        let result = self.execute();
+        self.interpreter.flush()?;
+        result
+    }
+
+    fn execute(&mut self) -> Result<(), InterpreterError> {
+        while !self.interpreter.finished() {
+            let pc = self.interpreter.pc();
+
+            self.counts[pc] += 1;
+            self.frames[self.frame].samples += 1;
+            self.window.rotate_left(1);
+            self.window[SEQUENCE - 1] = pc;
+            if self.window[0] != usize::MAX {
+                *self.sequences.entry(self.window).or_default() += 1;
+            }
+
+            self.interpreter.step()?;
+
+            // Falling through a bracket enters or leaves its loop.
+            let fell_through = self.interpreter.pc() == pc + 1;
+            match self.interpreter.program()[pc].node {
+                Instruction::LoopStart(_) if fell_through => self.enter(pc),
+                Instruction::LoopEnd(_) if fell_through => {
+                    self.frame = self.frames[self.frame].parent;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes the loop starting at `start` onto the stack.
+    fn enter(&mut self, start: usize) {
+        self.entries[start] += 1;
+        self.frame = match self.frames[self.frame].children.get(&start) {
+            Some(&child) => child,
+            None => {
+                let child = self.frames.len();
+                self.frames.push(Frame {
+                    parent: self.frame,
+                    start: Some(start),
+                    children: HashMap::new(),
+                    samples: 0,
+                });
+                self.frames[self.frame].children.insert(start, child);
+                child
+            }
+        };
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<C, R, W> {
+        &self.interpreter
+    }
+
+    pub fn into_interpreter(self) -> Interpreter<C, R, W> {
+        self.interpreter
+    }
+
+    /// Times each instruction ran.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Every loop that ran, as the indices of its brackets, with the times it
+    /// was entered and the iterations it took in total.
+    pub fn loops(&self) -> Vec<(usize, usize, u64, u64)> {
+        self.interpreter
+            .program()
+            .iter()
+            .enumerate()
+            .filter_map(|(start, instruction)| match instruction.node {
+                Instruction::LoopStart(end) if self.entries[start] > 0 => {
+                    Some((start, end, self.entries[start], self.counts[end]))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The source of the instructions from `start` to `end`, without
+    /// comments.
+    fn text(&self, start: usize, end: usize) -> String {
+        let program = self.interpreter.program();
+        let span = program[start].span.to(program[end].span);
+
+        self.source[span.start..span.end]
+            .chars()
+            .filter(|ch| "+-<>,.[]#".contains(*ch))
+            .collect()
+    }
+
+    fn span(&self, pc: usize) -> Span {
+        self.interpreter.program()[pc].span
+    }
+
+    /// A summary of the run: the hottest loops, the instructions that ran
+    /// most often and the most common sequences of instructions.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "Profile of {}: {} instructions ran\n",
+            self.name,
+            self.counts.iter().sum::<u64>()
+        );
+
+        let mut loops = self.loops();
+        loops.sort_by_key(|&(start, _, _, iterations)| (std::cmp::Reverse(iterations), start));
+        report.push_str(&format!(
+            "\nHottest loops:\n{:>12} {:>10} {:>10}  {:<10} loop\n",
+            "iterations", "entries", "average", "at"
+        ));
+        for &(start, end, entries, iterations) in loops.iter().take(TOP) {
+            let mut text = self.text(start, end);
+            if text.chars().count() > WIDTH {
+                text = text.chars().take(WIDTH - 3).collect::<String>() + "...";
+            }
+
+            report.push_str(&format!(
+                "{iterations:>12} {entries:>10} {:>10.1}  {:<10} {text}\n",
+                iterations as f64 / entries as f64,
+                self.span(start).to_string(),
+            ));
+        }
+
+        let mut instructions: Vec<_> = (0..self.counts.len())
+            .filter(|&pc| self.counts[pc] > 0)
+            .collect();
+        instructions.sort_by_key(|&pc| (std::cmp::Reverse(self.counts[pc]), pc));
+        report.push_str(&format!(
+            "\nHottest instructions:\n{:>12}  {:<10} instruction\n",
+            "count", "at"
+        ));
+        for &pc in instructions.iter().take(TOP) {
+            report.push_str(&format!(
+                "{:>12}  {:<10} {}\n",
+                self.counts[pc],
+                self.span(pc).to_string(),
+                self.text(pc, pc)
+            ));
+        }
+
+        // The same text can run from several places in the program.
+        let mut sequences: HashMap<String, u64> = HashMap::new();
+        for (window, &count) in &self.sequences {
+            let text: Vec<_> = window.iter().map(|&pc| self.text(pc, pc)).collect();
+            *sequences.entry(text.join(" ")).or_default() += count;
+        }
+        let mut sequences: Vec<_> = sequences.into_iter().collect();
+        sequences.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        report.push_str(&format!(
+            "\nMost common sequences:\n{:>12}  sequence\n",
+            "count"
+        ));
+        for (text, count) in sequences.iter().take(TOP) {
+            report.push_str(&format!("{count:>12}  {text}\n"));
+        }
+
+        report
+    }
+
+    /// The instructions that ran in each stack of loops, in the collapsed
+    /// stack format of flamegraph tools. The program is the bottom frame and
+    /// each loop a frame named after where it starts.
+    pub fn collapsed(&self) -> String {
+        let mut lines: Vec<String> = (0..self.frames.len())
+            .filter(|&frame| self.frames[frame].samples > 0)
+            .map(|frame| {
+                let mut stack = vec![];
+                let mut current = frame;
+                while let Some(start) = self.frames[current].start {
+                    stack.push(format!("loop {}", self.span(start)));
+                    current = self.frames[current].parent;
+                }
+                stack.push(self.name.to_string());
+                stack.reverse();
+
+                format!("{} {}", stack.join(";"), self.frames[frame].samples)
+            })
+            .collect();
+        lines.sort();
+
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::InterpreterBuilder, ir::lower, parser::ast};
+
+    fn profile(source: &str) -> Profiler<'_, u8, &'static [u8], Vec<u8>> {
+        let interpreter = InterpreterBuilder::new()
+            .program(lower(&ast(source).unwrap()))
+            .input(&b""[..])
+            .output(vec![])
+            .build::<u8>();
+        let mut profiler = Profiler::new(interpreter, "a.bf", source);
+
+        profiler.run().unwrap();
+        profiler
+    }
+
+    #[test]
+    fn test_counts() {
+        let profiler = profile("+++[>++[-]<-]");
+
+        assert_eq!(profiler.counts(), [1, 1, 3, 3, 3, 6, 6, 3, 3, 3]);
+        assert_eq!(profiler.loops(), [(1, 9, 1, 3), (4, 6, 3, 6)]);
+    }
+
+    #[test]
+    fn test_collapsed() {
+        let profiler = profile("++[>+\n[-]<-]");
+
+        assert_eq!(
+            profiler.collapsed(),
+            "a.bf 2\na.bf;loop 1:3 12\na.bf;loop 1:3;loop 2:1 4\n"
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let profiler = profile("+++[>++[-]<-]");
+        let report = profiler.report();
+
+        assert!(report.starts_with("Profile of a.bf: 32 instructions ran\n"));
+        assert!(report.contains("\n           6          3        2.0  1:8        [-]\n"));
+        assert!(report.contains("\n           3  [ - ]\n"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+/// A region of the source file.
+///
+/// `start` and `end` are byte offsets, `line` and `column` are 1-based and
+/// point at the first character. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        Span {
+            end: first.end.max(last.end),
+            ..first
+        }
+    }
+
+    /// Renders the span's first line of `source` with a caret under the
+    /// span, in the style of
+    ///
+    /// ```text
+    ///  --> hello.bf:2:3
+    ///   |
+    /// 2 | ++[>+<-
+    ///   |   ^
+    /// ```
+    pub fn snippet(&self, name: &str, source: &str) -> String {
+        let line_start = source[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.start..]
+            .find('\n')
+            .map_or(source.len(), |i| self.start + i);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+
+        let indent = source[line_start..self.start].chars().count();
+        let width = source[self.start..self.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        format!(
+            "{gutter}--> {name}:{self}\n\
+             {gutter} |\n\
+             {number} | {text}\n\
+             {gutter} | {}{}",
+            " ".repeat(indent),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A value together with the part of the source it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
+
+/// Every character of `source` with its span.
+pub fn chars(source: &str) -> impl Iterator<Item = (char, Span)> + '_ {
+    let mut line = 1;
+    let mut column = 1;
+
+    source.char_indices().map(move |(start, ch)| {
+        let span = Span {
+            start,
+            end: start + ch.len_utf8(),
+            line,
+            column,
+        };
+
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+
+        (ch, span)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("+", 0, "--> a.bf:1:1\n  |\n1 | +\n  | ^")]
+    #[case("ab\nc+d", 4, "--> a.bf:2:2\n  |\n2 | c+d\n  |  ^")]
+    #[case("é\r\n+++", 4, "--> a.bf:2:1\n  |\n2 | +++\n  | ^")]
+    fn test_snippet(#[case] source: &str, #[case] start: usize, #[case] expected: &str) {
+        let (_, span) = chars(source).find(|(_, span)| span.start == start).unwrap();
+
+        assert_eq!(span.snippet("a.bf", source), format!(" {expected}"));
+    }
+
+    #[test]
+    fn test_to() {
+        let spans: Vec<_> = chars("+\n-").map(|(_, span)| span).collect();
+
+        assert_eq!(
+            spans[2].to(spans[0]),
+            Span {
+                start: 0,
+                end: 3,
+                line: 1,
+                column: 1
+            }
+        );
+    }
+}